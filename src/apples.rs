@@ -0,0 +1,431 @@
+//! The apples snakes eat to grow and score, where they are placed and what happens when
+//! one is eaten.
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use snake::{
+    config::{SnakeConfig, growth_in_parts},
+    fabrik::{GameLayer, HeadOfSnake, Joint, Limb, LimbSegment, PartOf, SnakeParts, SnakeStyle},
+    grid::GridTrail,
+    level::Level,
+    mode::Walls,
+    placement::ApplePlacement,
+    powerup::ActivePowerUps,
+};
+
+use crate::{
+    AnimationTimer, Mouth, SimulationRng, SpeedFactor,
+    pickups::PowerUpPickup,
+    players::{Player, Score, ScoreboardUi},
+    snake_in_play,
+};
+
+pub struct ApplesPlugin;
+
+impl Plugin for ApplesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<AppleEaten>().add_systems(
+            Update,
+            detect_start_collision_with_apple_field.run_if(snake_in_play),
+        );
+    }
+}
+
+/// Index of the apple's type in `SnakeConfig::apple_types`.
+#[derive(Component)]
+pub struct Apple(usize);
+
+/// An apple was eaten this tick.
+#[derive(Message)]
+pub struct AppleEaten {
+    /// Index of its type in `SnakeConfig::apple_types`.
+    kind: usize,
+    /// The snake that ate it.
+    snake: Entity,
+}
+
+/// An apple a dead rival dropped. It is gone once eaten rather than moving elsewhere.
+#[derive(Component)]
+pub struct DroppedApple;
+
+#[derive(Component)]
+struct AppleField;
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct CrunchSound(pub Handle<AudioSource>);
+
+/// Every snake's limb, rivals' included, and the grid the players move on in grid mode.
+pub fn snake_limbs<'a>(
+    snakes: &'a Query<(&Limb, Option<&GridTrail>)>,
+) -> (Vec<&'a Limb>, Option<&'a GridTrail>) {
+    let limbs = snakes.iter().map(|(limb, _)| limb).collect();
+    (limbs, snakes.iter().find_map(|(_, grid_trail)| grid_trail))
+}
+
+/// Finds a spot for an apple that is clear of the `snakes` and the `other_apples`.
+pub fn place_apple(
+    config: &SnakeConfig,
+    level: &Level,
+    snakes: &[&Limb],
+    other_apples: &[Vec2],
+    grid_trail: Option<&GridTrail>,
+    walls: Walls,
+    rng: &mut SimulationRng,
+) -> Vec2 {
+    let placement = ApplePlacement {
+        wraps: walls == Walls::Wrap,
+        ..ApplePlacement::from_config(config)
+    };
+    match grid_trail {
+        Some(grid_trail) => placement.find_cell(
+            level,
+            snakes,
+            other_apples,
+            grid_trail.cell_size(),
+            &mut **rng,
+        ),
+        None => placement.find_position(level, snakes, other_apples, &mut **rng),
+    }
+}
+
+pub fn apple(
+    asset_server: &AssetServer,
+    config: &SnakeConfig,
+    kind: usize,
+    position: Vec2,
+) -> impl Bundle {
+    (
+        Sprite {
+            image: asset_server.load(config.apple_types[kind].sprite.clone()),
+            ..default()
+        },
+        Transform::from_translation(position.extend(-10.0)),
+        RigidBody::Kinematic,
+        Collider::circle(config.apple_radius),
+        CollisionLayers::new(
+            GameLayer::Apple,
+            [
+                GameLayer::Default,
+                GameLayer::SnakeHead,
+                GameLayer::RivalHead,
+            ],
+        ),
+        Sensor,
+        Apple(kind),
+        children![(
+            RigidBody::Kinematic,
+            Collider::circle(config.apple_field_radius),
+            CollisionLayers::new(
+                GameLayer::AppleField,
+                [GameLayer::Default, GameLayer::SnakeHead],
+            ),
+            Sensor,
+            AppleField
+        )],
+    )
+}
+
+/// Moves eaten apples somewhere else as a newly picked type and reports what was eaten and
+/// by whom. Dropped apples are despawned instead.
+pub fn detect_collision_with_apple(
+    mut collision_reader: MessageReader<CollisionEnd>,
+    mut apples: Query<(Entity, &mut Apple, &mut Transform, &mut Sprite)>,
+    pickups: Query<&Transform, (With<PowerUpPickup>, Without<Apple>)>,
+    dropped_apples: Query<(), With<DroppedApple>>,
+    heads: Query<&PartOf, With<HeadOfSnake>>,
+    mut apple_eaten: MessageWriter<AppleEaten>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    snakes: Query<(&Limb, Option<&GridTrail>)>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    walls: Res<Walls>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    let (limbs, grid_trail) = snake_limbs(&snakes);
+    let mut despawned = Vec::new();
+    for event in collision_reader.read() {
+        let colliders = [event.collider1, event.collider2];
+        let Some(eaten) = colliders
+            .into_iter()
+            .find(|entity| apples.contains(*entity) && !despawned.contains(entity))
+        else {
+            continue;
+        };
+        let Some(snake) = colliders
+            .into_iter()
+            .find_map(|entity| heads.get(entity).ok())
+            .map(|part_of| part_of.0)
+        else {
+            continue;
+        };
+        if dropped_apples.contains(eaten) {
+            if let Ok((_, apple, ..)) = apples.get(eaten) {
+                apple_eaten.write(AppleEaten {
+                    kind: apple.0,
+                    snake,
+                });
+            }
+            commands.entity(eaten).despawn();
+            despawned.push(eaten);
+            continue;
+        }
+        let other_apples: Vec<Vec2> = apples
+            .iter()
+            .filter(|(entity, ..)| *entity != eaten)
+            .map(|(_, _, transform, _)| transform)
+            .chain(&pickups)
+            .map(|transform| transform.translation.truncate())
+            .collect();
+        let Ok((_, mut apple, mut transform, mut sprite)) = apples.get_mut(eaten) else {
+            continue;
+        };
+        apple_eaten.write(AppleEaten {
+            kind: apple.0,
+            snake,
+        });
+
+        apple.0 = config.random_apple_type(&mut **rng).unwrap_or(apple.0);
+        sprite.image = asset_server.load(config.apple_types[apple.0].sprite.clone());
+        let apple_position = place_apple(
+            &config,
+            &level,
+            &limbs,
+            &other_apples,
+            grid_trail,
+            *walls,
+            &mut rng,
+        );
+        transform.translation.x = apple_position.x;
+        transform.translation.y = apple_position.y;
+    }
+}
+
+/// Grows or shrinks the snakes that ate apples, and speeds them up, by what the apples
+/// call for. A snake never shrinks below the length it started with.
+///
+/// A snake that ate several apples this tick changes length once by all of them together,
+/// as the parts spawned for one apple are not in the world yet to be renumbered for the next.
+pub fn grow_snake(
+    mut apple_eaten: MessageReader<AppleEaten>,
+    mut snakes: Query<(&mut Limb, &mut SpeedFactor, &SnakeStyle, &SnakeParts)>,
+    mut joints_query: Query<(Entity, &mut Joint)>,
+    mut limb_query: Query<(Entity, &mut LimbSegment)>,
+    mut commands: Commands,
+    config: Res<SnakeConfig>,
+) {
+    // Growth and speed factor per snake, in the order the snakes first ate.
+    let mut eaters: Vec<(Entity, i32, f32)> = Vec::new();
+    for eaten in apple_eaten.read() {
+        let apple_type = &config.apple_types[eaten.kind];
+        match eaters.iter_mut().find(|(snake, ..)| *snake == eaten.snake) {
+            Some((_, growth, speed_factor)) => {
+                *growth += apple_type.growth;
+                *speed_factor *= apple_type.speed_factor;
+            }
+            None => eaters.push((eaten.snake, apple_type.growth, apple_type.speed_factor)),
+        }
+    }
+
+    for (snake, growth, factor) in eaters {
+        let Ok((mut limb, mut speed_factor, style, parts)) = snakes.get_mut(snake) else {
+            continue;
+        };
+        **speed_factor *= factor;
+
+        let (no_of_snake_parts_to_add, no_of_snake_parts_to_remove) =
+            growth_in_parts(growth, limb.segments().len(), config.no_of_parts);
+        limb.add_multiple_snake_parts(
+            snake,
+            no_of_snake_parts_to_add,
+            &mut commands,
+            style,
+            parts,
+            &mut joints_query,
+            &mut limb_query,
+            &config,
+        );
+        limb.remove_snake_parts(
+            no_of_snake_parts_to_remove,
+            &mut commands,
+            parts,
+            &mut joints_query,
+            &mut limb_query,
+        );
+    }
+}
+
+pub fn score_apple(
+    mut apple_eaten: MessageReader<AppleEaten>,
+    mut commands: Commands,
+    crunch_sound: Res<CrunchSound>,
+    scoreboards: Query<(Entity, &ScoreboardUi), With<Text>>,
+    mut writer: TextUiWriter,
+    mut players: Query<(&Player, &ActivePowerUps, &mut Score)>,
+    config: Res<SnakeConfig>,
+) {
+    for eaten in apple_eaten.read() {
+        let Ok((player, power_ups, mut player_score)) = players.get_mut(eaten.snake) else {
+            continue;
+        };
+        commands.spawn((AudioPlayer(crunch_sound.clone()), PlaybackSettings::DESPAWN));
+        let score = config.apple_types[eaten.kind].score * power_ups.score_multiplier(&config);
+        **player_score = player_score.saturating_add_signed(score as isize);
+        if let Some((score_root, _)) = scoreboards
+            .iter()
+            .find(|(_, scoreboard)| scoreboard.0 == player.0)
+        {
+            *writer.text(score_root, 1) = player_score.to_string();
+        }
+    }
+}
+
+/// Replaces the apples on the field with `apple_count` fresh ones.
+pub fn reset_apples(
+    mut commands: Commands,
+    apples: Query<Entity, With<Apple>>,
+    asset_server: Res<AssetServer>,
+    snakes: Query<(&Limb, Option<&GridTrail>)>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    walls: Res<Walls>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    let (limbs, grid_trail) = snake_limbs(&snakes);
+    for entity in apples {
+        commands.entity(entity).despawn();
+    }
+    let mut positions = Vec::with_capacity(config.apple_count);
+    for _ in 0..config.apple_count {
+        let Some(kind) = config.random_apple_type(&mut **rng) else {
+            return;
+        };
+        let position = place_apple(
+            &config, &level, &limbs, &positions, grid_trail, *walls, &mut rng,
+        );
+        positions.push(position);
+        commands.spawn(apple(&asset_server, &config, kind, position));
+    }
+}
+
+fn detect_start_collision_with_apple_field(
+    mut collision_reader: MessageReader<CollisionStart>,
+    heads: Query<&Children, With<HeadOfSnake>>,
+    mut mouths: Query<&mut AnimationTimer, With<Mouth>>,
+    apple_fields: Query<(), With<AppleField>>,
+) {
+    for event in collision_reader.read() {
+        let colliders = [event.collider1, event.collider2];
+        if !colliders
+            .iter()
+            .any(|entity| apple_fields.contains(*entity))
+        {
+            continue;
+        }
+        let Some(children) = colliders
+            .into_iter()
+            .find_map(|entity| heads.get(entity).ok())
+        else {
+            continue;
+        };
+        let mut mouths = mouths.iter_many_mut(children);
+        while let Some(mut mouth) = mouths.fetch_next() {
+            mouth.timer = AnimationTimer::timer_from_fps(mouth.fps)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    /// Indices of the default apple types.
+    const APPLE: usize = 0;
+    const ROTTEN: usize = 2;
+    const SPEED: usize = 3;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(SnakeConfig::default());
+        world.init_resource::<Messages<AppleEaten>>();
+        world
+    }
+
+    /// Spawns a snake the length every snake starts with, and its parts.
+    fn spawn_snake(world: &mut World) -> Entity {
+        world
+            .run_system_once(|mut commands: Commands, config: Res<SnakeConfig>| {
+                let limb = Limb::new(Vec2::ZERO, Vec2::ZERO, &config.segment_lengths());
+                let style = SnakeStyle::player(default(), default(), Color::WHITE);
+                let snake = commands.spawn_empty().id();
+                limb.display(snake, &mut commands, &style, (), &config);
+                commands
+                    .entity(snake)
+                    .insert((limb, SpeedFactor::default(), style));
+                snake
+            })
+            .unwrap()
+    }
+
+    /// Has `snake` eat apples of each of the `kinds` in one tick.
+    fn eat(world: &mut World, snake: Entity, kinds: &[usize]) {
+        for &kind in kinds {
+            world.write_message(AppleEaten { kind, snake });
+        }
+        world.run_system_once(grow_snake).unwrap();
+    }
+
+    /// Checks that the snake's joints and colliding segments are numbered once each along
+    /// its limb, and returns how long the limb is.
+    fn parts_in_step(world: &mut World, snake: Entity) -> usize {
+        let parts = world.get::<Limb>(snake).unwrap().segments().len();
+        let mut joints: Vec<usize> = world
+            .query::<&Joint>()
+            .iter(world)
+            .map(|joint| joint.0)
+            .collect();
+        joints.sort_unstable();
+        let mut limb_segments: Vec<usize> = world
+            .query_filtered::<&LimbSegment, With<Collider>>()
+            .iter(world)
+            .map(|limb_segment| limb_segment.0)
+            .collect();
+        limb_segments.sort_unstable();
+        assert_eq!(joints, (0..parts).collect::<Vec<_>>());
+        assert_eq!(limb_segments, (0..parts - 1).collect::<Vec<_>>());
+        parts
+    }
+
+    #[test]
+    fn eating_two_apples_in_one_tick_grows_by_both() {
+        let mut world = world();
+        let snake = spawn_snake(&mut world);
+        let config = world.resource::<SnakeConfig>().clone();
+
+        eat(&mut world, snake, &[APPLE, SPEED]);
+        let growth = config.apple_types[APPLE].growth + config.apple_types[SPEED].growth;
+        assert_eq!(
+            parts_in_step(&mut world, snake),
+            config.no_of_parts + growth as usize
+        );
+        assert_eq!(
+            **world.get::<SpeedFactor>(snake).unwrap(),
+            config.apple_types[SPEED].speed_factor
+        );
+    }
+
+    #[test]
+    fn rotten_apples_eaten_together_stop_at_the_starting_length() {
+        let mut world = world();
+        let snake = spawn_snake(&mut world);
+        let no_of_parts = world.resource::<SnakeConfig>().no_of_parts;
+
+        eat(&mut world, snake, &[APPLE]);
+        eat(&mut world, snake, &[ROTTEN, ROTTEN, ROTTEN]);
+        assert_eq!(parts_in_step(&mut world, snake), no_of_parts);
+        eat(&mut world, snake, &[ROTTEN, ROTTEN]);
+        assert_eq!(parts_in_step(&mut world, snake), no_of_parts);
+    }
+}
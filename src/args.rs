@@ -0,0 +1,39 @@
+//! Command-line options shared by the game, the server and the bot.
+
+/// The value after the option `name`, given either as `name value` or `name=value`.
+pub fn arg_value(args: impl IntoIterator<Item = String>, name: &str) -> Option<String> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some(value) = arg
+            .strip_prefix(name)
+            .and_then(|rest| rest.strip_prefix('='))
+        {
+            return Some(value.to_owned());
+        }
+        if arg == name {
+            return args.next();
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn values_follow_a_space_or_an_equals_sign() {
+        let args = args(&["--seed", "12", "--connect=localhost:7878", "--seedling"]);
+        assert_eq!(arg_value(args.clone(), "--seed").as_deref(), Some("12"));
+        assert_eq!(
+            arg_value(args.clone(), "--connect").as_deref(),
+            Some("localhost:7878")
+        );
+        assert_eq!(arg_value(args.clone(), "--level"), None);
+        assert_eq!(arg_value(args[..1].to_vec(), "--seed"), None);
+    }
+}
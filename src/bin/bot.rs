@@ -0,0 +1,79 @@
+//! A scripted client that plays on a server with one of the rivals' brains, for testing
+//! a server without anyone at the keyboard.
+//!
+//! Usage: `snake-bot [--connect ADDRESS] [--brain Greedy|Cautious|Aggressive]`
+
+use std::{process::ExitCode, thread, time::Duration};
+
+use snake::{
+    args::arg_value,
+    net::{Client, DEFAULT_PORT, ServerMessage, bot::bot_input},
+    rival::Brain,
+};
+
+/// How long the bot sleeps between checks for new snapshots.
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+fn main() -> ExitCode {
+    let arg = |name: &str| arg_value(std::env::args().skip(1), name);
+    let address = arg("--connect").unwrap_or(format!("127.0.0.1:{DEFAULT_PORT}"));
+    let brain = match arg("--brain").map(|name| ron::from_str::<Brain>(&name)) {
+        None => Brain::Greedy,
+        Some(Ok(brain)) => brain,
+        Some(Err(_)) => {
+            eprintln!("Unknown brain; pick one of {:?}", Brain::ALL);
+            return ExitCode::FAILURE;
+        }
+    };
+    let steer = brain.build();
+
+    let mut client = match Client::connect(&address) {
+        Ok(client) => client,
+        Err(error) => {
+            eprintln!("Could not connect to {address}: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut world = None;
+    loop {
+        let messages = match client.poll() {
+            Ok(messages) => messages,
+            Err(error) => {
+                eprintln!("Lost the server: {error}");
+                return ExitCode::FAILURE;
+            }
+        };
+        for message in messages {
+            match message {
+                ServerMessage::Welcome {
+                    player,
+                    config,
+                    level,
+                    ..
+                } => {
+                    if world.is_none() {
+                        let name = &level.name;
+                        println!(
+                            "Playing {name} as player {} with the {brain:?} brain",
+                            player + 1
+                        );
+                    }
+                    world = Some((player, config, level));
+                }
+                ServerMessage::Full => {
+                    eprintln!("The server at {address} is full");
+                    return ExitCode::FAILURE;
+                }
+                ServerMessage::Snapshot(snapshot) => {
+                    let Some((player, config, level)) = &world else {
+                        continue;
+                    };
+                    let input = bot_input(&*steer, *player, &snapshot, level, config);
+                    // A lost input is made up for by the next snapshot's.
+                    let _ = client.send_input(input);
+                }
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
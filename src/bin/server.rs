@@ -0,0 +1,73 @@
+//! Headless game server. Clients join with `snake --connect ADDRESS` or `snake-bot`.
+//!
+//! Usage: `snake-server [--bind ADDRESS] [--level NAME] [--seed SEED] [--assets DIR]`
+
+use std::{fs, path::Path, process::ExitCode};
+
+use serde::de::DeserializeOwned;
+use snake::{
+    args::arg_value,
+    config::SnakeConfig,
+    level::Level,
+    net::{DEFAULT_PORT, Server, Simulation},
+};
+
+/// Length of a server tick, the same as the game's fixed timestep.
+const TICK_SECONDS: f32 = 1.0 / 64.0;
+
+fn main() -> ExitCode {
+    let arg = |name: &str| arg_value(std::env::args().skip(1), name);
+    let bind = arg("--bind").unwrap_or(format!("0.0.0.0:{DEFAULT_PORT}"));
+    let level_name = arg("--level").unwrap_or_else(|| String::from("open"));
+    let seed = arg("--seed").and_then(|seed| seed.parse().ok());
+    let seed = seed.unwrap_or_else(rand::random);
+    let assets = arg("--assets").unwrap_or_else(|| String::from("assets"));
+    let assets = Path::new(&assets);
+
+    let config = load::<SnakeConfig>(&assets.join("config/snake.snake.ron")).and_then(|config| {
+        config.validate().map_err(|error| error.to_string())?;
+        Ok(config)
+    });
+    let config = match config {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Using the default config: {error}");
+            SnakeConfig::default()
+        }
+    };
+    let level =
+        load::<Level>(&assets.join(format!("levels/{level_name}.level.ron"))).and_then(|level| {
+            level.validate().map_err(|error| error.to_string())?;
+            Ok(level)
+        });
+    let level = match level {
+        Ok(level) => level,
+        Err(error) => {
+            eprintln!("Could not load level {level_name}: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let level_title = level.name.clone();
+    let simulation = Simulation::new(config, level, seed);
+    let mut server = match Server::bind(&bind, simulation, TICK_SECONDS) {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!("Could not listen on {bind}: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("Serving {level_title} on {bind} with seed {seed}");
+    match server.run(|failure| eprintln!("{failure}")) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Server stopped: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn load<T: DeserializeOwned>(path: &Path) -> Result<T, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    ron::from_str(&text).map_err(|error| format!("{}: {error}", path.display()))
+}
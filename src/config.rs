@@ -0,0 +1,578 @@
+//! Runtime tuning for the snake, loaded from `assets/config/snake.snake.ron`.
+
+use std::fmt;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{powerup::PowerUp, rival::Brain};
+
+/// Everything a designer may want to tweak without recompiling.
+/// Fields missing from the config file keep their default value.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(
+    feature = "bevy",
+    derive(
+        bevy::asset::Asset,
+        bevy::reflect::TypePath,
+        bevy::ecs::resource::Resource
+    )
+)]
+#[serde(default)]
+pub struct SnakeConfig {
+    /// Number of joints in a freshly spawned snake, head included.
+    pub no_of_parts: usize,
+    pub part_length: f32,
+    pub part_thickness: f32,
+    pub head_length: f32,
+    pub head_thickness: f32,
+    /// Units per second.
+    pub speed: f32,
+    /// Largest bend between neighbouring segments, in degrees.
+    pub max_bend_degrees: Option<f32>,
+    /// How fast the head turns in steering mode, in degrees per second.
+    pub turn_rate_degrees: f32,
+    /// Width of a cell in grid mode. Segment lengths should be multiples of it for the
+    /// body to sit on cell centres.
+    pub grid_cell_size: f32,
+    /// Cells the head moves a second in grid mode.
+    pub grid_moves_per_second: f32,
+    /// Number of apples on the field at once.
+    pub apple_count: usize,
+    /// The kinds of apple that can spawn.
+    pub apple_types: Vec<AppleType>,
+    pub apple_radius: f32,
+    /// Radius of the sensor around an apple that makes the snake open its mouth.
+    pub apple_field_radius: f32,
+    /// Closest a new apple may spawn to the snake's head.
+    pub apple_min_head_distance: f32,
+    /// Number of power-up pickups on the field at once.
+    pub power_up_count: usize,
+    /// The power-ups that can spawn.
+    pub power_ups: Vec<PowerUpType>,
+    /// Speed multiplier while a speed boost runs.
+    pub speed_boost_factor: f32,
+    /// Speed multiplier while slow motion runs.
+    pub slow_motion_factor: f32,
+    /// How fast a magnet pulls apples within `apple_field_radius` of the head, in units per
+    /// second.
+    pub magnet_pull: f32,
+    /// Score multiplier while a score multiplier runs.
+    pub score_multiplier: i32,
+    /// Brains of the rival snakes, handed out in order and repeated when there are more
+    /// rivals than brains.
+    pub rival_brains: Vec<Brain>,
+    /// Rival speed relative to `speed`.
+    pub rival_speed_factor: f32,
+    /// How far ahead rivals look for walls and bodies.
+    pub rival_lookahead: f32,
+    /// A dead rival drops an apple on every this many of its joints.
+    pub rival_drop_spacing: usize,
+}
+
+impl Default for SnakeConfig {
+    fn default() -> Self {
+        Self {
+            no_of_parts: 10,
+            part_length: 20.0,
+            part_thickness: 5.0,
+            head_length: 40.0,
+            head_thickness: 35.0,
+            speed: 625.0 / 2.0,
+            max_bend_degrees: Some(30.0),
+            turn_rate_degrees: 180.0,
+            grid_cell_size: 20.0,
+            grid_moves_per_second: 10.0,
+            apple_count: 3,
+            apple_types: vec![
+                AppleType::default(),
+                AppleType {
+                    name: String::from("Golden"),
+                    sprite: String::from("sprites/golden_apple.png"),
+                    score: 5,
+                    spawn_weight: 10.0,
+                    ..AppleType::default()
+                },
+                AppleType {
+                    name: String::from("Rotten"),
+                    sprite: String::from("sprites/rotten_apple.png"),
+                    score: 0,
+                    growth: -2,
+                    spawn_weight: 12.0,
+                    ..AppleType::default()
+                },
+                AppleType {
+                    name: String::from("Speed"),
+                    sprite: String::from("sprites/speed_apple.png"),
+                    spawn_weight: 8.0,
+                    speed_factor: 1.15,
+                    ..AppleType::default()
+                },
+            ],
+            apple_radius: 15.0,
+            apple_field_radius: 150.0,
+            apple_min_head_distance: 150.0,
+            power_up_count: 1,
+            power_ups: vec![
+                PowerUpType::new(PowerUp::SpeedBoost, 6.0),
+                PowerUpType::new(PowerUp::SlowMotion, 6.0),
+                PowerUpType::new(PowerUp::Ghost, 5.0),
+                PowerUpType::new(PowerUp::Magnet, 8.0),
+                PowerUpType::new(PowerUp::ScoreMultiplier, 10.0),
+            ],
+            speed_boost_factor: 1.5,
+            slow_motion_factor: 0.6,
+            magnet_pull: 250.0,
+            score_multiplier: 2,
+            rival_brains: Brain::ALL.to_vec(),
+            rival_speed_factor: 0.8,
+            rival_lookahead: 60.0,
+            rival_drop_spacing: 3,
+        }
+    }
+}
+
+/// One row of the apple type table.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AppleType {
+    pub name: String,
+    /// Asset path of its sprite.
+    pub sprite: String,
+    /// Points for eating it.
+    pub score: i32,
+    /// Parts the snake gains from eating it, or loses when negative.
+    pub growth: i32,
+    /// How often it spawns, relative to the other types.
+    pub spawn_weight: f32,
+    /// Multiplies the snake's speed for the rest of the game.
+    pub speed_factor: f32,
+}
+
+impl AppleType {
+    /// How many parts a snake `parts` long gains, and loses, from eating it. Snakes never
+    /// shrink below `min_parts`, the length they start with.
+    pub fn growth(&self, parts: usize, min_parts: usize) -> (usize, usize) {
+        growth_in_parts(self.growth, parts, min_parts)
+    }
+}
+
+/// How many parts a snake `parts` long gains, and loses, when it grows by `growth` parts,
+/// or shrinks when that is negative. Snakes never shrink below `min_parts`.
+pub fn growth_in_parts(growth: i32, parts: usize, min_parts: usize) -> (usize, usize) {
+    let added = growth.max(0) as usize;
+    let removed = (growth.min(0).unsigned_abs() as usize).min(parts.saturating_sub(min_parts));
+    (added, removed)
+}
+
+impl Default for AppleType {
+    fn default() -> Self {
+        Self {
+            name: String::from("Apple"),
+            sprite: String::from("sprites/apple.png"),
+            score: 1,
+            growth: 2,
+            spawn_weight: 70.0,
+            speed_factor: 1.0,
+        }
+    }
+}
+
+/// One row of the power-up table.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PowerUpType {
+    pub effect: PowerUp,
+    /// How long the effect lasts once picked up.
+    pub seconds: f32,
+    /// How often it spawns, relative to the other power-ups.
+    pub spawn_weight: f32,
+}
+
+impl PowerUpType {
+    fn new(effect: PowerUp, seconds: f32) -> Self {
+        Self {
+            effect,
+            seconds,
+            spawn_weight: 1.0,
+        }
+    }
+}
+
+impl SnakeConfig {
+    /// Index of the segment that starts the head, which is always the second to last.
+    pub fn head_index(&self) -> usize {
+        self.no_of_parts.saturating_sub(2)
+    }
+
+    /// Checks for values the game can't run with.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.no_of_parts < MIN_PARTS {
+            return Err(ConfigError::TooFewParts(self.no_of_parts));
+        }
+        if !self.part_length.is_finite() || self.part_length <= PART_COLLIDER_INSET {
+            return Err(ConfigError::PartTooShort(self.part_length));
+        }
+        let lengths_and_rates = [
+            ("part_thickness", self.part_thickness),
+            ("head_length", self.head_length),
+            ("head_thickness", self.head_thickness),
+            ("speed", self.speed),
+            ("turn_rate_degrees", self.turn_rate_degrees),
+            ("grid_cell_size", self.grid_cell_size),
+            ("grid_moves_per_second", self.grid_moves_per_second),
+            ("apple_radius", self.apple_radius),
+            ("apple_field_radius", self.apple_field_radius),
+            ("speed_boost_factor", self.speed_boost_factor),
+            ("slow_motion_factor", self.slow_motion_factor),
+            ("rival_speed_factor", self.rival_speed_factor),
+            ("rival_lookahead", self.rival_lookahead),
+            ("score_multiplier", self.score_multiplier as f32),
+        ];
+        let max_bend = self
+            .max_bend_degrees
+            .map(|degrees| ("max_bend_degrees", degrees));
+        let apple_speeds = self
+            .apple_types
+            .iter()
+            .map(|apple| (apple.name.as_str(), apple.speed_factor));
+        if let Some((name, value)) = lengths_and_rates
+            .into_iter()
+            .chain(max_bend)
+            .chain(apple_speeds)
+            .find(|(_, value)| !value.is_finite() || *value <= 0.0)
+        {
+            let name = name.to_owned();
+            return Err(ConfigError::NotPositive { name, value });
+        }
+        if let Some(power_up) = self
+            .power_ups
+            .iter()
+            .find(|power_up| !power_up.seconds.is_finite() || power_up.seconds <= 0.0)
+        {
+            return Err(ConfigError::NotPositive {
+                name: format!("{:?} seconds", power_up.effect),
+                value: power_up.seconds,
+            });
+        }
+        let distances = [
+            ("apple_min_head_distance", self.apple_min_head_distance),
+            ("magnet_pull", self.magnet_pull),
+        ];
+        if let Some((name, value)) = distances
+            .into_iter()
+            .find(|(_, value)| !value.is_finite() || *value < 0.0)
+        {
+            let name = name.to_owned();
+            return Err(ConfigError::Negative { name, value });
+        }
+        let apple_weights = self
+            .apple_types
+            .iter()
+            .map(|apple| (apple.name.clone(), apple.spawn_weight));
+        let power_up_weights = self
+            .power_ups
+            .iter()
+            .map(|power_up| (format!("{:?}", power_up.effect), power_up.spawn_weight));
+        match apple_weights
+            .chain(power_up_weights)
+            .find(|(_, weight)| !weight.is_finite() || *weight < 0.0)
+        {
+            Some((name, weight)) => Err(ConfigError::InvalidSpawnWeight { name, weight }),
+            None => Ok(()),
+        }
+    }
+
+    /// Segment lengths for a fresh snake `Limb`, tail first.
+    pub fn segment_lengths(&self) -> Vec<f32> {
+        let mut lengths = vec![self.part_length; self.no_of_parts];
+        lengths[self.head_index()] = self.head_length;
+        lengths
+    }
+
+    /// The `Limb::reach` of a fresh snake, which spawn points keep room for.
+    pub fn starting_reach(&self) -> f32 {
+        let lengths = self.segment_lengths();
+        lengths[..lengths.len().saturating_sub(1)].iter().sum()
+    }
+
+    pub fn max_bend(&self) -> Option<f32> {
+        self.max_bend_degrees.map(f32::to_radians)
+    }
+
+    /// Turn rate in radians per second.
+    pub fn turn_rate(&self) -> f32 {
+        self.turn_rate_degrees.to_radians()
+    }
+
+    /// Picks an index into `apple_types` by spawn weight, or `None` if there are none.
+    pub fn random_apple_type(&self, rng: &mut impl Rng) -> Option<usize> {
+        let weights: Vec<f32> = self
+            .apple_types
+            .iter()
+            .map(|apple| apple.spawn_weight)
+            .collect();
+        pick_by_weight(&weights, rng)
+    }
+
+    /// Picks an index into `power_ups` by spawn weight, or `None` if there are none.
+    pub fn random_power_up(&self, rng: &mut impl Rng) -> Option<usize> {
+        let weights: Vec<f32> = self
+            .power_ups
+            .iter()
+            .map(|power_up| power_up.spawn_weight)
+            .collect();
+        pick_by_weight(&weights, rng)
+    }
+}
+
+/// A snake needs a tail segment besides its head.
+pub const MIN_PARTS: usize = 2;
+
+/// How much shorter a part's collider is than the part, so neighbouring parts' colliders
+/// don't overlap where they bend.
+pub const PART_COLLIDER_INSET: f32 = 10.0;
+
+/// Why a `SnakeConfig` was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    TooFewParts(usize),
+    /// A part length no longer than [`PART_COLLIDER_INSET`], which would leave its collider
+    /// with no length.
+    PartTooShort(f32),
+    /// A length, speed, rate, multiplier or duration that is zero, negative, infinite or NaN.
+    NotPositive {
+        name: String,
+        value: f32,
+    },
+    /// A distance that is negative, infinite or NaN.
+    Negative {
+        name: String,
+        value: f32,
+    },
+    /// A spawn weight that is negative, infinite or NaN.
+    InvalidSpawnWeight {
+        name: String,
+        weight: f32,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooFewParts(parts) => {
+                write!(f, "a snake needs at least {MIN_PARTS} parts, got {parts}")
+            }
+            Self::PartTooShort(length) => write!(
+                f,
+                "parts need to be longer than {PART_COLLIDER_INSET}, got {length}"
+            ),
+            Self::NotPositive { name, value } => write!(f, "{name} must be positive, got {value}"),
+            Self::Negative { name, value } => {
+                write!(f, "{name} must be finite and not negative, got {value}")
+            }
+            Self::InvalidSpawnWeight { name, weight } => {
+                write!(f, "{name} has a spawn weight of {weight}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Picks an index into `weights` with a chance proportional to its weight. When all
+/// weights are zero the first one is picked.
+/// Weights must be finite and not negative, which [`SnakeConfig::validate`] checks.
+fn pick_by_weight(weights: &[f32], rng: &mut impl Rng) -> Option<usize> {
+    let total_weight: f32 = weights.iter().sum();
+    if total_weight <= 0.0 {
+        return (!weights.is_empty()).then_some(0);
+    }
+    let mut pick = rng.random_range(0.0..total_weight);
+    for (index, weight) in weights.iter().enumerate() {
+        if pick < *weight {
+            return Some(index);
+        }
+        pick -= weight;
+    }
+    Some(weights.len() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+    use crate::fabrik::Limb;
+
+    #[test]
+    fn segment_lengths_put_the_head_second_to_last() {
+        let config = SnakeConfig::default();
+        let lengths = config.segment_lengths();
+        assert_eq!(lengths.len(), config.no_of_parts);
+        assert_eq!(lengths[config.no_of_parts - 2], config.head_length);
+        assert_eq!(lengths[0], config.part_length);
+    }
+
+    #[test]
+    fn the_starting_reach_is_a_fresh_limbs() {
+        let config = SnakeConfig::default();
+        let limb = Limb::new(Vec2::ZERO, Vec2::ZERO, &config.segment_lengths());
+        assert_eq!(config.starting_reach(), limb.reach());
+    }
+
+    #[test]
+    fn apples_never_shrink_a_snake_below_its_starting_length() {
+        let shrinking = AppleType {
+            growth: -3,
+            ..AppleType::default()
+        };
+        assert_eq!(shrinking.growth(12, 10), (0, 2));
+        assert_eq!(shrinking.growth(20, 10), (0, 3));
+        assert_eq!(shrinking.growth(10, 10), (0, 0));
+        assert_eq!(AppleType::default().growth(10, 10), (2, 0));
+    }
+
+    #[test]
+    fn snakes_need_at_least_two_parts() {
+        let mut config = SnakeConfig {
+            no_of_parts: 1,
+            ..SnakeConfig::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::TooFewParts(1)));
+        assert_eq!(config.head_index(), 0);
+        config.no_of_parts = MIN_PARTS;
+        assert_eq!(config.validate(), Ok(()));
+        assert_eq!(SnakeConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn lengths_speeds_and_distances_must_make_sense() {
+        assert_eq!(SnakeConfig::default().validate(), Ok(()));
+
+        let config = SnakeConfig {
+            part_length: PART_COLLIDER_INSET,
+            ..SnakeConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::PartTooShort(PART_COLLIDER_INSET))
+        );
+
+        let config = SnakeConfig {
+            grid_cell_size: 0.0,
+            ..SnakeConfig::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::NotPositive { name, .. }) if name == "grid_cell_size"
+        ));
+
+        let config = SnakeConfig {
+            speed: f32::NAN,
+            ..SnakeConfig::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::NotPositive { name, .. }) if name == "speed"
+        ));
+
+        let config = SnakeConfig {
+            score_multiplier: -2,
+            ..SnakeConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::NotPositive {
+                name: String::from("score_multiplier"),
+                value: -2.0
+            })
+        );
+
+        let config = SnakeConfig {
+            max_bend_degrees: Some(-5.0),
+            ..SnakeConfig::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::NotPositive { name, .. }) if name == "max_bend_degrees"
+        ));
+
+        for seconds in [0.0, -3.0, f32::INFINITY] {
+            let mut config = SnakeConfig::default();
+            config.power_ups[0].seconds = seconds;
+            assert_eq!(
+                config.validate(),
+                Err(ConfigError::NotPositive {
+                    name: format!("{:?} seconds", config.power_ups[0].effect),
+                    value: seconds
+                })
+            );
+        }
+
+        let config = SnakeConfig {
+            magnet_pull: -1.0,
+            ..SnakeConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::Negative {
+                name: String::from("magnet_pull"),
+                value: -1.0
+            })
+        );
+    }
+
+    #[test]
+    fn spawn_weights_must_be_finite_and_not_negative() {
+        for weight in [-1.0, f32::INFINITY, f32::NAN] {
+            let mut config = SnakeConfig::default();
+            config.apple_types[1].spawn_weight = weight;
+            let name = config.apple_types[1].name.clone();
+            assert!(matches!(
+                config.validate(),
+                Err(ConfigError::InvalidSpawnWeight { name: rejected, .. }) if rejected == name
+            ));
+
+            let mut config = SnakeConfig::default();
+            config.power_ups[0].spawn_weight = weight;
+            assert!(matches!(
+                config.validate(),
+                Err(ConfigError::InvalidSpawnWeight { .. })
+            ));
+        }
+        let mut config = SnakeConfig::default();
+        config.apple_types[0].spawn_weight = 0.0;
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn apple_types_are_picked_by_weight() {
+        use rand::{SeedableRng, rngs::StdRng};
+
+        let mut config = SnakeConfig::default();
+        for apple in &mut config.apple_types {
+            apple.spawn_weight = 0.0;
+        }
+        config.apple_types[2].spawn_weight = 1.0;
+        let mut rng = StdRng::seed_from_u64(1);
+        assert!((0..50).all(|_| config.random_apple_type(&mut rng) == Some(2)));
+
+        config.apple_types.clear();
+        assert_eq!(config.random_apple_type(&mut rng), None);
+    }
+
+    #[test]
+    fn shipped_config_matches_the_defaults() {
+        let config: SnakeConfig =
+            ron::from_str(include_str!("../assets/config/snake.snake.ron")).unwrap();
+        assert_eq!(config, SnakeConfig::default());
+    }
+
+    #[test]
+    fn missing_fields_fall_back_to_defaults() {
+        let config: SnakeConfig = ron::from_str("(no_of_parts: 6, speed: 100.0)").unwrap();
+        assert_eq!(config.no_of_parts, 6);
+        assert_eq!(config.speed, 100.0);
+        assert_eq!(config.head_length, SnakeConfig::default().head_length);
+    }
+}
@@ -0,0 +1,332 @@
+//! Keyboard bindings for the game's input actions, persisted through `storage`, and
+//! gamepad steering.
+
+use std::{collections::BTreeMap, io};
+
+use bevy::prelude::{ButtonInput, Gamepad, GamepadButton, KeyCode, Resource};
+use serde::{Deserialize, Serialize};
+
+use crate::{replay::Direction, storage};
+
+/// Number of keys that can be bound to each action.
+pub const KEYS_PER_ACTION: usize = 2;
+const STORAGE_KEY: &str = "controls";
+/// How far the left stick has to be pushed before it steers.
+pub const STICK_DEADZONE: f32 = 0.5;
+/// The key slot each player steers with in versus: the second slot, WASD by default, for
+/// the first player and the first slot, the arrow keys, for the second.
+pub const VERSUS_KEY_SLOTS: [usize; 2] = [1, 0];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Pause,
+    Restart,
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Pause,
+        Action::Restart,
+    ];
+
+    pub fn direction(self) -> Option<Direction> {
+        match self {
+            Action::Up => Some(Direction::Up),
+            Action::Down => Some(Direction::Down),
+            Action::Left => Some(Direction::Left),
+            Action::Right => Some(Direction::Right),
+            Action::Pause | Action::Restart => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Left => "Left",
+            Action::Right => "Right",
+            Action::Pause => "Pause",
+            Action::Restart => "Restart",
+        }
+    }
+}
+
+/// What the snake is steered with during play.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControlScheme {
+    /// The bound keys or a gamepad.
+    #[default]
+    Buttons,
+    /// The head makes for the mouse cursor while the left button is held, or for a touch.
+    Pointer,
+}
+
+impl ControlScheme {
+    pub fn label(self) -> &'static str {
+        match self {
+            ControlScheme::Buttons => "Keys & gamepad",
+            ControlScheme::Pointer => "Mouse & touch",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            ControlScheme::Buttons => ControlScheme::Pointer,
+            ControlScheme::Pointer => ControlScheme::Buttons,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    keys: BTreeMap<Action, [Option<KeyCode>; KEYS_PER_ACTION]>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let keys = [
+            (Action::Up, [Some(KeyCode::ArrowUp), Some(KeyCode::KeyW)]),
+            (
+                Action::Down,
+                [Some(KeyCode::ArrowDown), Some(KeyCode::KeyS)],
+            ),
+            (
+                Action::Left,
+                [Some(KeyCode::ArrowLeft), Some(KeyCode::KeyA)],
+            ),
+            (
+                Action::Right,
+                [Some(KeyCode::ArrowRight), Some(KeyCode::KeyD)],
+            ),
+            (Action::Pause, [Some(KeyCode::Escape), Some(KeyCode::KeyP)]),
+            (Action::Restart, [Some(KeyCode::KeyR), None]),
+        ];
+        Self {
+            keys: keys.into_iter().collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Reads the saved bindings. Actions missing from them keep their default keys.
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        if let Some(saved) =
+            storage::read(STORAGE_KEY).and_then(|text| ron::from_str::<KeyBindings>(&text).ok())
+        {
+            bindings.keys.extend(saved.keys);
+        }
+        bindings
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(io::Error::other)?;
+        storage::write(STORAGE_KEY, &text)
+    }
+
+    pub fn keys(&self, action: Action) -> [Option<KeyCode>; KEYS_PER_ACTION] {
+        self.keys.get(&action).copied().unwrap_or_default()
+    }
+
+    /// Binds `key` to `slot` of `action`, taking it away from whatever it was bound to before.
+    /// `None` clears the slot.
+    pub fn bind(&mut self, action: Action, slot: usize, key: Option<KeyCode>) {
+        if key.is_some() {
+            for keys in self.keys.values_mut() {
+                for bound in keys.iter_mut().filter(|bound| **bound == key) {
+                    *bound = None;
+                }
+            }
+        }
+        self.keys.entry(action).or_default()[slot] = key;
+    }
+
+    pub fn pressed(&self, action: Action, input: &ButtonInput<KeyCode>) -> bool {
+        input.any_pressed(self.keys(action).into_iter().flatten())
+    }
+
+    pub fn just_pressed(&self, action: Action, input: &ButtonInput<KeyCode>) -> bool {
+        input.any_just_pressed(self.keys(action).into_iter().flatten())
+    }
+
+    /// The direction being steered in. When several are held, the one listed last in
+    /// [`Action::ALL`] wins.
+    pub fn direction(&self, input: &ButtonInput<KeyCode>) -> Option<Direction> {
+        Action::ALL
+            .into_iter()
+            .rev()
+            .filter(|action| self.pressed(*action, input))
+            .find_map(Action::direction)
+    }
+
+    fn slot_key(&self, action: Action, slot: usize) -> Option<KeyCode> {
+        self.keys(action).get(slot).copied().flatten()
+    }
+
+    /// Whether the key in `slot` of `action` was just pressed, ignoring the other slots.
+    pub fn slot_just_pressed(
+        &self,
+        action: Action,
+        slot: usize,
+        input: &ButtonInput<KeyCode>,
+    ) -> bool {
+        self.slot_key(action, slot)
+            .is_some_and(|key| input.just_pressed(key))
+    }
+
+    /// Like [`KeyBindings::direction`], but only for the keys in `slot`.
+    pub fn slot_direction(&self, slot: usize, input: &ButtonInput<KeyCode>) -> Option<Direction> {
+        Action::ALL
+            .into_iter()
+            .rev()
+            .filter(|action| {
+                self.slot_key(*action, slot)
+                    .is_some_and(|key| input.pressed(key))
+            })
+            .find_map(Action::direction)
+    }
+}
+
+/// The direction a gamepad's D-pad is held in. Buttons follow the same precedence as
+/// [`KeyBindings::direction`].
+pub fn dpad_direction(gamepad: &Gamepad) -> Option<Direction> {
+    let dpad = [
+        (GamepadButton::DPadUp, Direction::Up),
+        (GamepadButton::DPadDown, Direction::Down),
+        (GamepadButton::DPadLeft, Direction::Left),
+        (GamepadButton::DPadRight, Direction::Right),
+    ];
+    dpad.into_iter()
+        .rev()
+        .find(|(button, _)| gamepad.pressed(*button))
+        .map(|(_, direction)| direction)
+}
+
+/// The direction a gamepad steers in with its D-pad, or failing that its left stick.
+pub fn gamepad_direction(gamepad: &Gamepad) -> Option<Direction> {
+    if let Some(direction) = dpad_direction(gamepad) {
+        return Some(direction);
+    }
+
+    let stick = gamepad.left_stick();
+    if stick.length() < STICK_DEADZONE {
+        None
+    } else if stick.x.abs() > stick.y.abs() {
+        Some(if stick.x > 0.0 {
+            Direction::Right
+        } else {
+            Direction::Left
+        })
+    } else {
+        Some(if stick.y > 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        })
+    }
+}
+
+/// The angle the left stick points at, in radians counter-clockwise from +x, once it is
+/// past the deadzone.
+pub fn stick_heading(gamepad: &Gamepad) -> Option<f32> {
+    let stick = gamepad.left_stick();
+    (stick.length() >= STICK_DEADZONE).then(|| stick.to_angle())
+}
+
+/// A short name for `key` to show in the settings screen, e.g. `W` rather than `KeyW`.
+pub fn key_label(key: KeyCode) -> String {
+    let name = format!("{key:?}");
+    ["Key", "Digit"]
+        .into_iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .map_or_else(|| name.clone(), str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebinding_a_key_unbinds_it_elsewhere() {
+        let mut bindings = KeyBindings::default();
+        bindings.bind(Action::Left, 1, Some(KeyCode::KeyW));
+        assert_eq!(bindings.keys(Action::Left)[1], Some(KeyCode::KeyW));
+        assert_eq!(bindings.keys(Action::Up), [Some(KeyCode::ArrowUp), None]);
+    }
+
+    #[test]
+    fn steers_with_any_bound_key() {
+        let mut bindings = KeyBindings::default();
+        bindings.bind(Action::Up, 1, Some(KeyCode::KeyZ));
+        let mut input = ButtonInput::default();
+        input.press(KeyCode::KeyZ);
+        assert_eq!(bindings.direction(&input), Some(Direction::Up));
+        input.press(KeyCode::ArrowDown);
+        assert_eq!(bindings.direction(&input), Some(Direction::Down));
+    }
+
+    #[test]
+    fn versus_players_steer_with_their_own_slot() {
+        let bindings = KeyBindings::default();
+        let [first, second] = VERSUS_KEY_SLOTS;
+        let mut input = ButtonInput::default();
+        input.press(KeyCode::KeyA);
+        input.press(KeyCode::ArrowUp);
+        assert_eq!(
+            bindings.slot_direction(first, &input),
+            Some(Direction::Left)
+        );
+        assert_eq!(bindings.slot_direction(second, &input), Some(Direction::Up));
+        assert!(bindings.slot_just_pressed(Action::Left, first, &input));
+        assert!(!bindings.slot_just_pressed(Action::Left, second, &input));
+    }
+
+    #[test]
+    fn saved_bindings_round_trip() {
+        let mut bindings = KeyBindings::default();
+        bindings.bind(Action::Pause, 0, None);
+        let text = ron::to_string(&bindings).unwrap();
+        assert_eq!(ron::from_str::<KeyBindings>(&text).unwrap(), bindings);
+    }
+
+    #[test]
+    fn gamepads_steer_with_the_dpad_before_the_stick() {
+        use bevy::prelude::GamepadAxis;
+
+        let mut gamepad = Gamepad::default();
+        assert_eq!(gamepad_direction(&gamepad), None);
+        gamepad.analog_mut().set(GamepadAxis::LeftStickX, -0.9);
+        gamepad.analog_mut().set(GamepadAxis::LeftStickY, 0.3);
+        assert_eq!(gamepad_direction(&gamepad), Some(Direction::Left));
+        gamepad.digital_mut().press(GamepadButton::DPadUp);
+        assert_eq!(gamepad_direction(&gamepad), Some(Direction::Up));
+    }
+
+    #[test]
+    fn stick_heading_ignores_input_inside_the_deadzone() {
+        use bevy::prelude::GamepadAxis;
+
+        let mut gamepad = Gamepad::default();
+        gamepad.analog_mut().set(GamepadAxis::LeftStickY, 0.3);
+        assert_eq!(stick_heading(&gamepad), None);
+        gamepad.analog_mut().set(GamepadAxis::LeftStickY, -0.8);
+        let heading = stick_heading(&gamepad).unwrap();
+        assert!((heading + std::f32::consts::FRAC_PI_2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn key_labels_drop_prefixes() {
+        assert_eq!(key_label(KeyCode::KeyQ), "Q");
+        assert_eq!(key_label(KeyCode::Digit1), "1");
+        assert_eq!(key_label(KeyCode::ArrowLeft), "ArrowLeft");
+    }
+}
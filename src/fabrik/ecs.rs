@@ -0,0 +1,440 @@
+use avian2d::prelude::*;
+use bevy::prelude::*;
+
+use super::Limb;
+use crate::{
+    config::{PART_COLLIDER_INSET, SnakeConfig},
+    level::Arena,
+};
+
+/// Draws the start of the snake's `Limb` segment at this index, counting from the tail.
+/// The `Limb` holds the positions, so the index is what ties the entity to them. It shifts
+/// when the tail grows or shrinks.
+#[derive(Component)]
+pub struct Joint(pub usize);
+
+#[derive(PhysicsLayer, Default)]
+pub enum GameLayer {
+    #[default]
+    Default, // Layer 0 - the default layer that objects are assigned to
+    Apple,      // Layer 1
+    AppleField, // Layer 2
+    Boundary,
+    SnakeHead,
+    SnakePart, // Layer 3
+    RivalHead,
+    RivalPart,
+}
+
+#[derive(Component)]
+pub struct SnakePart;
+
+pub type JointFilter = (With<Joint>, Without<LimbSegment>);
+
+pub type LimbFilter = (With<LimbSegment>, Without<Joint>);
+
+/// Draws the snake's `Limb` segment at this index, between the joints with this index and
+/// the next.
+#[derive(Component)]
+pub struct LimbSegment(pub usize);
+
+#[derive(Component)]
+pub struct HeadOfSnake;
+
+/// Ties a joint or segment entity to the snake whose `Limb` it draws. Its `Joint` or
+/// `LimbSegment` index counts along that snake only.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[relationship(relationship_target = SnakeParts)]
+pub struct PartOf(pub Entity);
+
+/// The joint and segment entities of a snake, which go with it when it is despawned.
+#[derive(Component, Debug, Default)]
+#[relationship_target(relationship = PartOf, linked_spawn)]
+pub struct SnakeParts(Vec<Entity>);
+
+/// How far the snake's head moves each tick, or how many cells in grid mode.
+#[derive(Component, Clone, Copy, Debug, Default, PartialEq, Deref, DerefMut)]
+pub struct SnakeVelocity(pub Vec2);
+
+/// What a snake's parts look like and what they run into.
+#[derive(Component, Clone)]
+pub struct SnakeStyle {
+    pub joint_mesh: Handle<Mesh>,
+    pub joint_material: Handle<ColorMaterial>,
+    pub part_color: Color,
+    pub head_layers: CollisionLayers,
+    pub part_layers: CollisionLayers,
+}
+
+impl SnakeStyle {
+    /// A player's snake, which eats apples and power-ups and can run into itself and the
+    /// other players.
+    pub fn player(
+        joint_mesh: Handle<Mesh>,
+        joint_material: Handle<ColorMaterial>,
+        part_color: Color,
+    ) -> Self {
+        Self {
+            joint_mesh,
+            joint_material,
+            part_color,
+            head_layers: CollisionLayers::new(
+                GameLayer::SnakeHead,
+                [
+                    GameLayer::Default,
+                    GameLayer::Boundary,
+                    GameLayer::SnakeHead,
+                    GameLayer::SnakePart,
+                    GameLayer::Apple,
+                    GameLayer::AppleField,
+                    GameLayer::RivalHead,
+                    GameLayer::RivalPart,
+                ],
+            ),
+            part_layers: CollisionLayers::new(
+                GameLayer::SnakePart,
+                [
+                    GameLayer::Default,
+                    GameLayer::SnakeHead,
+                    GameLayer::RivalHead,
+                ],
+            ),
+        }
+    }
+
+    /// A computer-controlled snake. It eats apples but leaves power-ups alone.
+    pub fn rival(
+        joint_mesh: Handle<Mesh>,
+        joint_material: Handle<ColorMaterial>,
+        part_color: Color,
+    ) -> Self {
+        Self {
+            joint_mesh,
+            joint_material,
+            part_color,
+            head_layers: CollisionLayers::new(
+                GameLayer::RivalHead,
+                [
+                    GameLayer::Default,
+                    GameLayer::Boundary,
+                    GameLayer::SnakeHead,
+                    GameLayer::SnakePart,
+                    GameLayer::RivalHead,
+                    GameLayer::RivalPart,
+                    GameLayer::Apple,
+                ],
+            ),
+            part_layers: CollisionLayers::new(
+                GameLayer::RivalPart,
+                [
+                    GameLayer::Default,
+                    GameLayer::SnakeHead,
+                    GameLayer::RivalHead,
+                ],
+            ),
+        }
+    }
+
+    fn joint(&self, snake: Entity, index: usize, position: Vec2) -> impl Bundle {
+        (
+            Mesh2d(self.joint_mesh.clone()),
+            MeshMaterial2d(self.joint_material.clone()),
+            Transform::from_xyz(position.x, position.y, 0.0),
+            Joint(index),
+            PartOf(snake),
+        )
+    }
+
+    fn part(
+        &self,
+        snake: Entity,
+        index: usize,
+        transform: Transform,
+        config: &SnakeConfig,
+    ) -> impl Bundle {
+        (
+            Sprite {
+                color: self.part_color,
+                custom_size: Some(Vec2 {
+                    x: config.part_length,
+                    y: config.part_thickness,
+                }),
+                ..default()
+            },
+            transform,
+            RigidBody::Kinematic,
+            Collider::rectangle(
+                config.part_length - PART_COLLIDER_INSET,
+                config.part_thickness,
+            ),
+            self.part_layers,
+            LimbSegment(index),
+            SnakePart,
+            PartOf(snake),
+        )
+    }
+}
+
+impl Limb {
+    /// Spawns the joint and segment entities of `snake`, which owns this limb. The head
+    /// segment gets `snake_bundle` on top.
+    pub fn display<S: Bundle>(
+        &self,
+        snake: Entity,
+        commands: &mut Commands,
+        style: &SnakeStyle,
+        snake_bundle: S,
+        config: &SnakeConfig,
+    ) {
+        let first_position = self.segments()[0].position();
+        let second_last_index = config.head_index();
+
+        commands.spawn(style.joint(snake, 0, first_position));
+
+        let start_point = self.segments()[second_last_index].position();
+        let end_point = self.segments()[second_last_index + 1].position();
+        let midpoint = (start_point + end_point) / 2.0;
+
+        commands.spawn((
+            Transform {
+                translation: midpoint.extend(0.0),
+                ..default()
+            },
+            LimbSegment(second_last_index),
+            HeadOfSnake,
+            PartOf(snake),
+            RigidBody::Kinematic,
+            Collider::rectangle(config.head_length, config.head_thickness),
+            style.head_layers,
+            CollisionEventsEnabled,
+            snake_bundle,
+        ));
+
+        for i in 0..self.segments().len() - 1 {
+            let start_point = self.segments()[i].position();
+            let end_point = self.segments()[i + 1].position();
+            let midpoint = (start_point + end_point) / 2.0;
+            if i != second_last_index {
+                let transform = Transform {
+                    translation: midpoint.extend(0.0),
+                    ..default()
+                };
+                commands.spawn(style.part(snake, i, transform, config));
+            }
+
+            commands.spawn(style.joint(snake, i + 1, end_point));
+        }
+    }
+    /// Moves the joint and segment entities in `parts` onto the limb. In a wrap-around
+    /// `arena`, parts outside it are drawn where they come back in, each segment next to
+    /// its head end.
+    pub fn update_visuals(
+        &self,
+        arena: Option<&Arena>,
+        parts: &SnakeParts,
+        joint_query: &mut Query<(&mut Transform, &Joint), JointFilter>,
+        limb_query: &mut Query<(&mut Transform, &LimbSegment), LimbFilter>,
+    ) {
+        let wrap_offset = |point: Vec2| arena.map_or(Vec2::ZERO, |arena| arena.wrap(point) - point);
+
+        let mut joints = joint_query.iter_many_mut(parts.iter());
+        while let Some((mut transform, joint)) = joints.fetch_next() {
+            if let Some(segment) = self.segments().get(joint.0) {
+                let position = segment.position();
+                transform.translation = (position + wrap_offset(position)).extend(0.0);
+            }
+        }
+
+        let mut limb_segments = limb_query.iter_many_mut(parts.iter());
+        while let Some((mut transform, limb_segment)) = limb_segments.fetch_next() {
+            let i = limb_segment.0;
+            let offset = wrap_offset(self.segments()[i + 1].position());
+            let start_point = self.segments()[i].position() + offset;
+            let end_point = self.segments()[i + 1].position() + offset;
+
+            let direction = start_point - end_point;
+
+            let angle = direction.y.atan2(direction.x);
+            let midpoint = (start_point + end_point) / 2.0;
+
+            transform.translation = midpoint.extend(0.0);
+            transform.rotation = Quat::from_rotation_z(angle);
+        }
+    }
+
+    /// Adds `no_of_parts` parts at the tail of `snake`, shifting the indices of its
+    /// existing `parts` up to make room.
+    pub fn add_multiple_snake_parts(
+        &mut self,
+        snake: Entity,
+        no_of_parts: usize,
+        commands: &mut Commands,
+        style: &SnakeStyle,
+        parts: &SnakeParts,
+        joint_query: &mut Query<(Entity, &mut Joint)>,
+        limb_query: &mut Query<(Entity, &mut LimbSegment)>,
+        config: &SnakeConfig,
+    ) {
+        if no_of_parts == 0 {
+            return;
+        }
+        let mut joints = joint_query.iter_many_mut(parts.iter());
+        while let Some((_, mut joint)) = joints.fetch_next() {
+            joint.0 += no_of_parts;
+        }
+        let mut limb_segments = limb_query.iter_many_mut(parts.iter());
+        while let Some((_, mut limb_segment)) = limb_segments.fetch_next() {
+            limb_segment.0 += no_of_parts;
+        }
+
+        for _ in 0..no_of_parts {
+            self.add_snake_part(config.part_length);
+        }
+        let first_position = self.segments()[0].position();
+        commands.spawn(style.joint(snake, 0, first_position));
+
+        for i in 0..no_of_parts {
+            let start_point = self.segments()[i].position();
+            let end_point = self.segments()[i + 1].position();
+
+            let direction = start_point - end_point;
+            let angle = direction.y.atan2(direction.x);
+            let midpoint = (start_point + end_point) / 2.0;
+
+            let transform = Transform {
+                translation: midpoint.extend(0.0),
+                rotation: Quat::from_rotation_z(angle),
+                ..default()
+            };
+            commands.spawn(style.part(snake, i, transform, config));
+
+            if i < no_of_parts - 1 {
+                commands.spawn(style.joint(snake, i + 1, end_point));
+            }
+        }
+    }
+    /// Takes up to `no_of_parts` parts off the tail, despawning their joint and segment
+    /// entities among `parts` and shifting the indices of the rest down to match. The head
+    /// segment is never removed. Returns how many parts came off.
+    pub fn remove_snake_parts(
+        &mut self,
+        no_of_parts: usize,
+        commands: &mut Commands,
+        parts: &SnakeParts,
+        joint_query: &mut Query<(Entity, &mut Joint)>,
+        limb_query: &mut Query<(Entity, &mut LimbSegment)>,
+    ) -> usize {
+        let no_of_parts = no_of_parts.min(self.segments().len().saturating_sub(2));
+        if no_of_parts == 0 {
+            return 0;
+        }
+        for _ in 0..no_of_parts {
+            self.remove_snake_part();
+        }
+
+        let mut joints = joint_query.iter_many_mut(parts.iter());
+        while let Some((entity, mut joint)) = joints.fetch_next() {
+            if joint.0 < no_of_parts {
+                commands.entity(entity).despawn();
+            } else {
+                joint.0 -= no_of_parts;
+            }
+        }
+        let mut limb_segments = limb_query.iter_many_mut(parts.iter());
+        while let Some((entity, mut limb_segment)) = limb_segments.fetch_next() {
+            if limb_segment.0 < no_of_parts {
+                commands.entity(entity).despawn();
+            } else {
+                limb_segment.0 -= no_of_parts;
+            }
+        }
+        no_of_parts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    fn remove_parts(world: &mut World, snake: Entity, limb: Limb, no_of_parts: usize) -> usize {
+        let mut limb = Some(limb);
+        world
+            .run_system_once(
+                move |mut commands: Commands,
+                      snakes: Query<&SnakeParts>,
+                      mut joint_query: Query<(Entity, &mut Joint)>,
+                      mut limb_query: Query<(Entity, &mut LimbSegment)>| {
+                    let mut limb = limb.take().unwrap();
+                    limb.remove_snake_parts(
+                        no_of_parts,
+                        &mut commands,
+                        snakes.get(snake).unwrap(),
+                        &mut joint_query,
+                        &mut limb_query,
+                    )
+                },
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn removing_parts_despawns_the_tail_and_reindexes_the_rest() {
+        let mut world = World::new();
+        let snake = world.spawn_empty().id();
+        let other_snake = world.spawn_empty().id();
+        for snake in [snake, other_snake] {
+            world.spawn_batch((0..5).map(move |i| (Joint(i), PartOf(snake))));
+            world.spawn_batch((0..4).map(move |i| (LimbSegment(i), PartOf(snake))));
+        }
+
+        let limb = Limb::new(Vec2::ZERO, Vec2::ZERO, &[10.0; 5]);
+        assert_eq!(remove_parts(&mut world, snake, limb, 2), 2);
+
+        let indices = |world: &mut World, snake: Entity| {
+            let mut joints: Vec<usize> = world
+                .query::<(&Joint, &PartOf)>()
+                .iter(world)
+                .filter(|(_, part_of)| part_of.0 == snake)
+                .map(|(joint, _)| joint.0)
+                .collect();
+            joints.sort_unstable();
+            let mut limb_segments: Vec<usize> = world
+                .query::<(&LimbSegment, &PartOf)>()
+                .iter(world)
+                .filter(|(_, part_of)| part_of.0 == snake)
+                .map(|(limb_segment, _)| limb_segment.0)
+                .collect();
+            limb_segments.sort_unstable();
+            (joints, limb_segments)
+        };
+        assert_eq!(indices(&mut world, snake), (vec![0, 1, 2], vec![0, 1]));
+        assert_eq!(
+            indices(&mut world, other_snake),
+            (vec![0, 1, 2, 3, 4], vec![0, 1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn the_head_is_never_removed() {
+        let mut world = World::new();
+        let snake = world.spawn(SnakeParts::default()).id();
+        let limb = Limb::new(Vec2::ZERO, Vec2::ZERO, &[10.0; 4]);
+        assert_eq!(remove_parts(&mut world, snake, limb, 10), 2);
+    }
+
+    #[test]
+    fn despawning_a_snake_despawns_its_parts() {
+        let mut world = World::new();
+        let snake = world.spawn_empty().id();
+        world.spawn_batch((0..3).map(move |i| (Joint(i), PartOf(snake))));
+        assert_eq!(
+            world.get::<SnakeParts>(snake).map(|parts| parts.len()),
+            Some(3)
+        );
+
+        world.despawn(snake);
+        assert_eq!(world.query::<&Joint>().iter(&world).count(), 0);
+    }
+}
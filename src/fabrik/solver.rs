@@ -0,0 +1,624 @@
+use std::collections::VecDeque;
+
+use glam::Vec2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    position: Vec2,
+    length: f32,
+    max_bend: Option<f32>,
+}
+
+impl Segment {
+    pub fn new(position: Vec2, length: f32) -> Self {
+        Self {
+            position,
+            length,
+            max_bend: None,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.length
+    }
+
+    pub fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: Vec2) {
+        self.position = position;
+    }
+
+    pub fn set_length(&mut self, length: f32) {
+        self.length = length;
+    }
+
+    /// Largest angle in radians this joint may bend away from a straight line.
+    /// Overrides the limb-wide limit when set.
+    pub fn max_bend(&self) -> Option<f32> {
+        self.max_bend
+    }
+
+    pub fn set_max_bend(&mut self, max_bend: Option<f32>) {
+        self.max_bend = max_bend;
+    }
+}
+
+/// Rotates `direction` so it is at most `max_bend` radians away from `reference`,
+/// keeping its length and the side it bends towards.
+fn constrain_direction(reference: Vec2, direction: Vec2, max_bend: f32) -> Vec2 {
+    if reference == Vec2::ZERO || direction == Vec2::ZERO {
+        return direction;
+    }
+    let angle = reference.angle_to(direction);
+    if angle.abs() <= max_bend {
+        return direction;
+    }
+    Vec2::from_angle(max_bend.copysign(angle)).rotate(reference.normalize()) * direction.length()
+}
+
+/// The point `distance` along the polyline `path`, clamped to its last point.
+fn point_along(path: &[Vec2], mut distance: f32) -> Vec2 {
+    for pair in path.windows(2) {
+        let step = pair[0].distance(pair[1]);
+        if distance <= step {
+            return pair[0].lerp(pair[1], if step > 0.0 { distance / step } else { 0.0 });
+        }
+        distance -= step;
+    }
+    path.last().copied().unwrap_or_default()
+}
+
+const DEFAULT_TOLERANCE: f32 = 0.01;
+const DEFAULT_MAX_ITERATIONS: usize = 10;
+
+/// How a `Limb` is solved towards its target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LimbMode {
+    /// The end of the limb is dragged to the target and the rest trails behind (the snake).
+    Follow,
+    /// `segments[0]` is pinned to `anchor` and the limb reaches for the target (tentacles, ropes).
+    Anchored { anchor: Vec2 },
+}
+
+/// Result of a full FABRIK solve.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FabrikOutcome {
+    pub reachable: bool,
+    pub iterations: usize,
+    pub distance_to_target: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::component::Component))]
+pub struct Limb {
+    segments: VecDeque<Segment>,
+    target: Vec2,
+    mode: LimbMode,
+    tolerance: f32,
+    max_iterations: usize,
+    max_bend: Option<f32>,
+}
+
+impl Limb {
+    /// Lays the segments out in a line from `starting_position` towards -x,
+    /// `lengths[i]` being the distance from segment `i` to segment `i + 1`.
+    pub fn new(target: Vec2, starting_position: Vec2, lengths: &[f32]) -> Self {
+        let segments = Limb::lay_out(starting_position, lengths);
+
+        Self {
+            segments,
+            target,
+            mode: LimbMode::Follow,
+            tolerance: DEFAULT_TOLERANCE,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_bend: None,
+        }
+    }
+
+    fn lay_out(starting_position: Vec2, lengths: &[f32]) -> VecDeque<Segment> {
+        let mut segments = VecDeque::<Segment>::new();
+        let mut sum = 0.0;
+
+        for &length in lengths {
+            segments.push_back(Segment::new(
+                Vec2 {
+                    x: starting_position.x + sum,
+                    y: starting_position.y,
+                },
+                length,
+            ));
+            sum -= length;
+        }
+
+        segments
+    }
+
+    pub fn segments(&self) -> &VecDeque<Segment> {
+        &self.segments
+    }
+
+    pub fn mode(&self) -> LimbMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: LimbMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_tolerance(&mut self, tolerance: f32) {
+        self.tolerance = tolerance;
+    }
+
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations;
+    }
+
+    pub fn max_bend(&self) -> Option<f32> {
+        self.max_bend
+    }
+
+    /// Limits how far every joint may bend, unless the joint's `Segment` has its own limit.
+    pub fn set_max_bend(&mut self, max_bend: Option<f32>) {
+        self.max_bend = max_bend;
+    }
+
+    pub fn set_joint_max_bend(&mut self, index: usize, max_bend: Option<f32>) {
+        if let Some(segment) = self.segments.get_mut(index) {
+            segment.set_max_bend(max_bend);
+        }
+    }
+
+    fn joint_max_bend(&self, index: usize) -> Option<f32> {
+        self.segments[index].max_bend().or(self.max_bend)
+    }
+
+    pub fn forward_fabrik(&mut self) {
+        let len = self.segments.len();
+        if len == 0 {
+            return;
+        }
+
+        // Set the last segment to target
+        self.segments[len - 1].set_position(self.target);
+
+        // Work backwards from second-to-last to first
+        for i in (0..len - 1).rev() {
+            let next_pos = self.segments[i + 1].position;
+            let current_pos = self.segments[i].position;
+            let current_length = self.segments[i].length();
+
+            let mut direction = next_pos - current_pos;
+            direction = direction.normalize_or_zero() * current_length;
+
+            // Keep the bend at the next joint within its limit
+            if i + 2 < len
+                && let Some(max_bend) = self.joint_max_bend(i + 1)
+            {
+                let reference = self.segments[i + 2].position - next_pos;
+                direction = constrain_direction(reference, direction, max_bend);
+            }
+
+            self.segments[i].set_position(next_pos - direction);
+        }
+    }
+
+    pub fn backward_fabrik(&mut self, anchor: Vec2) {
+        let len = self.segments.len();
+        if len == 0 {
+            return;
+        }
+
+        // Pin the first segment to the anchor
+        self.segments[0].set_position(anchor);
+
+        // Work forwards from the second segment to the last
+        for i in 1..len {
+            let previous_pos = self.segments[i - 1].position;
+            let current_pos = self.segments[i].position;
+            let previous_length = self.segments[i - 1].length();
+
+            let mut direction = current_pos - previous_pos;
+            direction = direction.normalize_or_zero() * previous_length;
+
+            // Keep the bend at the previous joint within its limit
+            if i >= 2
+                && let Some(max_bend) = self.joint_max_bend(i - 1)
+            {
+                let reference = previous_pos - self.segments[i - 2].position;
+                direction = constrain_direction(reference, direction, max_bend);
+            }
+
+            self.segments[i].set_position(previous_pos + direction);
+        }
+    }
+
+    /// Total length of the limb from `segments[0]` to the last joint.
+    pub fn reach(&self) -> f32 {
+        let len = self.segments.len();
+        self.segments
+            .iter()
+            .take(len.saturating_sub(1))
+            .map(Segment::length)
+            .sum()
+    }
+
+    pub fn solve(&mut self) -> FabrikOutcome {
+        if self.segments.is_empty() {
+            return FabrikOutcome {
+                reachable: false,
+                iterations: 0,
+                distance_to_target: f32::INFINITY,
+            };
+        }
+
+        let anchor = match self.mode {
+            LimbMode::Follow => {
+                self.forward_fabrik();
+                return FabrikOutcome {
+                    reachable: true,
+                    iterations: 1,
+                    distance_to_target: 0.0,
+                };
+            }
+            LimbMode::Anchored { anchor } => anchor,
+        };
+
+        if anchor.distance(self.target) > self.reach() {
+            // Out of reach: stretch the limb straight out towards the target
+            let direction = (self.target - anchor).normalize_or_zero();
+            let mut position = anchor;
+            for segment in self.segments.iter_mut() {
+                segment.set_position(position);
+                position += direction * segment.length();
+            }
+            return FabrikOutcome {
+                reachable: false,
+                iterations: 0,
+                distance_to_target: self.get_last_segment_position().distance(self.target),
+            };
+        }
+
+        let mut iterations = 0;
+        let mut distance_to_target = self.get_last_segment_position().distance(self.target);
+        while distance_to_target > self.tolerance && iterations < self.max_iterations {
+            self.forward_fabrik();
+            self.backward_fabrik(anchor);
+            iterations += 1;
+            distance_to_target = self.get_last_segment_position().distance(self.target);
+        }
+
+        FabrikOutcome {
+            reachable: distance_to_target <= self.tolerance,
+            iterations,
+            distance_to_target,
+        }
+    }
+
+    pub fn get_last_segment_position(&self) -> Vec2 {
+        let last_index = self.segments.len() - 1;
+        self.segments[last_index].position
+    }
+
+    /// Direction the head end of the limb points in.
+    pub fn heading(&self) -> Vec2 {
+        let last_index = self.segments.len() - 1;
+        (self.segments[last_index].position - self.segments[last_index - 1].position)
+            .normalize_or_zero()
+    }
+
+    pub fn set_target(&mut self, target: Vec2) {
+        self.target = target;
+    }
+
+    /// Moves the whole limb, and its target, by `offset`.
+    pub fn translate(&mut self, offset: Vec2) {
+        for segment in self.segments.iter_mut() {
+            segment.position += offset;
+        }
+        self.target += offset;
+    }
+
+    /// Places the joints along `path`, which starts at the head end, each one its segment's
+    /// length further along the path than the joint in front of it. Joints that would be
+    /// past the end of the path sit on its last point.
+    pub fn lay_along(&mut self, path: &[Vec2]) {
+        let Some(&head) = path.first() else {
+            return;
+        };
+        let mut distance = 0.0;
+        for index in (0..self.segments.len()).rev() {
+            if index + 1 < self.segments.len() {
+                distance += self.segments[index].length();
+            }
+            self.segments[index].set_position(point_along(path, distance));
+        }
+        self.target = head;
+    }
+
+    pub fn add_snake_part(&mut self, length: f32) {
+        let start_point = self.segments[0].position;
+        let end_point = self.segments[1].position;
+        let direction = (start_point - end_point).normalize_or_zero() * length;
+        let new_point = start_point + direction;
+        self.segments.push_front(Segment::new(new_point, length));
+    }
+
+    /// Takes the tail-most segment off the limb.
+    pub fn remove_snake_part(&mut self) {
+        self.segments.pop_front();
+    }
+
+    pub fn reset_limb(&mut self, starting_position: Vec2, lengths: &[f32]) {
+        self.segments = Limb::lay_out(starting_position, lengths);
+        self.target = self.get_last_segment_position();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-3;
+    const NO_OF_SEGMENTS: usize = 10;
+    const PART_LENGTH: f32 = 20.0;
+    const HEAD_LENGTH: f32 = 40.0;
+
+    fn snake_lengths() -> Vec<f32> {
+        let mut lengths = vec![PART_LENGTH; NO_OF_SEGMENTS];
+        lengths[NO_OF_SEGMENTS - 2] = HEAD_LENGTH;
+        lengths
+    }
+
+    fn straight_limb() -> Limb {
+        Limb::new(Vec2::ZERO, Vec2::ZERO, &snake_lengths())
+    }
+
+    fn bends(limb: &Limb) -> impl Iterator<Item = f32> + '_ {
+        let segments = limb.segments();
+        (0..segments.len() - 2).map(move |i| {
+            let first = segments[i + 1].position() - segments[i].position();
+            let second = segments[i + 2].position() - segments[i + 1].position();
+            first.angle_to(second).abs()
+        })
+    }
+
+    fn assert_bends_within(limb: &Limb, max_bend: f32) {
+        for (i, bend) in bends(limb).enumerate() {
+            assert!(bend <= max_bend + EPSILON, "joint {i} bends by {bend}");
+        }
+    }
+
+    fn assert_lengths_preserved(limb: &Limb) {
+        let segments = limb.segments();
+        for i in 0..segments.len() - 1 {
+            let distance = segments[i].position().distance(segments[i + 1].position());
+            assert!(
+                (distance - segments[i].length()).abs() < EPSILON,
+                "segment {i} has length {distance}, expected {}",
+                segments[i].length()
+            );
+        }
+    }
+
+    #[test]
+    fn new_lays_segments_out_in_a_line() {
+        let limb = straight_limb();
+        let segments = limb.segments();
+        assert_eq!(segments.len(), NO_OF_SEGMENTS);
+        assert_eq!(segments[NO_OF_SEGMENTS - 2].length(), HEAD_LENGTH);
+        assert_eq!(segments[0].length(), PART_LENGTH);
+        assert!(segments.iter().all(|segment| segment.position().y == 0.0));
+        assert_lengths_preserved(&limb);
+        assert_eq!(limb.heading(), Vec2::NEG_X);
+    }
+
+    #[test]
+    fn lay_along_follows_the_path_around_corners() {
+        let mut limb = straight_limb();
+        let path = [Vec2::new(0.0, 40.0), Vec2::ZERO, Vec2::new(200.0, 0.0)];
+        limb.lay_along(&path);
+        let segments = limb.segments();
+        assert_eq!(limb.get_last_segment_position(), path[0]);
+        // The head segment is exactly as long as the first leg of the path.
+        assert!(segments[NO_OF_SEGMENTS - 2].position().distance(Vec2::ZERO) < EPSILON);
+        assert!(segments[0].position().distance(Vec2::new(160.0, 0.0)) < EPSILON);
+        assert_eq!(limb.heading(), Vec2::Y);
+
+        limb.lay_along(&path[..2]);
+        assert_eq!(limb.segments()[0].position(), Vec2::ZERO);
+    }
+
+    #[test]
+    fn follow_mode_moves_the_end_onto_the_target() {
+        let mut limb = straight_limb();
+        let target = Vec2::new(30.0, 40.0);
+        limb.set_target(target);
+        let outcome = limb.solve();
+        assert!(outcome.reachable);
+        assert_eq!(limb.get_last_segment_position(), target);
+        assert_lengths_preserved(&limb);
+    }
+
+    #[test]
+    fn anchored_mode_keeps_the_root_pinned() {
+        let mut limb = straight_limb();
+        let anchor = limb.segments()[0].position();
+        limb.set_mode(LimbMode::Anchored { anchor });
+        limb.set_target(anchor + Vec2::new(50.0, 60.0));
+        let outcome = limb.solve();
+        assert!(outcome.reachable);
+        assert!(outcome.distance_to_target <= DEFAULT_TOLERANCE);
+        assert_eq!(limb.segments()[0].position(), anchor);
+        assert_lengths_preserved(&limb);
+    }
+
+    #[test]
+    fn anchored_mode_reports_unreachable_targets() {
+        let mut limb = straight_limb();
+        let anchor = Vec2::ZERO;
+        limb.set_mode(LimbMode::Anchored { anchor });
+        limb.set_target(Vec2::new(0.0, limb.reach() + 100.0));
+        let outcome = limb.solve();
+        assert!(!outcome.reachable);
+        assert_eq!(limb.segments()[0].position(), anchor);
+        assert!(
+            (limb.get_last_segment_position() - Vec2::new(0.0, limb.reach())).length() < EPSILON
+        );
+        assert_lengths_preserved(&limb);
+    }
+
+    #[test]
+    fn anchored_mode_iterates_until_within_tolerance() {
+        let solve = |tolerance, max_iterations| {
+            let mut limb = straight_limb();
+            limb.set_mode(LimbMode::Anchored { anchor: Vec2::ZERO });
+            limb.set_tolerance(tolerance);
+            limb.set_max_iterations(max_iterations);
+            limb.set_target(Vec2::new(50.0, 60.0));
+            limb.solve()
+        };
+
+        let already_close = solve(1_000.0, 10);
+        assert!(already_close.reachable);
+        assert_eq!(already_close.iterations, 0);
+
+        let one_pass = solve(EPSILON, 1);
+        assert!(!one_pass.reachable);
+        assert_eq!(one_pass.iterations, 1);
+
+        let converged = solve(EPSILON, 50);
+        assert!(converged.reachable);
+        assert!(converged.iterations > 1);
+        assert!(converged.distance_to_target <= EPSILON);
+    }
+
+    #[test]
+    fn a_limb_can_switch_from_follow_to_anchored_mode() {
+        let mut limb = straight_limb();
+        limb.set_target(Vec2::new(300.0, 0.0));
+        limb.solve();
+        let anchor = limb.segments()[0].position();
+        assert_ne!(anchor, Vec2::ZERO);
+
+        limb.set_mode(LimbMode::Anchored { anchor });
+        assert_eq!(limb.mode(), LimbMode::Anchored { anchor });
+        limb.set_target(anchor + Vec2::new(0.0, 120.0));
+        assert!(limb.solve().reachable);
+        assert_eq!(limb.segments()[0].position(), anchor);
+        assert_lengths_preserved(&limb);
+    }
+
+    #[test]
+    fn max_bend_limits_the_angle_between_neighbouring_segments() {
+        let max_bend = std::f32::consts::FRAC_PI_6;
+        let mut limb = straight_limb();
+        limb.set_max_bend(Some(max_bend));
+        let head = limb.get_last_segment_position();
+        // Reverse straight back into the neck
+        for step in 1..=20 {
+            limb.set_target(head - Vec2::new(step as f32 * 5.0, 0.0));
+            limb.solve();
+        }
+        assert_bends_within(&limb, max_bend);
+        assert_lengths_preserved(&limb);
+    }
+
+    #[test]
+    fn max_bend_holds_in_anchored_mode() {
+        let max_bend = std::f32::consts::FRAC_PI_6;
+        let mut limb = straight_limb();
+        limb.set_max_bend(Some(max_bend));
+        limb.set_mode(LimbMode::Anchored { anchor: Vec2::ZERO });
+        // Folding back over the anchor needs far more than 30 degrees per joint
+        limb.set_target(Vec2::new(-40.0, 60.0));
+        limb.solve();
+        assert_eq!(limb.segments()[0].position(), Vec2::ZERO);
+        assert_bends_within(&limb, max_bend);
+        assert_lengths_preserved(&limb);
+    }
+
+    #[test]
+    fn without_max_bend_the_limb_can_fold_back() {
+        let mut limb = straight_limb();
+        limb.set_mode(LimbMode::Anchored { anchor: Vec2::ZERO });
+        limb.set_target(Vec2::new(-40.0, 60.0));
+        assert!(limb.solve().reachable);
+        assert!(bends(&limb).any(|bend| bend > std::f32::consts::FRAC_PI_6));
+    }
+
+    #[test]
+    fn segment_max_bend_overrides_the_limb_limit() {
+        let mut limb = straight_limb();
+        limb.set_max_bend(Some(std::f32::consts::FRAC_PI_6));
+        limb.set_joint_max_bend(3, Some(0.0));
+        assert_eq!(limb.joint_max_bend(3), Some(0.0));
+        assert_eq!(limb.joint_max_bend(4), Some(std::f32::consts::FRAC_PI_6));
+    }
+
+    #[test]
+    fn segment_max_bend_constrains_the_solved_limb() {
+        let curl = |limb: &mut Limb| {
+            let head = limb.get_last_segment_position();
+            // Swing the head round in a tight circle so every joint has to bend
+            for step in 1..=40 {
+                let angle = step as f32 * 0.15;
+                limb.set_target(head + Vec2::new(angle.sin(), 1.0 - angle.cos()) * 60.0);
+                limb.solve();
+            }
+        };
+        let max_bend = std::f32::consts::FRAC_PI_6;
+        let mut free = straight_limb();
+        free.set_max_bend(Some(max_bend));
+        curl(&mut free);
+        let mut stiff = straight_limb();
+        stiff.set_max_bend(Some(max_bend));
+        stiff.set_joint_max_bend(3, Some(0.0));
+        curl(&mut stiff);
+
+        // `bends` starts at joint 1
+        let joint_3_bend = |limb: &Limb| bends(limb).nth(2).unwrap();
+        assert!(joint_3_bend(&free) > 0.1, "joint 3 never had to bend");
+        assert!(joint_3_bend(&stiff) < EPSILON);
+        assert_bends_within(&stiff, max_bend);
+        assert_lengths_preserved(&stiff);
+    }
+
+    #[test]
+    fn add_snake_part_extends_the_tail() {
+        let mut limb = straight_limb();
+        let tail = limb.segments()[0].position();
+        limb.add_snake_part(PART_LENGTH);
+        assert_eq!(limb.segments().len(), NO_OF_SEGMENTS + 1);
+        assert_eq!(limb.segments()[1].position(), tail);
+        assert!(limb.segments()[0].position().x > tail.x);
+        assert_lengths_preserved(&limb);
+    }
+
+    #[test]
+    fn remove_snake_part_takes_off_the_tail() {
+        let mut limb = straight_limb();
+        let head = limb.get_last_segment_position();
+        let second = limb.segments()[1].position();
+        limb.remove_snake_part();
+        assert_eq!(limb.segments().len(), NO_OF_SEGMENTS - 1);
+        assert_eq!(limb.segments()[0].position(), second);
+        assert_eq!(limb.get_last_segment_position(), head);
+    }
+
+    #[test]
+    fn reset_limb_restores_the_initial_layout() {
+        let mut limb = straight_limb();
+        limb.add_snake_part(PART_LENGTH);
+        limb.set_target(Vec2::new(-100.0, 80.0));
+        limb.solve();
+        limb.reset_limb(Vec2::ZERO, &snake_lengths());
+        let fresh = straight_limb();
+        assert_eq!(limb.segments().len(), fresh.segments().len());
+        for (reset, fresh) in limb.segments().iter().zip(fresh.segments()) {
+            assert_eq!(reset.position(), fresh.position());
+            assert_eq!(reset.length(), fresh.length());
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Grid mode: the head hops from cell centre to cell centre and the body follows the
+//! cells it went through.
+
+use std::collections::VecDeque;
+
+use glam::Vec2;
+
+use crate::{fabrik::Limb, replay::Direction};
+
+/// Snaps `position` to the centre of the cell it is in. Cells are centred on multiples
+/// of `cell_size`, so the arena's centre is always a cell centre.
+pub fn snap(position: Vec2, cell_size: f32) -> Vec2 {
+    (position / cell_size).round() * cell_size
+}
+
+/// Whether the head hops a cell on simulation tick `tick`, moving `moves_per_second` cells
+/// a second with ticks `tick_seconds` apart.
+pub fn step_due(tick: u32, moves_per_second: f32, tick_seconds: f32) -> bool {
+    let moves = |tick: u32| {
+        (f64::from(tick) * f64::from(tick_seconds) * f64::from(moves_per_second)).floor()
+    };
+    moves(tick + 1) > moves(tick)
+}
+
+/// Cells the head went through, newest first, as far back as the body reaches.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::component::Component))]
+pub struct GridTrail {
+    cell_size: f32,
+    cells: VecDeque<Vec2>,
+}
+
+impl GridTrail {
+    /// Snaps `limb` onto the grid, stretched out straight behind its head.
+    pub fn new(cell_size: f32, limb: &mut Limb) -> Self {
+        let head = snap(limb.get_last_segment_position(), cell_size);
+        let behind = Direction::nearest(-limb.heading()).map_or(Vec2::X, Direction::as_vec2);
+        let mut trail = Self {
+            cell_size,
+            cells: (0..cells_to_cover(limb, cell_size))
+                .map(|cell| head + behind * cell_size * cell as f32)
+                .collect(),
+        };
+        limb.lay_along(trail.cells.make_contiguous());
+        trail
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn head(&self) -> Vec2 {
+        self.cells[0]
+    }
+
+    /// Moves every cell by `offset`, for when `limb` was moved by it too.
+    pub fn translate(&mut self, offset: Vec2) {
+        for cell in self.cells.iter_mut() {
+            *cell += offset;
+        }
+    }
+
+    /// Moves the head into `cell` and pulls the rest of `limb` along after it.
+    pub fn advance(&mut self, cell: Vec2, limb: &mut Limb) {
+        self.cells.push_front(cell);
+        self.cells.truncate(cells_to_cover(limb, self.cell_size));
+        limb.lay_along(self.cells.make_contiguous());
+    }
+}
+
+fn cells_to_cover(limb: &Limb, cell_size: f32) -> usize {
+    (limb.reach() / cell_size).ceil() as usize + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limb() -> Limb {
+        Limb::new(Vec2::ZERO, Vec2::new(103.0, 2.0), &[20.0, 20.0, 40.0, 20.0])
+    }
+
+    #[test]
+    fn snaps_to_cell_centres() {
+        assert_eq!(snap(Vec2::new(29.0, -31.0), 20.0), Vec2::new(20.0, -40.0));
+    }
+
+    #[test]
+    fn steps_at_the_configured_rate() {
+        let steps = (0..64)
+            .filter(|tick| step_due(*tick, 10.0, 1.0 / 64.0))
+            .count();
+        assert_eq!(steps, 10);
+    }
+
+    #[test]
+    fn body_follows_the_head_through_each_cell() {
+        let mut limb = limb();
+        let mut trail = GridTrail::new(20.0, &mut limb);
+        let positions: Vec<Vec2> = limb.segments().iter().map(|s| s.position()).collect();
+        assert_eq!(
+            positions,
+            [(100.0, 0.0), (80.0, 0.0), (60.0, 0.0), (20.0, 0.0)].map(Vec2::from)
+        );
+
+        trail.advance(trail.head() + Vec2::new(0.0, 20.0), &mut limb);
+        let positions: Vec<Vec2> = limb.segments().iter().map(|s| s.position()).collect();
+        assert_eq!(
+            positions,
+            [(80.0, 0.0), (60.0, 0.0), (40.0, 0.0), (20.0, 20.0)].map(Vec2::from)
+        );
+    }
+}
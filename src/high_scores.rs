@@ -0,0 +1,239 @@
+//! High scores: recording a finished game's score, typing initials for a new entry
+//! and the panel that lists the best scores.
+
+use bevy::{
+    input::keyboard::{Key, KeyboardInput},
+    prelude::*,
+};
+use snake::{
+    fabrik::Limb,
+    leaderboard::{Leaderboard, ScoreEntry, format_date},
+    mode::{GameMode, Players, Walls},
+    rival::Rivals,
+};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    GameState, SCORE_COLOR, TEXT_COLOR,
+    playback::Recording,
+    players::{Player, Score},
+};
+
+pub struct HighScoresPlugin;
+
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Leaderboard::load())
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                record_score.run_if(resource_exists_and_changed::<Recording>),
+            )
+            .add_systems(OnExit(GameState::GameOver), submit_pending_score)
+            .add_systems(
+                Update,
+                (
+                    type_initials.run_if(resource_exists::<PendingScore>),
+                    update_leaderboard_panel,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::GameOver)),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct LeaderboardPanel;
+
+/// A score that made the leaderboard and is waiting for the player's initials.
+#[derive(Resource, Deref, DerefMut)]
+pub struct PendingScore(ScoreEntry);
+
+const INITIALS_LENGTH: usize = 3;
+
+const LEADERBOARD_FONT_SIZE: f32 = 20.0;
+
+const LEADERBOARD_COLUMN_WIDTHS: [f32; 6] = [40.0, 90.0, 80.0, 80.0, 100.0, 130.0];
+
+/// Offers the player's score to the leaderboard. Versus games are not ranked.
+fn record_score(
+    mut commands: Commands,
+    players: Query<(&Player, &Score, &Limb)>,
+    leaderboard: Res<Leaderboard>,
+    mode: Res<GameMode>,
+    walls: Res<Walls>,
+    rivals: Res<Rivals>,
+    player_count: Res<Players>,
+) {
+    if *player_count == Players::Versus {
+        return;
+    }
+    let Some((_, player_score, limb)) = players.iter().find(|(player, ..)| player.0 == 0) else {
+        return;
+    };
+    if !leaderboard.qualifies(**player_score) {
+        return;
+    }
+    let unix_seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_secs());
+    let mut mode = match *walls {
+        Walls::Solid => mode.label().to_string(),
+        Walls::Wrap => format!("{}+Wrap", mode.label()),
+    };
+    if rivals.0 > 0 {
+        mode = format!("{mode} vs {}", rivals.0);
+    }
+    commands.insert_resource(PendingScore(ScoreEntry {
+        initials: String::new(),
+        score: **player_score,
+        length: limb.segments().len(),
+        mode,
+        date: format_date(unix_seconds),
+    }));
+}
+
+fn submit_score(leaderboard: &mut Leaderboard, mut entry: ScoreEntry) {
+    if entry.initials.is_empty() {
+        entry.initials = String::from("???");
+    }
+    leaderboard.insert(entry);
+    if let Err(error) = leaderboard.save() {
+        warn!("Could not save the leaderboard: {error}");
+    }
+}
+
+/// Keeps a new high score even if the player leaves the game-over screen without
+/// confirming their initials.
+fn submit_pending_score(
+    mut commands: Commands,
+    pending_score: Option<Res<PendingScore>>,
+    mut leaderboard: ResMut<Leaderboard>,
+) {
+    if let Some(pending_score) = pending_score {
+        submit_score(&mut leaderboard, pending_score.0.clone());
+        commands.remove_resource::<PendingScore>();
+    }
+}
+
+fn type_initials(
+    mut commands: Commands,
+    mut keyboard_events: MessageReader<KeyboardInput>,
+    mut pending_score: ResMut<PendingScore>,
+    mut leaderboard: ResMut<Leaderboard>,
+) {
+    // Ignore whatever was pressed while steering the snake into the wall.
+    if pending_score.is_added() {
+        keyboard_events.clear();
+        return;
+    }
+    for event in keyboard_events.read() {
+        if !event.state.is_pressed() || event.repeat {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Character(text) => {
+                for character in text.chars().filter(char::is_ascii_alphanumeric) {
+                    if pending_score.initials.len() < INITIALS_LENGTH {
+                        pending_score.initials.push(character.to_ascii_uppercase());
+                    }
+                }
+            }
+            Key::Backspace => {
+                pending_score.initials.pop();
+            }
+            Key::Enter => {
+                submit_score(&mut leaderboard, pending_score.0.clone());
+                commands.remove_resource::<PendingScore>();
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn leaderboard_text(text: impl Into<String>, color: Color) -> impl Bundle {
+    (
+        Text::new(text),
+        TextFont {
+            font_size: LEADERBOARD_FONT_SIZE,
+            ..default()
+        },
+        TextColor(color),
+    )
+}
+
+fn leaderboard_row(cells: [String; 6], color: Color) -> impl Bundle {
+    (
+        Node::default(),
+        Children::spawn(SpawnIter(
+            cells
+                .into_iter()
+                .zip(LEADERBOARD_COLUMN_WIDTHS)
+                .map(move |(cell, width)| {
+                    (
+                        leaderboard_text(cell, color),
+                        Node {
+                            width: px(width),
+                            ..default()
+                        },
+                    )
+                }),
+        )),
+    )
+}
+
+/// Rebuilds the table whenever a score is added or the initials being typed change.
+fn update_leaderboard_panel(
+    mut commands: Commands,
+    panel: Single<(Entity, Ref<LeaderboardPanel>)>,
+    leaderboard: Res<Leaderboard>,
+    pending_score: Option<Res<PendingScore>>,
+) {
+    let (panel, marker) = panel.into_inner();
+    let pending_changed = pending_score
+        .as_ref()
+        .is_some_and(|pending_score| pending_score.is_changed());
+    if !marker.is_added() && !leaderboard.is_changed() && !pending_changed {
+        return;
+    }
+
+    let header = ["#", "Name", "Score", "Length", "Mode", "Date"].map(String::from);
+    let rows: Vec<[String; 6]> = leaderboard
+        .entries()
+        .iter()
+        .enumerate()
+        .map(|(rank, entry)| {
+            [
+                (rank + 1).to_string(),
+                entry.initials.clone(),
+                entry.score.to_string(),
+                entry.length.to_string(),
+                entry.mode.clone(),
+                entry.date.clone(),
+            ]
+        })
+        .collect();
+
+    commands
+        .entity(panel)
+        .despawn_children()
+        .with_children(|parent| {
+            parent.spawn(leaderboard_text("Leaderboard", TEXT_COLOR));
+            if let Some(pending_score) = &pending_score {
+                parent.spawn(leaderboard_text(
+                    format!(
+                        "New high score! Type your initials: {:_<3} (Enter)",
+                        pending_score.initials
+                    ),
+                    SCORE_COLOR,
+                ));
+            }
+            parent.spawn(leaderboard_row(header, TEXT_COLOR));
+            if rows.is_empty() {
+                parent.spawn(leaderboard_text("No scores yet", Color::WHITE));
+            }
+            for row in rows {
+                parent.spawn(leaderboard_row(row, Color::WHITE));
+            }
+        });
+}
@@ -0,0 +1,142 @@
+//! The local top-10 table, persisted through `storage`.
+
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+pub const LEADERBOARD_SIZE: usize = 10;
+const STORAGE_KEY: &str = "leaderboard";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub initials: String,
+    pub score: usize,
+    /// Number of parts the snake had when the game ended.
+    pub length: usize,
+    pub mode: String,
+    /// `YYYY-MM-DD`, see [`format_date`].
+    pub date: String,
+}
+
+/// Best scores first. Ties keep the entry that got there first above the newer one.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::resource::Resource))]
+pub struct Leaderboard {
+    entries: Vec<ScoreEntry>,
+}
+
+impl Leaderboard {
+    /// Reads the saved leaderboard. A missing or unreadable one starts out empty.
+    pub fn load() -> Self {
+        storage::read(STORAGE_KEY)
+            .and_then(|text| ron::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(io::Error::other)?;
+        storage::write(STORAGE_KEY, &text)
+    }
+
+    pub fn entries(&self) -> &[ScoreEntry] {
+        &self.entries
+    }
+
+    pub fn high_score(&self) -> usize {
+        self.entries.first().map_or(0, |entry| entry.score)
+    }
+
+    /// Whether `score` would make it onto the table.
+    pub fn qualifies(&self, score: usize) -> bool {
+        score > 0
+            && (self.entries.len() < LEADERBOARD_SIZE
+                || self.entries.last().is_some_and(|entry| score > entry.score))
+    }
+
+    /// Adds `entry` and returns its rank, or `None` if it did not make the cut.
+    pub fn insert(&mut self, entry: ScoreEntry) -> Option<usize> {
+        if !self.qualifies(entry.score) {
+            return None;
+        }
+        let rank = self
+            .entries
+            .iter()
+            .position(|existing| entry.score > existing.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(rank, entry);
+        self.entries.truncate(LEADERBOARD_SIZE);
+        Some(rank)
+    }
+}
+
+/// Formats seconds since the Unix epoch as a UTC `YYYY-MM-DD` date.
+pub fn format_date(unix_seconds: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm.
+    let days = (unix_seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(initials: &str, score: usize) -> ScoreEntry {
+        ScoreEntry {
+            initials: initials.to_string(),
+            score,
+            length: 10 + score * 2,
+            mode: String::from("Classic"),
+            date: String::from("2024-01-01"),
+        }
+    }
+
+    #[test]
+    fn keeps_the_best_ten_scores_in_order() {
+        let mut leaderboard = Leaderboard::default();
+        for score in 1..=12 {
+            leaderboard.insert(entry("AAA", score));
+        }
+        let scores: Vec<usize> = leaderboard.entries().iter().map(|e| e.score).collect();
+        assert_eq!(scores, (3..=12).rev().collect::<Vec<_>>());
+        assert_eq!(leaderboard.high_score(), 12);
+        assert!(!leaderboard.qualifies(3));
+        assert_eq!(leaderboard.insert(entry("BBB", 2)), None);
+    }
+
+    #[test]
+    fn ties_rank_below_the_existing_score() {
+        let mut leaderboard = Leaderboard::default();
+        leaderboard.insert(entry("OLD", 5));
+        assert_eq!(leaderboard.insert(entry("NEW", 5)), Some(1));
+        assert_eq!(leaderboard.insert(entry("TOP", 6)), Some(0));
+        assert_eq!(leaderboard.entries()[2].initials, "NEW");
+    }
+
+    #[test]
+    fn zero_never_qualifies() {
+        assert!(!Leaderboard::default().qualifies(0));
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_735_689_599), "2024-12-31");
+    }
+}
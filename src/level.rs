@@ -0,0 +1,500 @@
+//! Arena layouts, loaded from `assets/levels/*.level.ron`.
+
+use std::{f32::consts::TAU, fmt};
+
+use glam::Vec2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Keeps apples from spawning flush against the walls.
+const APPLE_WALL_MARGIN: f32 = 20.0;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(
+    feature = "bevy",
+    derive(
+        bevy::asset::Asset,
+        bevy::reflect::TypePath,
+        bevy::ecs::resource::Resource
+    )
+)]
+pub struct Level {
+    pub name: String,
+    pub arena: Arena,
+    /// Interior walls, in world coordinates.
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+    /// Where the tail of the snake starts; the body is laid out towards -x from here.
+    pub spawn: Vec2,
+    /// Areas apples may spawn in. The whole arena is used when empty.
+    #[serde(default)]
+    pub apple_zones: Vec<Zone>,
+}
+
+/// The open field, for when none of the level files can be used.
+impl Default for Level {
+    fn default() -> Self {
+        Self {
+            name: String::from("Open Field"),
+            arena: Arena {
+                width: 1200.0,
+                height: 600.0,
+                wall_thickness: 20.0,
+            },
+            obstacles: Vec::new(),
+            spawn: Vec2::new(200.0, -100.0),
+            apple_zones: Vec::new(),
+        }
+    }
+}
+
+/// A rectangular arena centred on the origin and enclosed by four walls.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Arena {
+    pub width: f32,
+    pub height: f32,
+    pub wall_thickness: f32,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Obstacle {
+    Rectangle {
+        center: Vec2,
+        size: Vec2,
+    },
+    /// A convex polygon.
+    Polygon {
+        points: Vec<Vec2>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Zone {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+/// Why a `Level` was rejected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LevelError {
+    /// The walls leave no room for apples away from them.
+    ArenaTooSmall { width: f32, height: f32 },
+    /// An apple zone whose `min` isn't at or below its `max`.
+    InvertedZone(Zone),
+    /// The obstacle with this index is a polygon that isn't convex.
+    ConcaveObstacle(usize),
+    /// A spawn point outside the arena or inside an obstacle.
+    SpawnInWall(Vec2),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ArenaTooSmall { width, height } => write!(
+                f,
+                "a {width}x{height} arena leaves no room for apples inside its walls"
+            ),
+            Self::InvertedZone(zone) => write!(
+                f,
+                "apple zone from {} to {} is inside out",
+                zone.min, zone.max
+            ),
+            Self::ConcaveObstacle(index) => {
+                write!(f, "obstacle {index} is a polygon that isn't convex")
+            }
+            Self::SpawnInWall(spawn) => write!(f, "spawn point {spawn} is inside a wall"),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl Arena {
+    /// Half extents of the playable area between the inner faces of the walls.
+    pub fn inner_half_size(&self) -> Vec2 {
+        Vec2::new(
+            self.width / 2.0 - self.wall_thickness / 2.0,
+            self.height / 2.0 - self.wall_thickness / 2.0,
+        )
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        let half_size = self.inner_half_size();
+        point.x < half_size.x
+            && point.x > -half_size.x
+            && point.y < half_size.y
+            && point.y > -half_size.y
+    }
+
+    /// Size of the playable area, which is how far apart the two sides of a wrap-around
+    /// arena are.
+    pub fn period(&self) -> Vec2 {
+        self.inner_half_size() * 2.0
+    }
+
+    /// Brings `point` back into the arena through the opposite wall, as if the arena
+    /// wrapped around.
+    pub fn wrap(&self, point: Vec2) -> Vec2 {
+        let half_size = self.inner_half_size();
+        (point + half_size).rem_euclid(self.period()) - half_size
+    }
+
+    /// Distance from a point inside the arena to the closest wall, negative outside.
+    pub fn distance_to_walls(&self, point: Vec2) -> f32 {
+        let gap = self.inner_half_size() - point.abs();
+        gap.min_element()
+    }
+}
+
+impl Obstacle {
+    /// Whether `distance_to` can measure the obstacle. Polygons need at least three corners
+    /// that all turn the same way, going round once.
+    pub fn is_convex(&self) -> bool {
+        let Obstacle::Polygon { points } = self else {
+            return true;
+        };
+        let len = points.len();
+        if len < 3 {
+            return false;
+        }
+        let mut winding = 0.0;
+        let mut turned = 0.0;
+        for i in 0..len {
+            let incoming = points[(i + 1) % len] - points[i];
+            let outgoing = points[(i + 2) % len] - points[(i + 1) % len];
+            let side = incoming.perp_dot(outgoing);
+            if side != 0.0 {
+                if winding != 0.0 && side.signum() != winding {
+                    return false;
+                }
+                winding = side.signum();
+            }
+            turned += incoming.angle_to(outgoing);
+        }
+        // A star turns the same way at every corner too, but goes round more than once.
+        winding != 0.0 && (turned.abs() - TAU).abs() < 1e-3
+    }
+
+    /// Distance from `point` to the obstacle's outline, zero or negative inside it.
+    pub fn distance_to(&self, point: Vec2) -> f32 {
+        match self {
+            Obstacle::Rectangle { center, size } => {
+                let offset = (point - *center).abs() - *size / 2.0;
+                offset.max(Vec2::ZERO).length() + offset.max_element().min(0.0)
+            }
+            Obstacle::Polygon { points } => {
+                let len = points.len();
+                let mut distance = f32::INFINITY;
+                let mut inside = len >= 3;
+                let mut winding = 0.0;
+                for i in 0..len {
+                    let start = points[i];
+                    let end = points[(i + 1) % len];
+                    distance = distance.min(distance_to_line_segment(point, start, end));
+                    let side = (end - start).perp_dot(point - start);
+                    if side != 0.0 {
+                        if winding != 0.0 && side.signum() != winding {
+                            inside = false;
+                        }
+                        winding = side.signum();
+                    }
+                }
+                if inside { -distance } else { distance }
+            }
+        }
+    }
+}
+
+pub fn distance_to_line_segment(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let line = end - start;
+    let length_squared = line.length_squared();
+    if length_squared == 0.0 {
+        return point.distance(start);
+    }
+    let t = ((point - start).dot(line) / length_squared).clamp(0.0, 1.0);
+    point.distance(start + line * t)
+}
+
+impl Zone {
+    pub fn area(&self) -> f32 {
+        let size = (self.max - self.min).max(Vec2::ZERO);
+        size.x * size.y
+    }
+
+    pub fn random_point(&self, rng: &mut impl Rng) -> Vec2 {
+        Vec2::new(
+            rng.random_range(self.min.x..=self.max.x),
+            rng.random_range(self.min.y..=self.max.y),
+        )
+    }
+}
+
+impl Level {
+    /// Checks for layouts apples can't be placed in, obstacles that can't be built and
+    /// snakes that would start inside a wall.
+    pub fn validate(&self) -> Result<(), LevelError> {
+        let room = self.arena.inner_half_size() * 2.0;
+        if !room.cmpge(Vec2::splat(2.0 * APPLE_WALL_MARGIN)).all() {
+            return Err(LevelError::ArenaTooSmall {
+                width: self.arena.width,
+                height: self.arena.height,
+            });
+        }
+        if let Some(zone) = self
+            .apple_zones
+            .iter()
+            .find(|zone| !zone.min.cmple(zone.max).all())
+        {
+            return Err(LevelError::InvertedZone(*zone));
+        }
+        if let Some(index) = self
+            .obstacles
+            .iter()
+            .position(|obstacle| !obstacle.is_convex())
+        {
+            return Err(LevelError::ConcaveObstacle(index));
+        }
+        if !self.arena.contains(self.spawn) || self.distance_to_nearest_wall(self.spawn) <= 0.0 {
+            return Err(LevelError::SpawnInWall(self.spawn));
+        }
+        Ok(())
+    }
+
+    /// Distance from `point` to the closest arena wall or obstacle.
+    pub fn distance_to_nearest_wall(&self, point: Vec2) -> f32 {
+        self.obstacles
+            .iter()
+            .map(|obstacle| obstacle.distance_to(point))
+            .fold(self.arena.distance_to_walls(point), f32::min)
+    }
+
+    pub fn apple_zones(&self) -> Vec<Zone> {
+        if !self.apple_zones.is_empty() {
+            return self.apple_zones.clone();
+        }
+        let half_size = self.arena.inner_half_size() - Vec2::splat(APPLE_WALL_MARGIN);
+        vec![Zone {
+            min: -half_size,
+            max: half_size,
+        }]
+    }
+
+    /// Picks a zone weighted by its area, then a uniform point inside it.
+    pub fn random_apple_position(&self, rng: &mut impl Rng) -> Vec2 {
+        let zones = self.apple_zones();
+        let total_area: f32 = zones.iter().map(Zone::area).sum();
+        let mut pick = rng.random_range(0.0..=total_area);
+        for zone in &zones {
+            if pick <= zone.area() {
+                return zone.random_point(rng);
+            }
+            pick -= zone.area();
+        }
+        zones[zones.len() - 1].random_point(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    #[test]
+    fn wrapping_comes_back_through_the_opposite_wall() {
+        let arena = Level::default().arena;
+        assert_eq!(arena.wrap(Vec2::new(600.0, 10.0)), Vec2::new(-580.0, 10.0));
+        assert_eq!(
+            arena.wrap(Vec2::new(-20.0, -300.0)),
+            Vec2::new(-20.0, 280.0)
+        );
+        assert_eq!(arena.wrap(Vec2::new(100.0, 50.0)), Vec2::new(100.0, 50.0));
+    }
+
+    #[test]
+    fn apples_default_to_the_whole_arena() {
+        let level = Level::default();
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let position = level.random_apple_position(&mut rng);
+            assert!(level.arena.contains(position));
+        }
+    }
+
+    #[test]
+    fn apples_stay_inside_their_zones() {
+        let zones = vec![
+            Zone {
+                min: Vec2::new(-100.0, -100.0),
+                max: Vec2::new(-50.0, -50.0),
+            },
+            Zone {
+                min: Vec2::new(50.0, 50.0),
+                max: Vec2::new(100.0, 100.0),
+            },
+        ];
+        let level = Level {
+            apple_zones: zones.clone(),
+            ..Level::default()
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let position = level.random_apple_position(&mut rng);
+            assert!(
+                zones.iter().any(|zone| {
+                    position.cmpge(zone.min).all() && position.cmple(zone.max).all()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn inside_out_zones_are_rejected() {
+        let zone = Zone {
+            min: Vec2::new(50.0, -50.0),
+            max: Vec2::new(-50.0, 50.0),
+        };
+        assert_eq!(
+            Level {
+                apple_zones: vec![zone],
+                ..Level::default()
+            }
+            .validate(),
+            Err(LevelError::InvertedZone(zone))
+        );
+
+        let nan = Zone {
+            min: Vec2::new(f32::NAN, 0.0),
+            max: Vec2::new(10.0, 10.0),
+        };
+        assert!(matches!(
+            Level {
+                apple_zones: vec![nan],
+                ..Level::default()
+            }
+            .validate(),
+            Err(LevelError::InvertedZone(_))
+        ));
+    }
+
+    #[test]
+    fn concave_and_star_polygons_are_rejected() {
+        let square = vec![
+            Vec2::new(-50.0, -50.0),
+            Vec2::new(50.0, -50.0),
+            Vec2::new(50.0, 50.0),
+            Vec2::new(-50.0, 50.0),
+        ];
+        let arrow = vec![
+            Vec2::new(-50.0, -50.0),
+            Vec2::new(0.0, 0.0),
+            Vec2::new(50.0, -50.0),
+            Vec2::new(0.0, 50.0),
+        ];
+        let star = (0..5)
+            .map(|i| Vec2::from_angle(i as f32 * 2.0 * TAU / 5.0) * 50.0)
+            .collect();
+        let with_obstacle = |points| Level {
+            obstacles: vec![
+                Obstacle::Rectangle {
+                    center: Vec2::new(-300.0, 0.0),
+                    size: Vec2::splat(40.0),
+                },
+                Obstacle::Polygon { points },
+            ],
+            ..Level::default()
+        };
+        assert_eq!(with_obstacle(square).validate(), Ok(()));
+        assert_eq!(
+            with_obstacle(arrow).validate(),
+            Err(LevelError::ConcaveObstacle(1))
+        );
+        assert_eq!(
+            with_obstacle(star).validate(),
+            Err(LevelError::ConcaveObstacle(1))
+        );
+        assert_eq!(
+            with_obstacle(vec![Vec2::ZERO, Vec2::X]).validate(),
+            Err(LevelError::ConcaveObstacle(1))
+        );
+    }
+
+    #[test]
+    fn snakes_cannot_spawn_inside_walls() {
+        let outside = Vec2::new(700.0, 0.0);
+        let level = Level {
+            spawn: outside,
+            ..Level::default()
+        };
+        assert_eq!(level.validate(), Err(LevelError::SpawnInWall(outside)));
+
+        let level = Level {
+            obstacles: vec![Obstacle::Rectangle {
+                center: Level::default().spawn,
+                size: Vec2::splat(40.0),
+            }],
+            ..Level::default()
+        };
+        assert_eq!(level.validate(), Err(LevelError::SpawnInWall(level.spawn)));
+    }
+
+    #[test]
+    fn arenas_need_room_for_apples() {
+        let mut level = Level {
+            spawn: Vec2::ZERO,
+            ..Level::default()
+        };
+        level.arena.height = level.arena.wall_thickness + 2.0 * APPLE_WALL_MARGIN;
+        assert_eq!(level.validate(), Ok(()));
+
+        level.arena.height -= 1.0;
+        assert_eq!(
+            level.validate(),
+            Err(LevelError::ArenaTooSmall {
+                width: 1200.0,
+                height: level.arena.height,
+            })
+        );
+    }
+
+    #[test]
+    fn obstacle_distances() {
+        let rectangle = Obstacle::Rectangle {
+            center: Vec2::new(10.0, 0.0),
+            size: Vec2::new(20.0, 10.0),
+        };
+        assert_eq!(rectangle.distance_to(Vec2::new(30.0, 0.0)), 10.0);
+        assert!(rectangle.distance_to(Vec2::new(10.0, 0.0)) < 0.0);
+
+        let diamond = Obstacle::Polygon {
+            points: vec![
+                Vec2::new(0.0, -10.0),
+                Vec2::new(10.0, 0.0),
+                Vec2::new(0.0, 10.0),
+                Vec2::new(-10.0, 0.0),
+            ],
+        };
+        assert!(diamond.distance_to(Vec2::ZERO) < 0.0);
+        assert!((diamond.distance_to(Vec2::new(20.0, 0.0)) - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn bundled_levels_parse() {
+        for source in [
+            include_str!("../assets/levels/open.level.ron"),
+            include_str!("../assets/levels/pillars.level.ron"),
+            include_str!("../assets/levels/cross.level.ron"),
+            include_str!("../assets/levels/diamonds.level.ron"),
+        ] {
+            let level: Level = ron::from_str(source).unwrap();
+            assert!(level.arena.contains(level.spawn));
+            assert_eq!(level.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn the_default_level_is_the_open_field() {
+        let open: Level = ron::from_str(include_str!("../assets/levels/open.level.ron")).unwrap();
+        assert_eq!(open, Level::default());
+    }
+}
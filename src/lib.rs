@@ -1 +1,18 @@
-pub mod fabrik;
\ No newline at end of file
+pub mod args;
+pub mod config;
+#[cfg(feature = "bevy")]
+pub mod controls;
+pub mod fabrik;
+pub mod grid;
+pub mod leaderboard;
+pub mod level;
+pub mod mode;
+pub mod net;
+pub mod placement;
+pub mod powerup;
+pub mod replay;
+pub mod rival;
+#[cfg(feature = "bevy")]
+pub mod ron_asset;
+pub mod storage;
+pub mod turns;
@@ -1,12 +1,64 @@
-use std::{cmp, time::Duration};
+mod apples;
+mod high_scores;
+mod menus;
+mod online;
+mod pickups;
+mod playback;
+mod players;
+mod rival_snakes;
+mod stage;
+
+use std::time::Duration;
 
 use avian2d::prelude::*;
-use bevy::{input_focus::InputFocus, prelude::*, window::PrimaryWindow};
-use rand::Rng;
-use snake::fabrik::{
-    GameLayer, HeadOfSnake, Joint, JointFilter, Limb, LimbFilter, LimbSegment, NO_OF_SNAKE_PARTS,
-    SNAKE_HEAD_LENGTH, SNAKE_HEAD_THICKNESS, SnakePart,
+use bevy::{ecs::schedule::ScheduleLabel, prelude::*, window::PrimaryWindow};
+use bevy_asset_loader::prelude::*;
+use rand::{SeedableRng, rngs::StdRng};
+use snake::{
+    args::arg_value,
+    config::SnakeConfig,
+    controls::{
+        Action, ControlScheme, KeyBindings, VERSUS_KEY_SLOTS, dpad_direction, gamepad_direction,
+        stick_heading,
+    },
+    fabrik::{
+        HeadOfSnake, Joint, JointFilter, Limb, LimbFilter, LimbSegment, PartOf, SnakePart,
+        SnakeParts, SnakeStyle, SnakeVelocity,
+    },
+    grid::{self, GridTrail},
+    leaderboard::Leaderboard,
+    level::Level,
+    mode::{GameMode, Players, RoundResult, Walls, can_turn, travel_direction},
+    powerup::ActivePowerUps,
+    replay::{Direction, Input},
+    rival,
+    ron_asset::RonAssetAppExt,
+    turns::TurnBuffer,
 };
+
+use crate::{
+    apples::{
+        ApplesPlugin, CrunchSound, DroppedApple, apple, detect_collision_with_apple, grow_snake,
+        reset_apples, score_apple,
+    },
+    high_scores::HighScoresPlugin,
+    menus::MenusPlugin,
+    online::OnlinePlugin,
+    pickups::{
+        POWER_UP_FONT_SIZE, POWER_UP_TEXT_TOP, PickupsPlugin, PowerUpUi, apply_ghost,
+        collect_power_ups, magnet_apples, reset_power_ups, tick_power_ups,
+    },
+    playback::{
+        PlaybackPlugin, ReplayPlayback, record_tick_input, replay_tick_input, start_recording,
+    },
+    players::{
+        Player, PlayerLooks, PlayersPlugin, ScoreboardUi, player_gamepads, player_spawn_point,
+        scoreboard_label, spawn_players,
+    },
+    rival_snakes::{RIVAL_COLOR, Rival, RivalSnakesPlugin, RivalStyle, move_rivals, reset_rivals},
+    stage::{Boundary, LevelAssets, StagePlugin, wrapping_arena},
+};
+
 fn main() {
     App::new()
         .add_plugins((
@@ -21,45 +73,142 @@ fn main() {
             PhysicsPlugins::default(),
             // PhysicsDebugPlugin,
         ))
-        .init_resource::<InputFocus>()
+        .add_plugins((
+            StagePlugin,
+            ApplesPlugin,
+            PickupsPlugin,
+            RivalSnakesPlugin,
+            PlayersPlugin,
+            MenusPlugin,
+            HighScoresPlugin,
+            PlaybackPlugin,
+            OnlinePlugin,
+        ))
+        .insert_resource(SimulationSeed::from_args())
+        .insert_resource(SimulationRng(StdRng::seed_from_u64(0)))
+        .init_resource::<SimulationTick>()
+        .init_resource::<GameMode>()
+        .init_resource::<Walls>()
+        .init_resource::<ControlScheme>()
+        .insert_resource(KeyBindings::load())
+        .init_ron_asset::<SnakeConfig>(&["snake.ron"])
+        .init_ron_asset::<Level>(&["level.ron"])
         .init_state::<GameState>()
+        .add_loading_state(loading_state())
+        .add_systems(OnExit(GameState::Loading), insert_snake_config)
         .add_systems(
-            OnEnter(GameState::Restart),
-            (restart_game, despawn_snake_parts, reset_snake_position).chain(),
+            ResetGame,
+            (
+                reroll_seed,
+                seed_simulation,
+                restart_game,
+                despawn_snake_parts,
+                spawn_players,
+                snap_to_grid,
+                update_snake_visuals,
+                reset_apples,
+                reset_power_ups,
+                reset_rivals,
+                start_recording,
+            )
+                .chain(),
         )
-        .add_systems(OnEnter(GameState::Restart), reset_scores)
         .add_systems(
-            OnEnter(GameState::GameOver),
-            (game_over_screen, reset_velocity),
+            ResetGame,
+            (reset_velocity, reset_speed, reset_tick, clear_turns),
         )
-        .add_systems(Startup, (setup, draw_snake_head).chain())
-        .add_systems(Startup, (draw_boundaries, setup_scoreboard))
-        // .add_systems(Update, follow_mouse.run_if(in_state(GameState::Start).or(in_state(GameState::Restart))))
         .add_systems(
-            Update,
-            move_snake.run_if(in_state(GameState::Start).or(in_state(GameState::Restart))),
+            OnEnter(GameState::Restart),
+            (reset_game, begin_playing).chain(),
         )
-        .add_systems(Update, button_system.run_if(in_state(GameState::GameOver)))
+        .add_systems(OnEnter(GameState::GameOver), reset_velocity)
         .add_systems(
-            Update,
+            OnEnter(GameState::Start),
+            (
+                seed_simulation,
+                setup,
+                load_head_items,
+                spawn_players,
+                snap_to_grid,
+                reset_apples,
+                reset_power_ups,
+                reset_rivals,
+                start_recording,
+                begin_playing,
+            )
+                .chain(),
+        )
+        .add_systems(Startup, (spawn_camera, setup_scoreboard))
+        .add_systems(
+            FixedUpdate,
             (
-                detect_start_collision_with_apple_field,
+                (
+                    // The pointer can only steer one snake, so in versus both use the keys.
+                    player_tick_input.run_if(
+                        resource_equals(ControlScheme::Buttons)
+                            .or(resource_equals(Players::Versus)),
+                    ),
+                    follow_pointer.run_if(
+                        resource_equals(ControlScheme::Pointer).and(resource_equals(Players::Solo)),
+                    ),
+                    record_tick_input,
+                )
+                    .chain()
+                    .run_if(not(resource_exists::<ReplayPlayback>)),
+                replay_tick_input.run_if(resource_exists::<ReplayPlayback>),
+                move_snake.run_if(not(resource_equals(GameMode::Grid))),
+                step_snake_on_grid.run_if(resource_equals(GameMode::Grid)),
+                move_rivals,
+                update_snake_visuals,
+                magnet_apples,
                 detect_collision_with_apple,
-                detect_end_collision_with_apple,
-                trigger_tounge_and_eyes_animation,
-                detect_start_collision_with_boundary,
-                detect_start_collision_with_snake_parts,
+                (grow_snake, score_apple),
+                tick_power_ups,
+                collect_power_ups,
+                apply_ghost,
+                detect_snake_collisions,
+                advance_tick,
             )
-                .run_if(in_state(GameState::Start).or(in_state(GameState::Restart))),
+                .chain()
+                .run_if(snake_in_play)
+                .run_if(not(game_over_pending)),
+        )
+        .add_systems(
+            RunFixedMainLoop,
+            buffer_turns
+                .in_set(RunFixedMainLoopSystems::BeforeFixedMainLoop)
+                .run_if(in_state(GameState::Playing))
+                .run_if(not(resource_equals(GameMode::Steering)))
+                .run_if(
+                    resource_equals(ControlScheme::Buttons).or(resource_equals(Players::Versus)),
+                ),
+        )
+        .add_systems(
+            Update,
+            trigger_tounge_and_eyes_animation.run_if(snake_in_play),
+        )
+        .add_systems(
+            Update,
+            execute_animations.run_if(not(in_state(GameState::Paused))),
         )
-        .add_systems(Update, execute_animations)
         .run();
 }
 
-type SnakePartFilter = (With<LimbSegment>, Without<Joint>, Without<HeadOfSnake>);
+/// Loads the config and levels, then moves on to the main menu. A file that fails to load
+/// doesn't stop the game: what's missing falls back to the built-in defaults on the way out.
+fn loading_state() -> LoadingState<GameState> {
+    LoadingState::new(GameState::Loading)
+        .continue_to_state(GameState::MainMenu)
+        .on_failure_continue_to_state(GameState::MainMenu)
+        .load_collection::<ConfigAssets>()
+        .load_collection::<LevelAssets>()
+}
 
-#[derive(Resource, Deref, DerefMut)]
-struct LimbResource(Limb);
+#[derive(AssetCollection, Resource)]
+struct ConfigAssets {
+    #[asset(path = "config/snake.snake.ron")]
+    snake: Handle<SnakeConfig>,
+}
 
 #[derive(Resource)]
 struct HeadItems {
@@ -74,32 +223,61 @@ struct HeadItems {
 #[derive(Resource, Deref, DerefMut)]
 struct ToungeAndEyesAnimationTimer(Timer);
 
-#[derive(Resource)]
-struct CircleMeshAndMaterial {
-    mesh: Handle<Mesh>,
-    material: Handle<ColorMaterial>,
-}
-
 #[derive(Resource)]
 struct HitAnimationTextureAndAtlas {
     texture: Handle<Image>,
     texture_atlas_layout: Handle<TextureAtlasLayout>,
 }
 
-#[derive(Resource, Deref, DerefMut)]
-struct SnakeVelocity(Vec2);
+/// Seed of the current game. Rerolled every game unless it was passed with `--seed`.
+#[derive(Resource)]
+struct SimulationSeed {
+    seed: u64,
+    fixed: bool,
+}
 
+impl SimulationSeed {
+    fn from_args() -> Self {
+        let seed = arg_value(std::env::args().skip(1), "--seed");
+        match seed.and_then(|seed| seed.parse().ok()) {
+            Some(seed) => Self { seed, fixed: true },
+            None => Self {
+                seed: rand::random(),
+                fixed: false,
+            },
+        }
+    }
+}
+
+/// The only source of randomness the simulation may use.
 #[derive(Resource, Deref, DerefMut)]
-struct CrunchSound(Handle<AudioSource>);
+struct SimulationRng(StdRng);
+
+/// Number of fixed steps simulated since the game started.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct SimulationTick(u32);
+
+/// A player's steering input for the current tick, read from the keyboard, a gamepad or a
+/// replay.
+#[derive(Component, Default, Deref, DerefMut)]
+struct TickInput(Option<Input>);
+
+/// Runs everything needed to play a fresh game in an existing world.
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+struct ResetGame;
 
 #[derive(Resource, Deref, DerefMut)]
 struct HitSound(Handle<AudioSource>);
 
-#[derive(Component)]
-pub struct Apple;
+/// Multiplies a snake's speed. Speed apples raise it for the rest of the game.
+#[derive(Component, Deref, DerefMut)]
+struct SpeedFactor(f32);
 
-#[derive(Component)]
-pub struct AppleField;
+impl Default for SpeedFactor {
+    fn default() -> Self {
+        Self(1.0)
+    }
+}
 
 #[derive(Component)]
 pub struct Mouth;
@@ -110,9 +288,6 @@ pub struct Tongue;
 #[derive(Component)]
 pub struct Eye;
 
-#[derive(Component)]
-pub struct Boundary;
-
 #[derive(Component)]
 struct AnimationTimer {
     frame_count: usize,
@@ -132,64 +307,238 @@ impl AnimationTimer {
     }
 }
 
-#[derive(Component)]
-struct ScoreboardUi;
-
 #[derive(Component)]
 struct HighScoreUi;
 
-#[derive(Resource)]
-struct PlayerScore {
-    current_score: usize,
-    high_score: usize,
-}
+/// Best score on the leaderboard or of this session.
+#[derive(Resource, Deref, DerefMut)]
+struct HighScore(usize);
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Default, States)]
 enum GameState {
     #[default]
+    Loading,
+    MainMenu,
+    Settings,
+    /// Sets up the first game, then moves on to `Playing`.
     Start,
+    /// Resets the world for another game, then moves on to `Playing`.
     Restart,
+    Playing,
+    Paused,
     GameOver,
+    Replay,
+    /// Playing on the server given with `--connect`.
+    Online,
+    /// Playing versus the peer given with `--peer`, both sides running the whole game.
+    PeerVersus,
 }
 
-const SNAKE_SPEED: f32 = 625.0/2.0;
-
-const WALL_HEIGHT: f32 = 600.0;
-const WALL_THICKNESS: f32 = 20.0;
-
-const FLOOR_THICKNESS: f32 = WALL_THICKNESS;
-const WALL_RIGHT_POSITION: f32 = 600.0;
 const SCOREBOARD_FONT_SIZE: f32 = 33.0;
 const SCOREBOARD_TEXT_PADDING: Val = Val::Px(5.0);
 const HIGH_SCORE_TEXT_PADDING: Val = Val::Px(200.0);
 const TEXT_COLOR: Color = Color::srgb(0.5, 0.5, 1.0);
 const SCORE_COLOR: Color = Color::srgb(1.0, 0.5, 0.5);
 type TongueAndEyesFilter = Or<(With<Tongue>, With<Eye>)>;
+fn reroll_seed(mut simulation_seed: ResMut<SimulationSeed>) {
+    if !simulation_seed.fixed {
+        simulation_seed.seed = rand::random();
+    }
+}
 
-fn setup_scoreboard(mut commands: Commands) {
-    commands.spawn((
-        Text::new("Score: "),
-        TextFont {
-            font_size: SCOREBOARD_FONT_SIZE,
+fn seed_simulation(
+    simulation_seed: Res<SimulationSeed>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    let seed = playback.map_or(simulation_seed.seed, |playback| playback.replay.seed);
+    info!("Starting game with seed {}", seed);
+    **rng = StdRng::seed_from_u64(seed);
+}
+
+fn game_over_pending(next_state: Res<NextState<GameState>>) -> bool {
+    matches!(*next_state, NextState::Pending(GameState::GameOver))
+}
+
+fn snake_in_play(state: Res<State<GameState>>) -> bool {
+    matches!(state.get(), GameState::Playing | GameState::Replay)
+}
+
+fn begin_playing(mut game_state: ResMut<NextState<GameState>>) {
+    game_state.set(GameState::Playing);
+}
+
+fn reset_game(world: &mut World) {
+    world.run_schedule(ResetGame);
+}
+
+fn reset_tick(mut tick: ResMut<SimulationTick>) {
+    **tick = 0;
+}
+
+fn advance_tick(mut tick: ResMut<SimulationTick>) {
+    **tick += 1;
+}
+
+/// Queues the turns pressed this frame. Runs every frame rather than every tick so that a
+/// quick tap between two ticks is not lost.
+fn buffer_turns(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    gamepads: Query<&Gamepad>,
+    players: Res<Players>,
+    mut last_gamepad_directions: Local<[Option<Direction>; 2]>,
+    mut snakes: Query<(&Player, &mut TurnBuffer)>,
+) {
+    for (player, mut turns) in &mut snakes {
+        let slot = (*players == Players::Versus).then(|| VERSUS_KEY_SLOTS[player.0]);
+        for direction in Action::ALL
+            .into_iter()
+            .filter(|action| match slot {
+                Some(slot) => bindings.slot_just_pressed(*action, slot, &keyboard_input),
+                None => bindings.just_pressed(*action, &keyboard_input),
+            })
+            .filter_map(Action::direction)
+        {
+            turns.push(direction);
+        }
+
+        // The stick has no "just pressed", so gamepads turn when their direction changes.
+        let held = player_gamepads(&gamepads, *players, player.0).find_map(gamepad_direction);
+        let last_gamepad_direction = &mut last_gamepad_directions[player.0];
+        if held != *last_gamepad_direction
+            && let Some(direction) = held
+        {
+            turns.push(direction);
+        }
+        *last_gamepad_direction = held;
+    }
+}
+
+fn clear_turns(mut snakes: Query<&mut TurnBuffer>) {
+    for mut turns in &mut snakes {
+        turns.clear();
+    }
+}
+
+fn player_tick_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    gamepads: Query<&Gamepad>,
+    mode: Res<GameMode>,
+    players: Res<Players>,
+    mut snakes: Query<(
+        &Player,
+        &Limb,
+        &SnakeVelocity,
+        &SpeedFactor,
+        &ActivePowerUps,
+        &mut TurnBuffer,
+        &mut TickInput,
+    )>,
+    tick: Res<SimulationTick>,
+    time: Res<Time>,
+    config: Res<SnakeConfig>,
+) {
+    for (player, limb, snake_velocity, speed_factor, power_ups, mut turns, mut tick_input) in
+        &mut snakes
+    {
+        let moves_per_second =
+            config.grid_moves_per_second * **speed_factor * power_ups.speed_factor(&config);
+        let keys_direction = match *players {
+            Players::Solo => bindings.direction(&keyboard_input),
+            Players::Versus => bindings.slot_direction(VERSUS_KEY_SLOTS[player.0], &keyboard_input),
+        };
+        let gamepads = || player_gamepads(&gamepads, *players, player.0);
+        **tick_input = match *mode {
+            // Turns wait in the buffer for the tick the head hops on, so two quick ones are
+            // not taken between the same two cells.
+            GameMode::Grid if !grid::step_due(**tick, moves_per_second, time.delta_secs()) => None,
+            GameMode::Classic | GameMode::Grid => {
+                // Grid hops keep turns a cell apart already.
+                let min_spacing = match *mode {
+                    GameMode::Grid => 0.0,
+                    _ => config.head_thickness,
+                };
+                turns
+                    .next(
+                        Direction::nearest(travel_direction(snake_velocity.0, limb.heading())),
+                        limb.get_last_segment_position(),
+                        min_spacing,
+                    )
+                    .map(Input::Direction)
+            }
+            // The stick points where the head should go rather than picking a direction.
+            GameMode::Steering => keys_direction
+                .or_else(|| gamepads().find_map(dpad_direction))
+                .map(Input::Direction)
+                .or_else(|| gamepads().find_map(stick_heading).map(Input::heading)),
+        };
+    }
+}
+
+/// Inserts the loaded snake config, or the defaults when it failed to load or validate.
+fn insert_snake_config(
+    mut commands: Commands,
+    config_assets: Option<Res<ConfigAssets>>,
+    snake_configs: Res<Assets<SnakeConfig>>,
+) {
+    let Some(config) = config_assets.and_then(|assets| snake_configs.get(&assets.snake).cloned())
+    else {
+        warn!("The snake config could not be loaded; using the defaults");
+        commands.insert_resource(SnakeConfig::default());
+        return;
+    };
+    match config.validate() {
+        Ok(()) => commands.insert_resource(config),
+        Err(error) => {
+            warn!("Ignoring the snake config: {error}");
+            commands.insert_resource(SnakeConfig::default());
+        }
+    }
+}
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn(Camera2d);
+}
+
+fn setup_scoreboard(mut commands: Commands, leaderboard: Res<Leaderboard>) {
+    for (player, node) in [
+        Node {
+            left: SCOREBOARD_TEXT_PADDING,
             ..default()
         },
-        TextColor(TEXT_COLOR),
-        ScoreboardUi,
         Node {
-            position_type: PositionType::Absolute,
-            top: SCOREBOARD_TEXT_PADDING,
-            left: SCOREBOARD_TEXT_PADDING,
+            right: SCOREBOARD_TEXT_PADDING,
             ..default()
         },
-        children![(
-            TextSpan::new("0"),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        commands.spawn((
+            Text::new(scoreboard_label(Players::Solo, player)),
             TextFont {
                 font_size: SCOREBOARD_FONT_SIZE,
                 ..default()
             },
-            TextColor(SCORE_COLOR),
-        )],
-    ));
+            TextColor(TEXT_COLOR),
+            ScoreboardUi(player),
+            Node {
+                position_type: PositionType::Absolute,
+                top: SCOREBOARD_TEXT_PADDING,
+                ..node
+            },
+            children![(
+                TextSpan::new("0"),
+                TextFont {
+                    font_size: SCOREBOARD_FONT_SIZE,
+                    ..default()
+                },
+                TextColor(SCORE_COLOR),
+            )],
+        ));
+    }
     commands.spawn((
         Text::new("High Score: "),
         TextFont {
@@ -205,7 +554,7 @@ fn setup_scoreboard(mut commands: Commands) {
             ..default()
         },
         children![(
-            TextSpan::new("0"),
+            TextSpan::new(leaderboard.high_score().to_string()),
             TextFont {
                 font_size: SCOREBOARD_FONT_SIZE,
                 ..default()
@@ -213,19 +562,28 @@ fn setup_scoreboard(mut commands: Commands) {
             TextColor(SCORE_COLOR),
         )],
     ));
-    commands.insert_resource(PlayerScore {
-        current_score: 0,
-        high_score: 0,
-    });
+    commands.spawn((
+        Text::default(),
+        TextFont {
+            font_size: POWER_UP_FONT_SIZE,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+        PowerUpUi,
+        Node {
+            position_type: PositionType::Absolute,
+            top: POWER_UP_TEXT_TOP,
+            left: SCOREBOARD_TEXT_PADDING,
+            ..default()
+        },
+    ));
+    commands.insert_resource(HighScore(leaderboard.high_score()));
 }
-fn draw_snake_head(
+
+fn load_head_items(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
-    limb_resource: Res<LimbResource>,
-    circle_mesh_and_material: Res<CircleMeshAndMaterial>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     let mouth_texture = asset_server.load("sprites/snake_mouth_sprite.png");
 
@@ -237,11 +595,60 @@ fn draw_snake_head(
         None,
     );
     let mouth_texture_atlas_layout = texture_atlas_layouts.add(layout);
+
+    let tongue_texture = asset_server.load("sprites/snake_tounge.png");
+
+    let layout = TextureAtlasLayout::from_grid(
+        UVec2 { x: 47, y: 22 },
+        21,
+        1,
+        Some(UVec2 { x: 2, y: 2 }),
+        Some(UVec2 { x: 0, y: 3 }),
+    );
+    let tongue_texture_atlas_layout = texture_atlas_layouts.add(layout);
+
+    let eye_texture = asset_server.load("sprites/snake_eye_sprite.png");
+
+    let layout = TextureAtlasLayout::from_grid(
+        UVec2 { x: 26, y: 28 },
+        9,
+        1,
+        Some(UVec2 { x: 3, y: 0 }),
+        None,
+    );
+    let eye_texture_atlas_layout = texture_atlas_layouts.add(layout);
+    commands.insert_resource(HeadItems {
+        eye_texture,
+        eye_texture_atlas_layout,
+        mouth_texture,
+        mouth_texture_atlas_layout,
+        tongue_texture,
+        tongue_texture_atlas_layout,
+    });
+
+    let texture = asset_server.load("sprites/snake_hit.png");
+
+    let layout = TextureAtlasLayout::from_grid(
+        UVec2 { x: 64, y: 53 },
+        36,
+        1,
+        Some(UVec2 { x: 2, y: 0 }),
+        None,
+    );
+    let texture_atlas_layout = texture_atlas_layouts.add(layout);
+    commands.insert_resource(HitAnimationTextureAndAtlas {
+        texture,
+        texture_atlas_layout,
+    });
+}
+
+/// The tongue, mouth and eyes on a player's head.
+fn head_features(head_items: &HeadItems) -> impl Bundle {
     let mouth_bundle = (
         Sprite {
-            image: mouth_texture.clone(),
+            image: head_items.mouth_texture.clone(),
             texture_atlas: Some(TextureAtlas {
-                layout: mouth_texture_atlas_layout.clone(),
+                layout: head_items.mouth_texture_atlas_layout.clone(),
                 index: 0,
             }),
             flip_x: true,
@@ -252,21 +659,11 @@ fn draw_snake_head(
         Mouth,
     );
 
-    let tongue_texture = asset_server.load("sprites/snake_tounge.png");
-
-    let layout = TextureAtlasLayout::from_grid(
-        UVec2 { x: 47, y: 22 },
-        21,
-        1,
-        Some(UVec2 { x: 2, y: 2 }),
-        Some(UVec2 { x: 0, y: 3 }),
-    );
-    let tongue_texture_atlas_layout = texture_atlas_layouts.add(layout);
-    let tounge_bundle = (
+    let tongue_bundle = (
         Sprite {
-            image: tongue_texture.clone(),
+            image: head_items.tongue_texture.clone(),
             texture_atlas: Some(TextureAtlas {
-                layout: tongue_texture_atlas_layout.clone(),
+                layout: head_items.tongue_texture_atlas_layout.clone(),
                 index: 0,
             }),
             flip_x: true,
@@ -277,21 +674,11 @@ fn draw_snake_head(
         Tongue,
     );
 
-    let eye_texture = asset_server.load("sprites/snake_eye_sprite.png");
-
-    let layout = TextureAtlasLayout::from_grid(
-        UVec2 { x: 26, y: 28 },
-        9,
-        1,
-        Some(UVec2 { x: 3, y: 0 }),
-        None,
-    );
-    let eye_texture_atlas_layout = texture_atlas_layouts.add(layout);
     let eye_bundle1 = (
         Sprite {
-            image: eye_texture.clone(),
+            image: head_items.eye_texture.clone(),
             texture_atlas: Some(TextureAtlas {
-                layout: eye_texture_atlas_layout.clone(),
+                layout: head_items.eye_texture_atlas_layout.clone(),
                 index: 0,
             }),
             flip_x: true,
@@ -304,9 +691,9 @@ fn draw_snake_head(
 
     let eye_bundle2 = (
         Sprite {
-            image: eye_texture.clone(),
+            image: head_items.eye_texture.clone(),
             texture_atlas: Some(TextureAtlas {
-                layout: eye_texture_atlas_layout.clone(),
+                layout: head_items.eye_texture_atlas_layout.clone(),
                 index: 0,
             }),
             flip_x: true,
@@ -316,44 +703,7 @@ fn draw_snake_head(
         AnimationTimer::new(9, 20),
         Eye,
     );
-    commands.insert_resource(HeadItems {
-        eye_texture,
-        eye_texture_atlas_layout,
-        mouth_texture,
-        mouth_texture_atlas_layout,
-        tongue_texture,
-        tongue_texture_atlas_layout,
-    });
-
-    let texture = asset_server.load("sprites/snake_hit.png");
-
-    let layout = TextureAtlasLayout::from_grid(
-        UVec2 { x: 64, y: 53 },
-        36,
-        1,
-        Some(UVec2 { x: 2, y: 0 }),
-        None,
-    );
-    let texture_atlas_layout = texture_atlas_layouts.add(layout);
-    commands.insert_resource(HitAnimationTextureAndAtlas {
-        texture,
-        texture_atlas_layout,
-    });
-    let shape = Rectangle::new(SNAKE_HEAD_LENGTH, SNAKE_HEAD_THICKNESS);
-    let mesh = meshes.add(shape);
-    let color = Color::Srgba(Srgba::rgb(1.0, 0.647, 0.0));
-    let material = materials.add(color);
-    let snake_bundle = (
-        Mesh2d(mesh),
-        MeshMaterial2d(material),
-        children![tounge_bundle, mouth_bundle, eye_bundle1, eye_bundle2,],
-    );
-    limb_resource.display(
-        &mut commands,
-        circle_mesh_and_material.mesh.clone(),
-        circle_mesh_and_material.material.clone(),
-        snake_bundle,
-    );
+    children![tongue_bundle, mouth_bundle, eye_bundle1, eye_bundle2]
 }
 
 fn trigger_tounge_and_eyes_animation(
@@ -387,123 +737,22 @@ fn execute_animations(time: Res<Time>, mut query: Query<(&mut AnimationTimer, &m
         }
     }
 }
-fn draw_boundaries(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-) {
-    let shape = Rectangle::new(WALL_THICKNESS, WALL_HEIGHT);
-    let mesh = meshes.add(shape);
-    let color = Color::Srgba(Srgba::rgb(1.0, 0.647, 0.0));
-    let material = materials.add(color);
-    commands.spawn((
-        Mesh2d(mesh.clone()),
-        MeshMaterial2d(material.clone()),
-        Transform::from_xyz(-WALL_RIGHT_POSITION, 0.0, 0.0),
-        RigidBody::Static,
-        Collider::rectangle(WALL_THICKNESS, WALL_HEIGHT),
-        CollisionLayers::new(
-            GameLayer::Boundary,
-            [GameLayer::Default, GameLayer::SnakeHead],
-        ),
-        Boundary,
-    ));
-
-    commands.spawn((
-        Mesh2d(mesh),
-        MeshMaterial2d(material.clone()),
-        Transform::from_xyz(WALL_RIGHT_POSITION, 0.0, 0.0),
-        RigidBody::Static,
-        Collider::rectangle(WALL_THICKNESS, WALL_HEIGHT),
-        CollisionLayers::new(
-            GameLayer::Boundary,
-            [GameLayer::Default, GameLayer::SnakeHead],
-        ),
-        Boundary,
-    ));
-
-    let shape = Rectangle::new(WALL_RIGHT_POSITION * 2.0, FLOOR_THICKNESS);
-    let mesh = meshes.add(shape);
-
-    commands.spawn((
-        Mesh2d(mesh.clone()),
-        MeshMaterial2d(material.clone()),
-        Transform::from_xyz(0.0, WALL_HEIGHT / 2.0, 0.0),
-        RigidBody::Static,
-        Collider::rectangle(WALL_RIGHT_POSITION * 2.0, FLOOR_THICKNESS),
-        CollisionLayers::new(
-            GameLayer::Boundary,
-            [GameLayer::Default, GameLayer::SnakeHead],
-        ),
-        Boundary,
-    ));
-
-    commands.spawn((
-        Mesh2d(mesh),
-        MeshMaterial2d(material),
-        Transform::from_xyz(0.0, -WALL_HEIGHT / 2.0, 0.0),
-        RigidBody::Static,
-        Collider::rectangle(WALL_RIGHT_POSITION * 2.0, FLOOR_THICKNESS),
-        CollisionLayers::new(
-            GameLayer::Boundary,
-            [GameLayer::Default, GameLayer::SnakeHead],
-        ),
-        Boundary,
-    ));
-}
-
 fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     asset_server: Res<AssetServer>,
+    config: Res<SnakeConfig>,
 ) {
     let shape = Circle::new(5.0);
     let mesh = meshes.add(shape);
-    let color = Color::Srgba(Srgba::rgb(1.0, 0.647, 0.0));
-    let material = materials.add(color);
-    commands.insert_resource(CircleMeshAndMaterial {
-        mesh: mesh.clone(),
-        material: material.clone(),
-    });
-    commands.spawn(Camera2d);
-    let limb = Limb::new(
-        Vec2 { x: 200.0, y: 200.0 },
-        NO_OF_SNAKE_PARTS,
-        Vec2 {
-            x: 200.0,
-            y: -100.0,
-        },
-    );
-    commands.insert_resource(LimbResource(limb));
-
-    commands.insert_resource(SnakeVelocity(Vec2 {
-        x: 0.0,
-        y: 0.0,
-    }));
-
-    commands.spawn((
-        Sprite {
-            image: asset_server.load("sprites/apple.png"),
-            ..default()
-        },
-        Transform::from_xyz(50.0, 120.0, -10.0),
-        RigidBody::Kinematic,
-        Collider::circle(15.0),
-        CollisionLayers::new(GameLayer::Apple, [GameLayer::Default, GameLayer::SnakeHead]),
-        Sensor,
-        Apple,
-        children![(
-            RigidBody::Kinematic,
-            Collider::circle(150.0),
-            CollisionLayers::new(
-                GameLayer::AppleField,
-                [GameLayer::Default, GameLayer::SnakeHead],
-            ),
-            Sensor,
-            AppleField
-        )],
-    ));
+    let head_mesh = meshes.add(Rectangle::new(config.head_length, config.head_thickness));
+    commands.insert_resource(PlayerLooks::new(&mesh, &head_mesh, &mut materials));
+    commands.insert_resource(RivalStyle(SnakeStyle::rival(
+        mesh,
+        materials.add(RIVAL_COLOR.darker(0.2)),
+        RIVAL_COLOR,
+    )));
 
     let apple_crunch_sound = asset_server.load("sounds/crunch.wav");
     commands.insert_resource(CrunchSound(apple_crunch_sound));
@@ -517,443 +766,440 @@ fn setup(
     )));
 }
 
+fn grid_trail(mode: GameMode, config: &SnakeConfig, limb: &mut Limb) -> Option<GridTrail> {
+    (mode == GameMode::Grid).then(|| GridTrail::new(config.grid_cell_size, limb))
+}
+
+/// Steers each player's snake by its tick input and moves its head a tick's travel,
+/// stopping it at solid outer walls and carrying it across wrap-around ones. Grid snakes
+/// move in `step_snake_on_grid` instead.
 fn move_snake(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    joint_query: Query<(&mut Transform, &Joint), JointFilter>,
-    limb_query: Query<(&mut Transform, &LimbSegment), LimbFilter>,
-    mut limb_resource: ResMut<LimbResource>,
-    mut snake_velocity: ResMut<SnakeVelocity>,
-    time:Res<Time>
+    players: Query<
+        (
+            &TickInput,
+            &ActivePowerUps,
+            &mut Limb,
+            &mut SnakeVelocity,
+            &SpeedFactor,
+        ),
+        With<Player>,
+    >,
+    time: Res<Time>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    mode: Res<GameMode>,
+    walls: Res<Walls>,
 ) {
-    if keyboard_input.pressed(KeyCode::ArrowLeft) {
-        snake_velocity.0 = Vec2 {
-            x: -SNAKE_SPEED*time.delta_secs(),
-            y: 0.0,
-        };
-    }
-    if keyboard_input.pressed(KeyCode::ArrowRight) {
-        snake_velocity.0 = Vec2 {
-            x: SNAKE_SPEED*time.delta_secs(),
-            y: 0.0,
-        };
-    }
-
-    if keyboard_input.pressed(KeyCode::ArrowUp) {
-        snake_velocity.0 = Vec2 {
-            x: 0.0,
-            y: SNAKE_SPEED*time.delta_secs(),
-        };
-    }
-
-    if keyboard_input.pressed(KeyCode::ArrowDown) {
-        snake_velocity.0 = Vec2 {
-            x: 0.0,
-            y: -SNAKE_SPEED*time.delta_secs(),
-        };
-    }
-
-    if keyboard_input.pressed(KeyCode::KeyA) {
-        snake_velocity.0 = Vec2 {
-            x: -SNAKE_SPEED*time.delta_secs(),
-            y: 0.0,
-        };
-    }
-    if keyboard_input.pressed(KeyCode::KeyD) {
-        snake_velocity.0 = Vec2 {
-            x: SNAKE_SPEED*time.delta_secs(),
-            y: 0.0,
-        };
-    }
-
-    if keyboard_input.pressed(KeyCode::KeyW) {
-        snake_velocity.0 = Vec2 {
-            x: 0.0,
-            y: SNAKE_SPEED*time.delta_secs(),
-        };
+    for (tick_input, power_ups, mut limb, mut snake_velocity, speed_factor) in players {
+        let speed = config.speed * **speed_factor * power_ups.speed_factor(&config);
+        snake_velocity.0 = mode.steer(
+            **tick_input,
+            snake_velocity.0,
+            limb.heading(),
+            speed * time.delta_secs(),
+            config.turn_rate() * time.delta_secs(),
+        );
+        if snake_velocity.0.length() == 0.0 {
+            continue;
+        }
+        let target = limb.get_last_segment_position() + snake_velocity.0;
+        let wrapping_arena = wrapping_arena(*walls, &level);
+        if wrapping_arena.is_none() && !level.arena.contains(target) {
+            snake_velocity.0 = Vec2::ZERO;
+        }
+        limb.set_target(target);
+        limb.solve();
+        if let Some(arena) = wrapping_arena {
+            let head = limb.get_last_segment_position();
+            limb.translate(arena.wrap(head) - head);
+        }
     }
+}
 
-    if keyboard_input.pressed(KeyCode::KeyS) {
-        snake_velocity.0 = Vec2 {
-            x: 0.0,
-            y: -SNAKE_SPEED*time.delta_secs(),
-        };
-    }
-    if snake_velocity.0.length() == 0.0 {
-        return;
-    }
-    let right_bound_x = WALL_RIGHT_POSITION - WALL_THICKNESS / 2.0;
-    let upper_bound_y = WALL_HEIGHT / 2.0 - FLOOR_THICKNESS / 2.0;
-    let target = limb_resource.get_last_segment_position() + snake_velocity.0;
-    if target.x >= right_bound_x
-        || target.x <= -right_bound_x
-        || target.y >= upper_bound_y
-        || target.y <= -upper_bound_y
+fn step_snake_on_grid(
+    tick: Res<SimulationTick>,
+    players: Query<
+        (
+            &TickInput,
+            &ActivePowerUps,
+            &mut GridTrail,
+            &mut Limb,
+            &mut SnakeVelocity,
+            &SpeedFactor,
+        ),
+        With<Player>,
+    >,
+    time: Res<Time>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    walls: Res<Walls>,
+) {
+    for (tick_input, power_ups, mut grid_trail, mut limb, mut snake_velocity, speed_factor) in
+        players
     {
-        snake_velocity.0 = Vec2::ZERO;
+        let moves_per_second =
+            config.grid_moves_per_second * **speed_factor * power_ups.speed_factor(&config);
+        if !grid::step_due(**tick, moves_per_second, time.delta_secs()) {
+            continue;
+        }
+        let direction = match **tick_input {
+            Some(Input::Direction(direction)) => Some(direction),
+            Some(Input::Heading(steps)) => {
+                Direction::nearest(Vec2::from_angle(Input::heading_angle(steps)))
+            }
+            None => None,
+        };
+        let travel = travel_direction(snake_velocity.0, limb.heading());
+        if let Some(direction) = direction.filter(|direction| can_turn(direction.as_vec2(), travel))
+        {
+            snake_velocity.0 = direction.as_vec2();
+        }
+        if snake_velocity.0 == Vec2::ZERO {
+            continue;
+        }
+        let cell = grid_trail.head() + snake_velocity.0 * grid_trail.cell_size();
+        let wrapping_arena = wrapping_arena(*walls, &level);
+        if wrapping_arena.is_none() && !level.arena.contains(cell) {
+            snake_velocity.0 = Vec2::ZERO;
+        }
+        grid_trail.advance(cell, &mut limb);
+        if let Some(arena) = wrapping_arena {
+            let offset = arena.wrap(cell) - cell;
+            grid_trail.translate(offset);
+            limb.translate(offset);
+        }
     }
-    limb_resource.set_target(target);
-    limb_resource.forward_fabrik();
-    limb_resource.update_visuals(joint_query, limb_query);
 }
-fn follow_mouse(
+
+/// Heads for the first touch, or the cursor while the left mouse button is held. The
+/// input is recorded as a heading so `move_snake` keeps to the configured speed and walls.
+fn follow_pointer(
     buttons: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
     windows: Query<&Window, With<PrimaryWindow>>,
     camera: Query<(&Camera, &GlobalTransform)>,
-    joint_query: Query<(&mut Transform, &Joint), JointFilter>,
-    limb_query: Query<(&mut Transform, &LimbSegment), LimbFilter>,
-    mut limb_resource: ResMut<LimbResource>,
+    player: Single<(&Limb, &mut TickInput), With<Player>>,
+    config: Res<SnakeConfig>,
+    time: Res<Time>,
 ) -> Result {
-    if buttons.pressed(MouseButton::Left) {
-        let window = windows.single()?;
-        let (camera, camera_transform) = camera.single()?;
-
-        if let Some(cursor_world_pos) = window
-            .cursor_position()
-            .and_then(|cursor| camera.viewport_to_world_2d(camera_transform, cursor).ok())
-        {
-            limb_resource.set_target(cursor_world_pos);
-
-            limb_resource.forward_fabrik();
-            limb_resource.update_visuals(joint_query, limb_query);
-        }
-    }
+    let window = windows.single()?;
+    let (camera, camera_transform) = camera.single()?;
+    let (limb, mut tick_input) = player.into_inner();
+
+    let pointer = touches
+        .iter()
+        .next()
+        .map(|touch| touch.position())
+        .or_else(|| {
+            window
+                .cursor_position()
+                .filter(|_| buttons.pressed(MouseButton::Left))
+        });
+    **tick_input = pointer
+        .and_then(|pointer| camera.viewport_to_world_2d(camera_transform, pointer).ok())
+        .map(|target| target - limb.get_last_segment_position())
+        // Already on the pointer, turning towards it would only make the head wobble.
+        .filter(|offset| offset.length() > config.speed * time.delta_secs())
+        .map(|offset| Input::heading(offset.to_angle()));
 
     Ok(())
 }
 
-fn detect_collision_with_apple(
-    mut collision_reader: MessageReader<CollisionEnd>,
-    mut apple: Single<(Entity, &mut Transform), With<Apple>>,
-    mut joints_query: Query<&mut Joint>,
-    mut limb_query: Query<&mut LimbSegment>,
-    mut limb_resource: ResMut<LimbResource>,
-    mut commands: Commands,
-    circle_mesh_and_material: Res<CircleMeshAndMaterial>,
+/// Plays the hit sound and animation on the player's head and ends the game.
+fn hit_player(
+    commands: &mut Commands,
+    snake_head: Entity,
+    hit_sound: &HitSound,
+    hit_animation: &HitAnimationTextureAndAtlas,
+    game_state: &mut NextState<GameState>,
 ) {
-    let no_of_snake_parts_to_add = 2;
-    for event in collision_reader.read() {
-        if event.collider1 != apple.0 && event.collider2 != apple.0 {
-            continue;
-        }
-        for mut joint in joints_query.iter_mut() {
-            joint.0 += no_of_snake_parts_to_add;
-        }
-        for mut limb in limb_query.iter_mut() {
-            limb.0 += no_of_snake_parts_to_add;
-        }
-        limb_resource.add_multiple_snake_parts(
-            no_of_snake_parts_to_add,
-            &mut commands,
-            circle_mesh_and_material.mesh.clone(),
-            circle_mesh_and_material.material.clone(),
-        );
+    commands.spawn((AudioPlayer(hit_sound.0.clone()), PlaybackSettings::DESPAWN));
+    let hit_bundle = (
+        Sprite {
+            image: hit_animation.texture.clone(),
+            texture_atlas: Some(TextureAtlas {
+                layout: hit_animation.texture_atlas_layout.clone(),
+                index: 0,
+            }),
+            flip_x: true,
+            ..default()
+        },
+        Transform::from_scale(Vec3::splat(1.0)).with_translation(Vec3::new(0.0, 0.0, 0.0)),
+        AnimationTimer::new(36, 20),
+    );
+    let animation_entity = commands.spawn(hit_bundle).id();
+    commands
+        .entity(snake_head)
+        .despawn_children()
+        .add_child(animation_entity);
 
-        let mut rng = rand::rng();
-        let right_bound_x = WALL_RIGHT_POSITION - WALL_THICKNESS / 2.0 - 20.0;
-        let upper_bound_y = WALL_HEIGHT / 2.0 - FLOOR_THICKNESS / 2.0 - 20.0;
-        let x: f32 = rng.random_range(-right_bound_x..=right_bound_x);
-        let y: f32 = rng.random_range(-upper_bound_y..=upper_bound_y);
-        apple.1.translation.x = x;
-        apple.1.translation.y = y;
-    }
+    game_state.set(GameState::GameOver);
 }
 
-fn detect_end_collision_with_apple(
-    mut collision_reader: MessageReader<CollisionEnd>,
-    apple: Single<Entity, With<Apple>>,
-    mut commands: Commands,
-    crunch_sound: Res<CrunchSound>,
-    score_root: Single<Entity, (With<ScoreboardUi>, With<Text>)>,
-    mut writer: TextUiWriter,
-    mut player_score: ResMut<PlayerScore>,
-) {
-    let apple = apple.entity();
-    for event in collision_reader.read() {
-        if event.collider1 != apple && event.collider2 != apple {
-            continue;
-        }
-        commands.spawn((AudioPlayer(crunch_sound.clone()), PlaybackSettings::DESPAWN));
-        player_score.current_score += 1;
-        *writer.text(*score_root, 1) = player_score.current_score.to_string();
+fn reset_velocity(mut snake_velocities: Query<&mut SnakeVelocity, With<Player>>) {
+    for mut snake_velocity in &mut snake_velocities {
+        snake_velocity.0 = Vec2 { x: 0.0, y: 0.0 };
     }
 }
 
-fn detect_start_collision_with_boundary(
-    mut collision_reader: MessageReader<CollisionStart>,
-    boundary: Query<Entity, With<Boundary>>,
-    snake_head: Single<Entity, With<HeadOfSnake>>,
+/// Lays every player's snake out at its starting point again, with a fresh face.
+fn restart_game(
+    mut players: Query<(&Player, &mut Limb, &SnakeParts)>,
+    heads: Query<Entity, With<HeadOfSnake>>,
     mut commands: Commands,
-    hit_sound: Res<HitSound>,
-    hit_animation: Res<HitAnimationTextureAndAtlas>,
-    mut game_state: ResMut<NextState<GameState>>,
+    player_count: Res<Players>,
+    head_items: Res<HeadItems>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    mut rng: ResMut<SimulationRng>,
 ) {
-    for event in collision_reader.read() {
-        if boundary.get(event.collider1).is_err() && boundary.get(event.collider2).is_err() {
-            continue;
+    // Players are laid out in order, so the random spawn points come out as they did when
+    // the snakes were first spawned.
+    let mut players: Vec<_> = players
+        .iter_mut()
+        .filter(|(player, ..)| player.0 < player_count.count())
+        .collect();
+    players.sort_by_key(|(player, ..)| player.0);
+    let lengths = config.segment_lengths();
+    let mut occupied = Vec::new();
+    for (player, mut limb, parts) in players {
+        if let Some(snake_head) = heads.iter_many(parts.iter()).next() {
+            commands
+                .entity(snake_head)
+                .despawn_children()
+                .insert(head_features(&head_items));
         }
-        commands.spawn((AudioPlayer(hit_sound.clone()), PlaybackSettings::DESPAWN));
-        let hit_bundle = (
-            Sprite {
-                image: hit_animation.texture.clone(),
-                texture_atlas: Some(TextureAtlas {
-                    layout: hit_animation.texture_atlas_layout.clone(),
-                    index: 0,
-                }),
-                flip_x: true,
-                ..default()
-            },
-            Transform::from_scale(Vec3::splat(1.0)).with_translation(Vec3::new(0.0, 0.0, 0.0)),
-            AnimationTimer::new(36, 20),
-        );
-        let animation_entity = commands.spawn(hit_bundle).id();
-        commands
-            .entity(snake_head.entity())
-            .despawn_children()
-            .add_child(animation_entity);
 
-        game_state.set(GameState::GameOver);
+        let reach = config.starting_reach();
+        let start = player_spawn_point(player.0, &level, &occupied, reach, &config, &mut rng);
+        limb.reset_limb(start, &lengths);
+        occupied.extend(limb.segments().iter().map(|segment| segment.position()));
     }
 }
 
-fn detect_start_collision_with_snake_parts(
-    mut collision_reader: MessageReader<CollisionStart>,
-    snake_parts: Query<Entity, With<SnakePart>>,
-    snake_head: Single<Entity, With<HeadOfSnake>>,
+fn despawn_snake_parts(
+    players: Query<&SnakeParts, With<Player>>,
+    joint_query: Query<(Entity, &Joint)>,
+    mut limb_query: Query<(Entity, &mut LimbSegment, Has<HeadOfSnake>)>,
     mut commands: Commands,
-    hit_sound: Res<HitSound>,
-    hit_animation: Res<HitAnimationTextureAndAtlas>,
-    mut game_state: ResMut<NextState<GameState>>,
+    config: Res<SnakeConfig>,
 ) {
-    for event in collision_reader.read() {
-        if snake_parts.get(event.collider1).is_err() && snake_parts.get(event.collider2).is_err() {
-            continue;
+    for parts in &players {
+        for (entity, joint_index) in joint_query.iter_many(parts.iter()) {
+            if joint_index.0 >= config.no_of_parts {
+                commands.entity(entity).despawn();
+            }
         }
-        
-        commands.spawn((AudioPlayer(hit_sound.clone()), PlaybackSettings::DESPAWN));
-        let hit_bundle = (
-            Sprite {
-                image: hit_animation.texture.clone(),
-                texture_atlas: Some(TextureAtlas {
-                    layout: hit_animation.texture_atlas_layout.clone(),
-                    index: 0,
-                }),
-                flip_x: true,
-                ..default()
-            },
-            Transform::from_scale(Vec3::splat(1.0)).with_translation(Vec3::new(0.0, 0.0, 0.0)),
-            AnimationTimer::new(36, 20),
-        );
-        let animation_entity = commands.spawn(hit_bundle).id();
-        commands
-            .entity(snake_head.entity())
-            .despawn_children()
-            .add_child(animation_entity);
 
-        game_state.set(GameState::GameOver);
+        let mut limb_segments = limb_query.iter_many_mut(parts.iter());
+        while let Some((entity, mut limb_index, is_head)) = limb_segments.fetch_next() {
+            if is_head {
+                limb_index.0 = config.head_index();
+            } else if limb_index.0 >= config.head_index() {
+                commands.entity(entity).despawn();
+            }
+        }
     }
 }
 
-fn detect_start_collision_with_apple_field(
-    mut collision_reader: MessageReader<CollisionStart>,
-    mut mouth: Single<&mut AnimationTimer, With<Mouth>>,
-    apple_field: Single<Entity, With<AppleField>>,
+/// Moves the joint and segment entities of every snake onto its limb.
+fn update_snake_visuals(
+    snakes: Query<(&Limb, &SnakeParts)>,
+    mut joint_query: Query<(&mut Transform, &Joint), JointFilter>,
+    mut limb_query: Query<(&mut Transform, &LimbSegment), LimbFilter>,
+    level: Res<Level>,
+    walls: Res<Walls>,
 ) {
-    let apple_field = apple_field.entity();
-    for event in collision_reader.read() {
-        if event.collider1 != apple_field && event.collider2 != apple_field {
-            continue;
-        }
-        mouth.timer = AnimationTimer::timer_from_fps(mouth.fps)
+    let wrapping_arena = wrapping_arena(*walls, &level);
+    for (limb, parts) in &snakes {
+        limb.update_visuals(wrapping_arena, parts, &mut joint_query, &mut limb_query);
     }
 }
 
-fn game_over_screen(mut commands: Commands) {
-    commands.spawn(button());
-}
-fn reset_velocity(mut snake_velocity: ResMut<SnakeVelocity>) {
-    snake_velocity.0 = Vec2 { x: 0.0, y: 0.0 };
-}
-fn reset_scores(
-    score_root: Single<Entity, (With<ScoreboardUi>, With<Text>)>,
-    high_score_root: Single<Entity, (With<HighScoreUi>, With<Text>)>,
-    mut writer: TextUiWriter,
-    mut player_score: ResMut<PlayerScore>,
-) {
-    player_score.high_score = cmp::max(player_score.current_score, player_score.high_score);
-    player_score.current_score = 0;
-    *writer.text(*score_root, 1) = player_score.current_score.to_string();
-    *writer.text(*high_score_root, 1) = player_score.high_score.to_string();
-}
-fn restart_game(
-    snake_head: Single<Entity, With<HeadOfSnake>>,
+fn snap_to_grid(
     mut commands: Commands,
-    head_items: Res<HeadItems>,
-    mut limb_resource: ResMut<LimbResource>,
+    mut players: Query<(Entity, &mut Limb), With<Player>>,
+    config: Res<SnakeConfig>,
+    mode: Res<GameMode>,
 ) {
-    let mouth_bundle = (
-        Sprite {
-            image: head_items.mouth_texture.clone(),
-            texture_atlas: Some(TextureAtlas {
-                layout: head_items.mouth_texture_atlas_layout.clone(),
-                index: 0,
-            }),
-            flip_x: true,
-            ..default()
-        },
-        Transform::from_scale(Vec3::splat(1.0)).with_translation(Vec3::new(-15.0, 0.0, 10.0)),
-        AnimationTimer::new(15, 30),
-        Mouth,
-    );
-    let mouth = commands.spawn(mouth_bundle).id();
-
-    let tongue_bundle = (
-        Sprite {
-            image: head_items.tongue_texture.clone(),
-            texture_atlas: Some(TextureAtlas {
-                layout: head_items.tongue_texture_atlas_layout.clone(),
-                index: 0,
-            }),
-            flip_x: true,
-            ..default()
-        },
-        Transform::from_scale(Vec3::splat(1.0)).with_translation(Vec3::new(-40.0, 0.0, 10.0)),
-        AnimationTimer::new(21, 20),
-        Tongue,
-    );
-    let tongue = commands.spawn(tongue_bundle).id();
-
-    let eye_bundle1 = (
-        Sprite {
-            image: head_items.eye_texture.clone(),
-            texture_atlas: Some(TextureAtlas {
-                layout: head_items.eye_texture_atlas_layout.clone(),
-                index: 0,
-            }),
-            flip_x: true,
-            ..default()
-        },
-        Transform::from_scale(Vec3::splat(1.0)).with_translation(Vec3::new(15.0, 10.0, 10.0)),
-        AnimationTimer::new(9, 20),
-        Eye,
-    );
-
-    let eye1 = commands.spawn(eye_bundle1).id();
-
-    let eye_bundle2 = (
-        Sprite {
-            image: head_items.eye_texture.clone(),
-            texture_atlas: Some(TextureAtlas {
-                layout: head_items.eye_texture_atlas_layout.clone(),
-                index: 0,
-            }),
-            flip_x: true,
-            ..default()
-        },
-        Transform::from_scale(Vec3::splat(1.0)).with_translation(Vec3::new(15.0, -10.0, 10.0)),
-        AnimationTimer::new(9, 20),
-        Eye,
-    );
-    let eye2 = commands.spawn(eye_bundle2).id();
+    for (player, mut limb) in &mut players {
+        match grid_trail(*mode, &config, &mut limb) {
+            Some(grid_trail) => commands.entity(player).insert(grid_trail),
+            None => commands.entity(player).remove::<GridTrail>(),
+        };
+    }
+}
 
-    commands
-        .entity(snake_head.entity())
-        .despawn_children()
-        .add_children(&[mouth, tongue, eye1, eye2]);
+fn reset_speed(mut speed_factors: Query<&mut SpeedFactor, With<Player>>) {
+    for mut speed_factor in &mut speed_factors {
+        speed_factor.0 = 1.0;
+    }
+}
 
-    limb_resource.reset_limb(Vec2 {
-        x: 200.0,
-        y: -100.0,
-    })
+/// What a collider that took part in a collision belongs to.
+#[derive(Clone, Copy)]
+enum Body {
+    /// A head segment and the snake it belongs to.
+    Head(Entity, Entity),
+    Part,
+    Wall,
 }
 
-fn despawn_snake_parts(
-    joint_query: Query<(Entity, &Joint), JointFilter>,
-    limb_query: Query<(Entity, &LimbSegment), SnakePartFilter>,
-    mut snake_head: Single<&mut LimbSegment, With<HeadOfSnake>>,
+/// Settles the collisions snakes take part in. A snake that runs into a wall or a body
+/// dies, and when two heads meet the shorter snake dies, or both if they are as long.
+/// A player's death ends the game, and in versus decides who won the round. A rival that
+/// dies drops apples along its body.
+fn detect_snake_collisions(
+    mut collision_reader: MessageReader<CollisionStart>,
+    heads: Query<&PartOf, With<HeadOfSnake>>,
+    snake_parts: Query<(), With<SnakePart>>,
+    boundary: Query<(), With<Boundary>>,
+    snakes: Query<(&Limb, Option<&Player>)>,
+    rivals: Query<(Entity, &Limb), With<Rival>>,
+    players: Res<Players>,
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    hit_sound: Res<HitSound>,
+    hit_animation: Res<HitAnimationTextureAndAtlas>,
+    mut game_state: ResMut<NextState<GameState>>,
 ) {
-    for (entity, joint_index) in joint_query {
-        if joint_index.0 >= NO_OF_SNAKE_PARTS {
-            commands.entity(entity).despawn();
+    let body = |entity: Entity| {
+        if let Ok(part_of) = heads.get(entity) {
+            Some(Body::Head(entity, part_of.0))
+        } else if snake_parts.contains(entity) {
+            Some(Body::Part)
+        } else if boundary.contains(entity) {
+            Some(Body::Wall)
+        } else {
+            None
+        }
+    };
+    let length = |snake: Entity| {
+        snakes
+            .get(snake)
+            .map_or(0, |(limb, _)| limb.segments().len())
+    };
+
+    // The outer walls of a wrap-around arena have no colliders, and rivals do not use them.
+    let mut dead: Vec<(Entity, Option<Entity>)> = rivals
+        .iter()
+        .filter(|(_, limb)| !level.arena.contains(limb.get_last_segment_position()))
+        .map(|(entity, _)| (entity, None))
+        .collect();
+    for event in collision_reader.read() {
+        let (Some(first), Some(second)) = (body(event.collider1), body(event.collider2)) else {
+            continue;
+        };
+        let casualties = match (first, second) {
+            (Body::Head(_, snake), Body::Head(_, other)) => {
+                rival::head_on_casualties(length(snake), length(other))
+            }
+            (Body::Head(..), _) => (true, false),
+            (_, Body::Head(..)) => (false, true),
+            _ => (false, false),
+        };
+        for (body, dies) in [(first, casualties.0), (second, casualties.1)] {
+            match body {
+                Body::Head(head, snake) if dies && !dead.iter().any(|(dead, _)| *dead == snake) => {
+                    dead.push((snake, Some(head)));
+                }
+                _ => {}
+            }
         }
     }
 
-    for (entity, limb_index) in limb_query {
-        if limb_index.0 >= NO_OF_SNAKE_PARTS - 2 {
-            commands.entity(entity).despawn();
+    let mut crashed = vec![false; players.count()];
+    for (snake, head) in dead {
+        let Ok((limb, player)) = snakes.get(snake) else {
+            continue;
+        };
+        if let Some(player) = player {
+            if let Some(head) = head {
+                hit_player(
+                    &mut commands,
+                    head,
+                    &hit_sound,
+                    &hit_animation,
+                    &mut game_state,
+                );
+                crashed[player.0] = true;
+            }
+            continue;
         }
+        if !config.apple_types.is_empty() {
+            let spacing = config.rival_drop_spacing.max(1);
+            for segment in limb.segments().iter().rev().step_by(spacing) {
+                let apple = apple(&asset_server, &config, 0, segment.position());
+                commands.spawn((apple, DroppedApple));
+            }
+        }
+        commands.entity(snake).despawn();
+    }
+    if *players == Players::Versus
+        && let Some(round_result) = RoundResult::from_crashes(&crashed)
+    {
+        commands.insert_resource(round_result);
     }
-    snake_head.0 = NO_OF_SNAKE_PARTS - 2;
 }
 
-fn reset_snake_position(
-    joint_query: Query<(&mut Transform, &Joint), JointFilter>,
-    limb_query: Query<(&mut Transform, &LimbSegment), LimbFilter>,
-    limb_resource: Res<LimbResource>,
-) {
-    limb_resource.update_visuals(joint_query, limb_query);
-}
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
 
-fn button() -> impl Bundle {
-    (
-        DespawnOnExit(GameState::GameOver),
-        Node {
-            width: percent(100),
-            height: percent(100),
-            align_items: AlignItems::Center,
-            justify_content: JustifyContent::Center,
-            ..default()
-        },
-        children![(
-            Button,
-            Node {
-                width: px(200),
-                height: px(65),
-                border: UiRect::all(px(5)),
-                // horizontally center child text
-                justify_content: JustifyContent::Center,
-                // vertically center child text
-                align_items: AlignItems::Center,
-                ..default()
-            },
-            BorderColor::all(Color::WHITE),
-            BorderRadius::MAX,
-            BackgroundColor(Color::BLACK),
-            children![(
-                Text::new("Restart"),
-                TextFont {
-                    font_size: 33.0,
-                    ..default()
-                },
-                TextColor(Color::srgb(0.9, 0.9, 0.9)),
-                TextShadow::default(),
-            )]
-        )],
-    )
-}
+    use super::*;
 
-fn button_system(
-    mut input_focus: ResMut<InputFocus>,
-    mut interaction_query: Query<(Entity, &Interaction, &mut Button), Changed<Interaction>>,
-    mut game_state: ResMut<NextState<GameState>>,
-) {
-    for (entity, interaction, mut button) in &mut interaction_query {
-        match *interaction {
-            Interaction::Pressed => {
-                input_focus.set(entity);
+    /// A versus game's world, with the random spawn points drawn from `seed`.
+    fn versus_world(seed: u64) -> World {
+        let mut world = World::new();
+        world.init_resource::<Assets<ColorMaterial>>();
+        let looks = PlayerLooks::new(
+            &default(),
+            &default(),
+            &mut world.resource_mut::<Assets<ColorMaterial>>(),
+        );
+        world.insert_resource(looks);
+        world.insert_resource(HeadItems {
+            eye_texture: default(),
+            eye_texture_atlas_layout: default(),
+            mouth_texture: default(),
+            mouth_texture_atlas_layout: default(),
+            tongue_texture: default(),
+            tongue_texture_atlas_layout: default(),
+        });
+        world.insert_resource(SnakeConfig::default());
+        world.insert_resource(Level::default());
+        world.insert_resource(Players::Versus);
+        world.insert_resource(SimulationRng(StdRng::seed_from_u64(seed)));
+        world
+    }
 
-                game_state.set(GameState::Restart);
+    /// Where each player's snake starts, in player order.
+    fn tails(world: &mut World) -> Vec<Vec2> {
+        let mut tails: Vec<(usize, Vec2)> = world
+            .query::<(&Player, &Limb)>()
+            .iter(world)
+            .map(|(player, limb)| (player.0, limb.segments()[0].position()))
+            .collect();
+        tails.sort_by_key(|(player, _)| *player);
+        tails.into_iter().map(|(_, tail)| tail).collect()
+    }
 
-                // The accessibility system's only update the button's state when the `Button` component is marked as changed.
-                button.set_changed();
-            }
-            Interaction::Hovered => {
-                input_focus.set(entity);
-                button.set_changed();
-            }
-            Interaction::None => {
-                input_focus.clear();
+    #[test]
+    fn grown_versus_games_restart_where_fresh_ones_start() {
+        let mut world = versus_world(5);
+        world.run_system_once(spawn_players).unwrap();
+        let fresh = tails(&mut world);
+        assert_eq!(fresh.len(), 2);
+
+        for mut limb in world.query::<&mut Limb>().iter_mut(&mut world) {
+            for _ in 0..30 {
+                limb.add_snake_part(20.0);
             }
         }
+        world.insert_resource(SimulationRng(StdRng::seed_from_u64(5)));
+        world.run_system_once(restart_game).unwrap();
+        assert_eq!(tails(&mut world), fresh);
     }
 }
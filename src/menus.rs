@@ -0,0 +1,808 @@
+//! The menus and their buttons: the main menu, settings with key rebinding, the pause
+//! and game over screens, and navigating them with a gamepad.
+
+use avian2d::prelude::*;
+use bevy::{
+    audio::Volume,
+    input::gamepad::{GamepadConnection, GamepadConnectionEvent},
+    input_focus::{
+        InputFocus, InputFocusVisible,
+        tab_navigation::{NavAction, TabGroup, TabIndex, TabNavigation},
+    },
+    prelude::*,
+};
+use snake::{
+    controls::{Action, ControlScheme, KEYS_PER_ACTION, KeyBindings, key_label},
+    level::Level,
+    mode::{GameMode, Players, RoundResult, Walls},
+    rival::Rivals,
+};
+
+use crate::{
+    GameState, SCORE_COLOR, TEXT_COLOR,
+    high_scores::{LeaderboardPanel, PendingScore},
+    online::{PeerAddress, ServerAddress},
+    players::{PLAYER_COLORS, Player},
+    stage::{LevelAssets, SelectedLevel},
+};
+
+pub struct MenusPlugin;
+
+impl Plugin for MenusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputFocus>()
+            .init_resource::<InputFocusVisible>()
+            .init_resource::<Rebinding>()
+            .add_systems(OnEnter(GameState::MainMenu), main_menu_screen)
+            .add_systems(OnEnter(GameState::Settings), settings_screen)
+            .add_systems(OnExit(GameState::Settings), stop_rebinding)
+            .add_systems(OnEnter(GameState::Paused), (pause_screen, pause_physics))
+            .add_systems(OnExit(GameState::Paused), resume_physics)
+            .add_systems(OnEnter(GameState::GameOver), game_over_screen)
+            .add_systems(
+                Update,
+                (
+                    gamepad_connections,
+                    gamepad_menu_navigation.run_if(not(in_state(GameState::Playing))),
+                    button_system,
+                    sound_button,
+                    cycle_button::<ControlScheme>,
+                    highlight_focused_button,
+                )
+                    .chain(),
+            )
+            .add_systems(
+                Update,
+                next_level_button
+                    .run_if(in_state(GameState::GameOver).or(in_state(GameState::MainMenu))),
+            )
+            .add_systems(
+                Update,
+                (cycle_button::<GameMode>, cycle_button::<Walls>)
+                    .run_if(in_state(GameState::MainMenu)),
+            )
+            .add_systems(
+                Update,
+                toggle_pause.run_if(
+                    in_state(GameState::Playing)
+                        .or(in_state(GameState::Paused))
+                        .or(in_state(GameState::Online))
+                        .or(in_state(GameState::PeerVersus)),
+                ),
+            )
+            .add_systems(
+                Update,
+                restart_shortcut.run_if(
+                    in_state(GameState::Playing)
+                        .or(in_state(GameState::Paused))
+                        .or(in_state(GameState::GameOver))
+                        .and(not(resource_exists::<PendingScore>)),
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    capture_rebinding_key.run_if(rebinding_in_progress),
+                    controls_buttons,
+                    update_binding_labels,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Settings)),
+            );
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq)]
+pub enum ButtonAction {
+    Play,
+    Settings,
+    Quit,
+    Resume,
+    MainMenu,
+    ToggleSound,
+    ToggleControlScheme,
+    /// Listens for a new key for the given slot of an action.
+    Rebind(Action, usize),
+    ResetControls,
+    Restart,
+    NextLevel,
+    NextMode,
+    ToggleWalls,
+    NextRivals,
+    TogglePlayers,
+    WatchReplay,
+    PlaybackSpeed,
+    StopReplay,
+}
+
+/// The action and key slot waiting for a key press in the settings screen.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct Rebinding(Option<(Action, usize)>);
+
+/// Full-screen root for menus, dimming whatever is behind it.
+fn menu_root(state: GameState) -> impl Bundle {
+    (
+        DespawnOnExit(state),
+        Node {
+            width: percent(100),
+            height: percent(100),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: px(20),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.6)),
+        GlobalZIndex(1),
+        TabGroup::new(0),
+    )
+}
+
+fn menu_title(title: &str) -> impl Bundle {
+    (
+        Text::new(title),
+        TextFont {
+            font_size: 66.0,
+            ..default()
+        },
+        TextColor(TEXT_COLOR),
+    )
+}
+
+fn main_menu_screen(
+    mut commands: Commands,
+    level: Res<Level>,
+    mode: Res<GameMode>,
+    walls: Res<Walls>,
+    rivals: Res<Rivals>,
+    players: Res<Players>,
+    server: Option<Res<ServerAddress>>,
+    peer: Option<Res<PeerAddress>>,
+) {
+    let play_label = if server.is_some() || peer.is_some() {
+        "Play Online"
+    } else {
+        "Play"
+    };
+    commands
+        .spawn(menu_root(GameState::MainMenu))
+        .with_children(|parent| {
+            parent.spawn(menu_title("Snake"));
+            parent.spawn(button(play_label, ButtonAction::Play));
+            parent.spawn(button(level_button_label(&level), ButtonAction::NextLevel));
+            parent.spawn(setting_button(*mode));
+            parent.spawn(setting_button(*walls));
+            parent.spawn(setting_button(*rivals));
+            parent.spawn(setting_button(*players));
+            parent.spawn(button("Settings", ButtonAction::Settings));
+            // There is nothing to quit to in a browser tab.
+            if cfg!(not(target_arch = "wasm32")) {
+                parent.spawn(button("Quit", ButtonAction::Quit));
+            }
+        });
+}
+
+fn game_over_screen(
+    mut commands: Commands,
+    level: Res<Level>,
+    round_result: Option<Res<RoundResult>>,
+) {
+    commands
+        .spawn((
+            DespawnOnExit(GameState::GameOver),
+            TabGroup::new(0),
+            Node {
+                width: percent(100),
+                height: percent(100),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                column_gap: px(60),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(Node {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: px(20),
+                    ..default()
+                })
+                .with_children(|column| {
+                    match round_result.as_deref() {
+                        Some(RoundResult::Won(player)) => {
+                            column
+                                .spawn(menu_title(&format!("Player {} wins!", player + 1)))
+                                .insert(TextColor(PLAYER_COLORS[*player].0));
+                        }
+                        Some(RoundResult::Draw) => {
+                            column.spawn(menu_title("Draw!"));
+                        }
+                        None => {}
+                    }
+                    column.spawn(button("Restart", ButtonAction::Restart));
+                    column.spawn(button(level_button_label(&level), ButtonAction::NextLevel));
+                    column.spawn(button("Watch replay", ButtonAction::WatchReplay));
+                    column.spawn(button("Main menu", ButtonAction::MainMenu));
+                });
+            parent.spawn((
+                LeaderboardPanel,
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: px(5),
+                    padding: UiRect::all(px(15)),
+                    ..default()
+                },
+                BorderRadius::all(px(10)),
+                BackgroundColor(Color::BLACK.with_alpha(0.7)),
+            ));
+        });
+}
+
+fn settings_screen(
+    mut commands: Commands,
+    global_volume: Res<GlobalVolume>,
+    control_scheme: Res<ControlScheme>,
+    bindings: Res<KeyBindings>,
+) {
+    let controls = commands
+        .spawn(Node {
+            flex_direction: FlexDirection::Column,
+            row_gap: px(8),
+            ..default()
+        })
+        .with_children(|parent| {
+            for action in Action::ALL {
+                parent
+                    .spawn(Node {
+                        align_items: AlignItems::Center,
+                        column_gap: px(10),
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        row.spawn((
+                            Text::new(action.label()),
+                            TextFont {
+                                font_size: 26.0,
+                                ..default()
+                            },
+                            TextColor(TEXT_COLOR),
+                            Node {
+                                width: px(120),
+                                ..default()
+                            },
+                        ));
+                        for slot in 0..KEYS_PER_ACTION {
+                            row.spawn(key_button(
+                                binding_label(&bindings, &Rebinding::default(), action, slot),
+                                ButtonAction::Rebind(action, slot),
+                            ));
+                        }
+                    });
+            }
+        })
+        .id();
+
+    commands
+        .spawn(menu_root(GameState::Settings))
+        .with_children(|parent| {
+            parent.spawn(menu_title("Settings"));
+            parent.spawn(button(
+                sound_button_label(&global_volume),
+                ButtonAction::ToggleSound,
+            ));
+            parent.spawn(setting_button(*control_scheme));
+        })
+        .add_child(controls)
+        .with_children(|parent| {
+            parent.spawn((
+                Node {
+                    column_gap: px(20),
+                    ..default()
+                },
+                children![
+                    button("Reset controls", ButtonAction::ResetControls),
+                    button("Back", ButtonAction::MainMenu),
+                ],
+            ));
+        });
+}
+
+fn key_button(label: impl Into<String>, action: ButtonAction) -> impl Bundle {
+    (
+        Button,
+        action,
+        TabIndex(0),
+        Node {
+            min_width: px(140),
+            height: px(44),
+            border: UiRect::all(px(3)),
+            padding: UiRect::horizontal(px(10)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BorderColor::all(Color::WHITE),
+        BorderRadius::MAX,
+        BackgroundColor(Color::BLACK),
+        children![(
+            Text::new(label),
+            TextFont {
+                font_size: 22.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+        )],
+    )
+}
+
+fn binding_label(
+    bindings: &KeyBindings,
+    rebinding: &Rebinding,
+    action: Action,
+    slot: usize,
+) -> String {
+    if **rebinding == Some((action, slot)) {
+        return String::from("Press a key");
+    }
+    bindings.keys(action)[slot].map_or_else(|| String::from("-"), key_label)
+}
+
+fn rebinding_in_progress(rebinding: Res<Rebinding>) -> bool {
+    rebinding.is_some()
+}
+
+fn stop_rebinding(mut rebinding: ResMut<Rebinding>) {
+    **rebinding = None;
+}
+
+fn save_bindings(bindings: &KeyBindings) {
+    if let Err(error) = bindings.save() {
+        warn!("Could not save the key bindings: {error}");
+    }
+}
+
+/// Binds the next key pressed. Escape cancels and Backspace clears the slot.
+fn capture_rebinding_key(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    let (Some((action, slot)), Some(&key)) =
+        (**rebinding, keyboard_input.get_just_pressed().next())
+    else {
+        return;
+    };
+    match key {
+        KeyCode::Escape => {}
+        KeyCode::Backspace => bindings.bind(action, slot, None),
+        key => bindings.bind(action, slot, Some(key)),
+    }
+    if key != KeyCode::Escape {
+        save_bindings(&bindings);
+    }
+    **rebinding = None;
+}
+
+fn controls_buttons(
+    interaction_query: Query<(&Interaction, &ButtonAction), Changed<Interaction>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    for (interaction, action) in interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *action {
+            ButtonAction::Rebind(action, slot) => **rebinding = Some((action, slot)),
+            ButtonAction::ResetControls => {
+                *bindings = KeyBindings::default();
+                save_bindings(&bindings);
+                **rebinding = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn update_binding_labels(
+    buttons: Query<(&ButtonAction, &Children)>,
+    mut texts: Query<&mut Text>,
+    bindings: Res<KeyBindings>,
+    rebinding: Res<Rebinding>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (action, children) in buttons {
+        let ButtonAction::Rebind(action, slot) = *action else {
+            continue;
+        };
+        if let Some(mut text) = texts.iter_many_mut(children).fetch_next() {
+            **text = binding_label(&bindings, &rebinding, action, slot);
+        }
+    }
+}
+
+fn pause_screen(mut commands: Commands) {
+    commands.spawn((
+        menu_root(GameState::Paused),
+        children![
+            menu_title("Paused"),
+            button("Resume", ButtonAction::Resume),
+            button("Main menu", ButtonAction::MainMenu),
+        ],
+    ));
+}
+
+fn toggle_pause(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    gamepads: Query<&Gamepad>,
+    state: Res<State<GameState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let pause_pressed = bindings.just_pressed(Action::Pause, &keyboard_input);
+    // Start only pauses; in the pause menu it presses the focused button instead.
+    let start_pressed = gamepads
+        .iter()
+        .any(|gamepad| gamepad.just_pressed(GamepadButton::Start));
+    match state.get() {
+        GameState::Playing if pause_pressed || start_pressed => game_state.set(GameState::Paused),
+        GameState::Paused if pause_pressed => game_state.set(GameState::Playing),
+        // Networked games go on, so there is no pausing them; leave instead.
+        GameState::Online | GameState::PeerVersus if pause_pressed || start_pressed => {
+            game_state.set(GameState::MainMenu)
+        }
+        _ => {}
+    }
+}
+
+fn restart_shortcut(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if bindings.just_pressed(Action::Restart, &keyboard_input) {
+        game_state.set(GameState::Restart);
+    }
+}
+
+fn pause_physics(mut time: ResMut<Time<Physics>>) {
+    time.pause();
+}
+
+fn resume_physics(mut time: ResMut<Time<Physics>>) {
+    time.unpause();
+}
+
+fn sound_button_label(global_volume: &GlobalVolume) -> &'static str {
+    if global_volume.volume == Volume::SILENT {
+        "Sound: Off"
+    } else {
+        "Sound: On"
+    }
+}
+
+fn sound_button(
+    interaction_query: Query<(&Interaction, &ButtonAction, &Children), Changed<Interaction>>,
+    mut texts: Query<&mut Text>,
+    mut global_volume: ResMut<GlobalVolume>,
+) {
+    for (interaction, action, children) in interaction_query {
+        if *interaction != Interaction::Pressed || !matches!(action, ButtonAction::ToggleSound) {
+            continue;
+        }
+        global_volume.volume = if global_volume.volume == Volume::SILENT {
+            Volume::Linear(1.0)
+        } else {
+            Volume::SILENT
+        };
+        if let Some(mut text) = texts.iter_many_mut(children).fetch_next() {
+            **text = sound_button_label(&global_volume).to_string();
+        }
+    }
+}
+
+fn level_button_label(level: &Level) -> String {
+    format!("Level: {}", level.name)
+}
+
+/// A setting whose button steps it through its values.
+pub trait MenuSetting: Resource + Copy {
+    /// What the setting's button does.
+    const ACTION: ButtonAction;
+
+    fn next(self) -> Self;
+
+    fn label(self) -> String;
+}
+
+impl MenuSetting for ControlScheme {
+    const ACTION: ButtonAction = ButtonAction::ToggleControlScheme;
+
+    fn next(self) -> Self {
+        self.toggled()
+    }
+
+    fn label(self) -> String {
+        format!("Steer with: {}", ControlScheme::label(self))
+    }
+}
+
+impl MenuSetting for GameMode {
+    const ACTION: ButtonAction = ButtonAction::NextMode;
+
+    fn next(self) -> Self {
+        GameMode::next(self)
+    }
+
+    fn label(self) -> String {
+        format!("Mode: {}", GameMode::label(self))
+    }
+}
+
+impl MenuSetting for Walls {
+    const ACTION: ButtonAction = ButtonAction::ToggleWalls;
+
+    fn next(self) -> Self {
+        self.toggled()
+    }
+
+    fn label(self) -> String {
+        format!("Walls: {}", Walls::label(self))
+    }
+}
+
+impl MenuSetting for Rivals {
+    const ACTION: ButtonAction = ButtonAction::NextRivals;
+
+    fn next(self) -> Self {
+        Rivals::next(self)
+    }
+
+    fn label(self) -> String {
+        format!("Rivals: {}", self.0)
+    }
+}
+
+impl MenuSetting for Players {
+    const ACTION: ButtonAction = ButtonAction::TogglePlayers;
+
+    fn next(self) -> Self {
+        self.toggled()
+    }
+
+    fn label(self) -> String {
+        format!("Players: {}", Players::label(self))
+    }
+}
+
+/// Steps `S` on to its next value when its button is pressed.
+pub fn cycle_button<S: MenuSetting>(
+    interaction_query: Query<(&Interaction, &ButtonAction, &Children), Changed<Interaction>>,
+    mut texts: Query<&mut Text>,
+    mut setting: ResMut<S>,
+) {
+    for (interaction, action, children) in interaction_query {
+        if *interaction != Interaction::Pressed || *action != S::ACTION {
+            continue;
+        }
+        *setting = setting.next();
+        if let Some(mut text) = texts.iter_many_mut(children).fetch_next() {
+            **text = setting.label();
+        }
+    }
+}
+
+pub fn button(label: impl Into<String>, action: ButtonAction) -> impl Bundle {
+    (
+        Button,
+        action,
+        TabIndex(0),
+        Node {
+            min_width: px(200),
+            height: px(65),
+            border: UiRect::all(px(5)),
+            padding: UiRect::horizontal(px(20)),
+            // horizontally center child text
+            justify_content: JustifyContent::Center,
+            // vertically center child text
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BorderColor::all(Color::WHITE),
+        BorderRadius::MAX,
+        BackgroundColor(Color::BLACK),
+        children![(
+            Text::new(label),
+            TextFont {
+                font_size: 33.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            TextShadow::default(),
+        )],
+    )
+}
+
+/// A button showing `setting` that steps it on when pressed.
+fn setting_button<S: MenuSetting>(setting: S) -> impl Bundle {
+    button(setting.label(), S::ACTION)
+}
+
+/// Logs gamepads coming and going, and pauses the game if one is unplugged mid-game.
+fn gamepad_connections(
+    mut connection_events: MessageReader<GamepadConnectionEvent>,
+    state: Res<State<GameState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    for event in connection_events.read() {
+        match &event.connection {
+            GamepadConnection::Connected { name, .. } => info!("Gamepad connected: {name}"),
+            GamepadConnection::Disconnected => {
+                info!("Gamepad disconnected");
+                if *state.get() == GameState::Playing {
+                    game_state.set(GameState::Paused);
+                }
+            }
+        }
+    }
+}
+
+/// Moves the focus between buttons with the D-pad and presses the focused one with A or
+/// Start. With nothing focused yet, A or Start presses the first button, e.g. Restart.
+fn gamepad_menu_navigation(
+    gamepads: Query<&Gamepad>,
+    navigation: TabNavigation,
+    mut input_focus: ResMut<InputFocus>,
+    mut focus_visible: ResMut<InputFocusVisible>,
+    mut interactions: Query<&mut Interaction, With<ButtonAction>>,
+    mut pressed: Local<Option<Entity>>,
+) {
+    // Release the button pressed last frame, like the mouse would.
+    if let Some(entity) = pressed.take()
+        && let Ok(mut interaction) = interactions.get_mut(entity)
+    {
+        interaction.set_if_neq(Interaction::None);
+    }
+
+    for gamepad in &gamepads {
+        let nav_action = if gamepad.just_pressed(GamepadButton::DPadDown)
+            || gamepad.just_pressed(GamepadButton::DPadRight)
+        {
+            Some(NavAction::Next)
+        } else if gamepad.just_pressed(GamepadButton::DPadUp)
+            || gamepad.just_pressed(GamepadButton::DPadLeft)
+        {
+            Some(NavAction::Previous)
+        } else {
+            None
+        };
+        if let Some(nav_action) = nav_action
+            && let Ok(next) = navigation.navigate(&input_focus, nav_action)
+        {
+            input_focus.set(next);
+            focus_visible.0 = true;
+        }
+
+        if !gamepad.any_just_pressed([GamepadButton::South, GamepadButton::Start]) {
+            continue;
+        }
+        let focused = input_focus
+            .get()
+            .filter(|entity| interactions.contains(*entity))
+            .or_else(|| {
+                navigation
+                    .navigate(&InputFocus::default(), NavAction::First)
+                    .ok()
+            });
+        if let Some(entity) = focused
+            && let Ok(mut interaction) = interactions.get_mut(entity)
+        {
+            input_focus.set(entity);
+            focus_visible.0 = true;
+            *interaction = Interaction::Pressed;
+            *pressed = Some(entity);
+        }
+    }
+}
+
+fn highlight_focused_button(
+    input_focus: Res<InputFocus>,
+    focus_visible: Res<InputFocusVisible>,
+    mut buttons: Query<(Entity, &mut BorderColor), With<ButtonAction>>,
+) {
+    if !input_focus.is_changed() && !focus_visible.is_changed() {
+        return;
+    }
+    for (entity, mut border_color) in &mut buttons {
+        let focused = focus_visible.0 && input_focus.get() == Some(entity);
+        *border_color = BorderColor::all(if focused { SCORE_COLOR } else { Color::WHITE });
+    }
+}
+
+fn button_system(
+    mut input_focus: ResMut<InputFocus>,
+    mut focus_visible: ResMut<InputFocusVisible>,
+    mut interaction_query: Query<
+        (Entity, &Interaction, &mut Button, &ButtonAction),
+        Changed<Interaction>,
+    >,
+    mut game_state: ResMut<NextState<GameState>>,
+    player: Query<(), With<Player>>,
+    server: Option<Res<ServerAddress>>,
+    peer: Option<Res<PeerAddress>>,
+    mut app_exit: MessageWriter<AppExit>,
+) {
+    for (entity, interaction, mut button, action) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                input_focus.set(entity);
+
+                match action {
+                    ButtonAction::Play if server.is_some() => game_state.set(GameState::Online),
+                    ButtonAction::Play if peer.is_some() => game_state.set(GameState::PeerVersus),
+                    // The world only needs setting up from scratch for the very first game.
+                    ButtonAction::Play if !player.is_empty() => game_state.set(GameState::Restart),
+                    ButtonAction::Play => game_state.set(GameState::Start),
+                    ButtonAction::Settings => game_state.set(GameState::Settings),
+                    ButtonAction::Quit => {
+                        app_exit.write(AppExit::Success);
+                    }
+                    ButtonAction::Resume => game_state.set(GameState::Playing),
+                    ButtonAction::MainMenu => game_state.set(GameState::MainMenu),
+                    ButtonAction::Restart => game_state.set(GameState::Restart),
+                    ButtonAction::WatchReplay => game_state.set(GameState::Replay),
+                    ButtonAction::StopReplay => game_state.set(GameState::GameOver),
+                    ButtonAction::NextLevel
+                    | ButtonAction::NextMode
+                    | ButtonAction::ToggleWalls
+                    | ButtonAction::NextRivals
+                    | ButtonAction::TogglePlayers
+                    | ButtonAction::PlaybackSpeed
+                    | ButtonAction::ToggleSound
+                    | ButtonAction::ToggleControlScheme
+                    | ButtonAction::Rebind(..)
+                    | ButtonAction::ResetControls => {}
+                }
+
+                // The accessibility system's only update the button's state when the `Button` component is marked as changed.
+                button.set_changed();
+            }
+            Interaction::Hovered => {
+                input_focus.set(entity);
+                focus_visible.0 = false;
+                button.set_changed();
+            }
+            // Keep the focus a gamepad moved here.
+            Interaction::None if !focus_visible.0 => {
+                input_focus.clear();
+            }
+            Interaction::None => {}
+        }
+    }
+}
+
+fn next_level_button(
+    interaction_query: Query<(&Interaction, &ButtonAction, &Children), Changed<Interaction>>,
+    mut texts: Query<&mut Text>,
+    mut selected_level: ResMut<SelectedLevel>,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<Level>>,
+    mut commands: Commands,
+) {
+    for (interaction, action, children) in interaction_query {
+        if *interaction != Interaction::Pressed || !matches!(action, ButtonAction::NextLevel) {
+            continue;
+        }
+        **selected_level = (**selected_level + 1) % level_assets.levels.len();
+        let Some(level) = levels.get(&level_assets.levels[**selected_level]) else {
+            continue;
+        };
+        if let Some(mut text) = texts.iter_many_mut(children).fetch_next() {
+            **text = level_button_label(level);
+        }
+        commands.insert_resource(level.clone());
+    }
+}
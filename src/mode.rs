@@ -0,0 +1,314 @@
+//! Game modes the player can pick from the main menu, and the steering rules they share
+//! with networked play.
+
+use std::f32::consts::{PI, TAU};
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::replay::{Direction, Input};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::resource::Resource))]
+pub enum GameMode {
+    /// Up/down/left/right sets the direction of travel.
+    #[default]
+    Classic,
+    /// Left/right turn the head at the config's turn rate, or an analog stick sets the
+    /// heading, and the snake keeps moving once it has started.
+    Steering,
+    /// Traditional tile-based snake: the head hops a cell at a time on a fixed beat.
+    Grid,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 3] = [GameMode::Classic, GameMode::Steering, GameMode::Grid];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GameMode::Classic => "Classic",
+            GameMode::Steering => "Steering",
+            GameMode::Grid => "Grid",
+        }
+    }
+
+    /// The mode after this one in [`GameMode::ALL`], wrapping around.
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|mode| *mode == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    /// The velocity a snake going at `velocity`, with its head facing `heading`, takes on
+    /// from `input` when it moves `step` a tick and turns at most `max_turn` radians a
+    /// tick. Grid snakes hop from cell to cell instead, so they keep their velocity.
+    pub fn steer(
+        self,
+        input: Option<Input>,
+        velocity: Vec2,
+        heading: Vec2,
+        step: f32,
+        max_turn: f32,
+    ) -> Vec2 {
+        match self {
+            GameMode::Grid => velocity,
+            GameMode::Classic => {
+                let travel = travel_direction(velocity, heading);
+                let wanted = match input {
+                    Some(Input::Direction(direction)) => Some(direction.as_vec2()),
+                    Some(Input::Heading(steps)) => {
+                        Some(Vec2::from_angle(Input::heading_angle(steps)))
+                    }
+                    None => None,
+                };
+                match wanted {
+                    Some(wanted) if can_turn(wanted, travel) => wanted * step,
+                    // Going straight on still picks up the current step, so speed changes
+                    // take effect without waiting for a turn.
+                    _ if velocity != Vec2::ZERO => travel * step,
+                    _ => velocity,
+                }
+            }
+            GameMode::Steering => {
+                let heading = heading.to_angle();
+                let heading = match input {
+                    Some(Input::Direction(Direction::Left)) => heading + max_turn,
+                    Some(Input::Direction(Direction::Right)) => heading - max_turn,
+                    Some(Input::Heading(steps)) => {
+                        turn_towards(heading, Input::heading_angle(steps), max_turn)
+                    }
+                    Some(Input::Direction(Direction::Up | Direction::Down)) | None => heading,
+                };
+                // The snake sets off with the first input and keeps going until it hits
+                // something.
+                if input.is_some() || velocity != Vec2::ZERO {
+                    Vec2::from_angle(heading) * step
+                } else {
+                    velocity
+                }
+            }
+        }
+    }
+}
+
+/// What happens at the arena's outer walls. Interior obstacles are always solid.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::resource::Resource))]
+pub enum Walls {
+    /// Running into them ends the game.
+    #[default]
+    Solid,
+    /// They are portals: the head leaves through one and comes back through the opposite one.
+    Wrap,
+}
+
+impl Walls {
+    pub fn label(self) -> &'static str {
+        match self {
+            Walls::Solid => "Solid",
+            Walls::Wrap => "Wrap",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Walls::Solid => Walls::Wrap,
+            Walls::Wrap => Walls::Solid,
+        }
+    }
+}
+
+/// How many people play on the one keyboard.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::resource::Resource))]
+pub enum Players {
+    #[default]
+    Solo,
+    /// Two players, one on WASD and one on the arrow keys, each with their own snake. The
+    /// round is over as soon as one of them crashes.
+    Versus,
+}
+
+impl Players {
+    pub fn label(self) -> &'static str {
+        match self {
+            Players::Solo => "1",
+            Players::Versus => "2",
+        }
+    }
+
+    pub fn toggled(self) -> Self {
+        match self {
+            Players::Solo => Players::Versus,
+            Players::Versus => Players::Solo,
+        }
+    }
+
+    pub fn count(self) -> usize {
+        match self {
+            Players::Solo => 1,
+            Players::Versus => 2,
+        }
+    }
+}
+
+/// How a versus round ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::resource::Resource))]
+pub enum RoundResult {
+    /// The player with this index was the only one left.
+    Won(usize),
+    /// Everyone crashed on the same tick.
+    Draw,
+}
+
+impl RoundResult {
+    /// The result once the players flagged in `crashed` have crashed, or `None` while more
+    /// than one player is still going.
+    pub fn from_crashes(crashed: &[bool]) -> Option<Self> {
+        let mut survivors = crashed.iter().enumerate().filter(|(_, crashed)| !**crashed);
+        match (survivors.next(), survivors.next()) {
+            (None, _) => Some(RoundResult::Draw),
+            (Some((winner, _)), None) => Some(RoundResult::Won(winner)),
+            (Some(_), Some(_)) => None,
+        }
+    }
+}
+
+/// The way a snake going at `velocity`, with its head facing `heading`, travels. Until it
+/// sets off its velocity is zero, and that is the way it faces.
+pub fn travel_direction(velocity: Vec2, heading: Vec2) -> Vec2 {
+    if velocity == Vec2::ZERO {
+        heading
+    } else {
+        velocity.normalize()
+    }
+}
+
+/// Whether a snake travelling along `travel` may turn to go along `direction`, whether it
+/// came from the keys or a heading from the pointer or a stick. Turning back would drive
+/// the head into the neck.
+pub fn can_turn(direction: Vec2, travel: Vec2) -> bool {
+    direction.dot(travel) >= 0.0
+}
+
+/// Turns `heading` towards `target` by at most `max_turn`, the short way round.
+/// All angles are in radians.
+pub fn turn_towards(heading: f32, target: f32, max_turn: f32) -> f32 {
+    let difference = (target - heading + PI).rem_euclid(TAU) - PI;
+    heading + difference.clamp(-max_turn, max_turn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    #[test]
+    fn modes_cycle() {
+        assert_eq!(GameMode::Classic.next(), GameMode::Steering);
+        assert_eq!(GameMode::Steering.next(), GameMode::Grid);
+        assert_eq!(GameMode::Grid.next(), GameMode::Classic);
+    }
+
+    #[test]
+    fn classic_snakes_never_turn_straight_back() {
+        let up = Some(Input::Direction(Direction::Up));
+        let down = Some(Input::Direction(Direction::Down));
+        let velocity = GameMode::Classic.steer(up, Vec2::ZERO, Vec2::X, 2.0, 0.0);
+        assert_eq!(velocity, Vec2::Y * 2.0);
+        assert_eq!(
+            GameMode::Classic.steer(down, velocity, Vec2::X, 2.0, 0.0),
+            velocity
+        );
+        // Before it sets off, the snake cannot turn back into its neck either.
+        let left = Some(Input::Direction(Direction::Left));
+        assert_eq!(
+            GameMode::Classic.steer(left, Vec2::ZERO, Vec2::X, 2.0, 0.0),
+            Vec2::ZERO
+        );
+    }
+
+    #[test]
+    fn classic_snakes_going_straight_on_pick_up_a_new_speed() {
+        let velocity = Vec2::X * 2.0;
+        assert_eq!(
+            GameMode::Classic.steer(None, velocity, Vec2::X, 3.0, 0.0),
+            Vec2::X * 3.0
+        );
+        let back = Some(Input::Direction(Direction::Left));
+        assert_eq!(
+            GameMode::Classic.steer(back, velocity, Vec2::X, 1.0, 0.0),
+            Vec2::X
+        );
+        assert_eq!(
+            GameMode::Classic.steer(None, Vec2::ZERO, Vec2::X, 3.0, 0.0),
+            Vec2::ZERO
+        );
+    }
+
+    #[test]
+    fn classic_headings_cannot_turn_back_either() {
+        let behind = Some(Input::heading(PI * 0.9));
+        let velocity = Vec2::X * 2.0;
+        assert_eq!(
+            GameMode::Classic.steer(behind, velocity, Vec2::X, 2.0, 0.0),
+            velocity
+        );
+        let ahead = Some(Input::heading(0.25));
+        let velocity = GameMode::Classic.steer(ahead, velocity, Vec2::X, 2.0, 0.0);
+        assert!((velocity.to_angle() - 0.25).abs() < 0.02);
+    }
+
+    #[test]
+    fn steering_snakes_turn_at_most_the_turn_rate_once_they_set_off() {
+        let left = Some(Input::Direction(Direction::Left));
+        assert_eq!(
+            GameMode::Steering.steer(None, Vec2::ZERO, Vec2::X, 2.0, 0.1),
+            Vec2::ZERO
+        );
+        let velocity = GameMode::Steering.steer(left, Vec2::ZERO, Vec2::X, 2.0, 0.1);
+        assert!((velocity.to_angle() - 0.1).abs() < EPSILON);
+        assert!((velocity.length() - 2.0).abs() < EPSILON);
+        let velocity = GameMode::Steering.steer(None, velocity, velocity.normalize(), 2.0, 0.1);
+        assert!((velocity.to_angle() - 0.1).abs() < EPSILON);
+    }
+
+    #[test]
+    fn grid_snakes_keep_their_velocity() {
+        let up = Some(Input::Direction(Direction::Up));
+        assert_eq!(
+            GameMode::Grid.steer(up, Vec2::X, Vec2::X, 2.0, 0.1),
+            Vec2::X
+        );
+    }
+
+    #[test]
+    fn the_last_player_left_wins_the_round() {
+        assert_eq!(
+            RoundResult::from_crashes(&[false, true]),
+            Some(RoundResult::Won(0))
+        );
+        assert_eq!(
+            RoundResult::from_crashes(&[true, false]),
+            Some(RoundResult::Won(1))
+        );
+        assert_eq!(
+            RoundResult::from_crashes(&[true, true]),
+            Some(RoundResult::Draw)
+        );
+        assert_eq!(RoundResult::from_crashes(&[false, false]), None);
+    }
+
+    #[test]
+    fn turns_the_short_way_round() {
+        // From just below +pi to just above -pi is a small counter-clockwise turn.
+        let heading = turn_towards(PI - 0.1, -PI + 0.1, 1.0);
+        assert!((heading - (PI + 0.1)).abs() < EPSILON);
+
+        let heading = turn_towards(0.0, 2.0, 0.5);
+        assert!((heading - 0.5).abs() < EPSILON);
+        let heading = turn_towards(0.0, -0.2, 0.5);
+        assert!((heading + 0.2).abs() < EPSILON);
+    }
+}
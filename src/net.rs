@@ -0,0 +1,37 @@
+//! Networked play, against an authoritative server or peer-to-peer.
+//!
+//! The server runs a headless [`Simulation`] and sends every client a [`Snapshot`] of all
+//! the snakes each tick, packed small and split over as many datagrams as it takes for the
+//! snakes to grow. Clients only send their steering input, and draw the snapshots
+//! a little in the past through a [`SnapshotBuffer`] so the snakes move smoothly between
+//! them. Everything runs over UDP and needs neither Bevy nor a window, so a [`Server`] and
+//! a scripted [`bot`] can play a whole game on localhost.
+//!
+//! Two players can also meet without a server: each [`Peer`] runs the whole game itself
+//! in a [`RollbackSession`], guessing at the other player's inputs and rolling back to a
+//! saved [`SimulationState`] whenever a guess turns out wrong.
+//!
+//! Both kinds of networked play run the [`Simulation`], not the local game: the local
+//! game's state lives in the Bevy world and its collisions in the physics engine, neither
+//! of which can be sent as a snapshot or rolled back. The simulation shares the steering,
+//! growth, spawning and apple placement rules with the local game, but plays a reduced
+//! rule set: classic mode only, solid outer walls, no power-ups or rivals, and crashes
+//! found by measuring distances to the joints rather than by colliders.
+
+pub mod bot;
+mod client;
+mod interpolation;
+mod packing;
+mod peer;
+mod protocol;
+mod rollback;
+mod server;
+mod simulation;
+
+pub use client::*;
+pub use interpolation::*;
+pub use peer::*;
+pub use protocol::*;
+pub use rollback::*;
+pub use server::*;
+pub use simulation::*;
@@ -0,0 +1,92 @@
+//! A scripted player that steers like a rival, for trying out a server without
+//! anyone at the keyboard.
+
+use glam::Vec2;
+
+use super::Snapshot;
+use crate::{
+    config::SnakeConfig,
+    level::Level,
+    replay::Input,
+    rival::{Steer, View},
+};
+
+/// What `brain` would have `player`'s snake do in `snapshot`, or `None` while the snake is
+/// dead or not in the game.
+pub fn bot_input(
+    brain: &dyn Steer,
+    player: usize,
+    snapshot: &Snapshot,
+    level: &Level,
+    config: &SnakeConfig,
+) -> Option<Input> {
+    let own = snapshot
+        .snake(player)
+        .filter(|snake| snake.alive && snake.segments.len() >= 2)?;
+    let head = own.head()?;
+    let neck = own.segments[own.segments.len() - 2];
+    let apples: Vec<Vec2> = snapshot.apples.iter().map(|apple| apple.position).collect();
+    // Leave out the bot's own head, which every point it looks at is close to.
+    let bodies: Vec<Vec2> = snapshot
+        .snakes
+        .iter()
+        .filter(|snake| snake.alive)
+        .flat_map(|snake| {
+            let own_head = usize::from(snake.player == player);
+            &snake.segments[..snake.segments.len() - own_head]
+        })
+        .copied()
+        .collect();
+    // Like a hunting rival, go after whichever other snake is closest.
+    let prey = snapshot
+        .snakes
+        .iter()
+        .filter(|snake| snake.player != player && snake.alive && snake.segments.len() >= 2)
+        .filter_map(|snake| {
+            let head = snake.head()?;
+            Some((head, head - snake.segments[snake.segments.len() - 2]))
+        })
+        .min_by(|(a, _), (b, _)| a.distance(head).total_cmp(&b.distance(head)));
+    let view = View {
+        head,
+        heading: (head - neck).to_angle(),
+        apples: &apples,
+        level,
+        bodies: &bodies,
+        prey,
+        lookahead: config.rival_lookahead,
+        clearance: config.head_thickness / 2.0,
+    };
+    Some(Input::heading(brain.steer(&view)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        net::{AppleState, SnakeState},
+        rival::Greedy,
+    };
+
+    #[test]
+    fn bots_head_for_the_nearest_apple() {
+        let level = Level::default();
+        let snapshot = Snapshot {
+            tick: 0,
+            snakes: vec![SnakeState {
+                player: 3,
+                segments: vec![Vec2::new(40.0, 0.0), Vec2::new(20.0, 0.0), Vec2::ZERO],
+                score: 0,
+                alive: true,
+            }],
+            apples: vec![AppleState {
+                kind: 0,
+                position: Vec2::new(0.0, 200.0),
+            }],
+        };
+        let config = SnakeConfig::default();
+        let input = bot_input(&Greedy, 3, &snapshot, &level, &config);
+        assert_eq!(input, Some(Input::heading(Vec2::Y.to_angle())));
+        assert_eq!(bot_input(&Greedy, 0, &snapshot, &level, &config), None);
+    }
+}
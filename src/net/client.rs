@@ -0,0 +1,119 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use web_time::Instant;
+
+use super::{ClientMessage, MAX_DATAGRAM_SIZE, MessageAssembler, ServerMessage};
+use crate::replay::Input;
+
+/// How often to ask to join again while the server has not answered.
+const JOIN_RETRY: Duration = Duration::from_millis(250);
+/// Longest the client goes without sending anything, so the server knows it is still
+/// there and gets the input again should it have been lost.
+const KEEPALIVE: Duration = Duration::from_secs(1);
+
+/// One player's end of a game on a [`super::Server`].
+pub struct Client {
+    socket: UdpSocket,
+    assembler: MessageAssembler,
+    player: Option<usize>,
+    input: Option<Input>,
+    last_sent: Instant,
+}
+
+impl Client {
+    /// Starts joining the server at `address`. The player is known once
+    /// [`Client::poll`] returns a [`ServerMessage::Welcome`].
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "the server address resolved to nothing",
+            )
+        })?;
+        let local: SocketAddr = if address.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(address)?;
+        socket.set_nonblocking(true)?;
+        let mut client = Self {
+            socket,
+            assembler: MessageAssembler::default(),
+            player: None,
+            input: None,
+            last_sent: Instant::now(),
+        };
+        client.send(&ClientMessage::Join)?;
+        Ok(client)
+    }
+
+    /// The player the server gave this client, once it has.
+    pub fn player(&self) -> Option<usize> {
+        self.player
+    }
+
+    /// Steers with `input` until the next one is sent.
+    pub fn send_input(&mut self, input: Option<Input>) -> io::Result<()> {
+        self.input = input;
+        self.send(&ClientMessage::Input(input))
+    }
+
+    pub fn leave(&mut self) -> io::Result<()> {
+        self.send(&ClientMessage::Leave)
+    }
+
+    /// Every message that has arrived from the server. Until the server has welcomed the
+    /// client this also asks to join again now and then, and after that repeats the last
+    /// input when nothing was sent for a while.
+    pub fn poll(&mut self) -> io::Result<Vec<ServerMessage>> {
+        let mut messages = Vec::new();
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let length = match self.socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                // The server is not up (yet); keep trying to join.
+                Err(error) if error.kind() == ErrorKind::ConnectionRefused => break,
+                Err(error) => return Err(error),
+            };
+            let Some(message) = self.assembler.push(&buffer[..length]) else {
+                continue;
+            };
+            if let ServerMessage::Welcome { player, .. } = message {
+                self.player = Some(player);
+            }
+            messages.push(message);
+        }
+
+        match self.player {
+            None if self.last_sent.elapsed() >= JOIN_RETRY => self.send(&ClientMessage::Join)?,
+            Some(_) if self.last_sent.elapsed() >= KEEPALIVE => {
+                self.send(&ClientMessage::Input(self.input))?
+            }
+            _ => {}
+        }
+        Ok(messages)
+    }
+
+    fn send(&mut self, message: &ClientMessage) -> io::Result<()> {
+        self.last_sent = Instant::now();
+        match self.socket.send(&message.encode()) {
+            // Lost like any datagram could be; the keepalive sends the input again.
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::ConnectionRefused
+                ) =>
+            {
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+}
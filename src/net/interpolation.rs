@@ -0,0 +1,232 @@
+use std::collections::VecDeque;
+
+use super::{SnakeState, Snapshot};
+
+/// Snapshots kept around to interpolate between.
+const CAPACITY: usize = 32;
+/// How many ticks behind the newest snapshot the snakes are drawn, so there usually is a
+/// newer snapshot to move towards even when one gets lost or arrives late.
+pub const INTERPOLATION_DELAY_TICKS: f32 = 2.0;
+/// How far the render clock may drift from where it should be before it jumps there.
+const MAX_DRIFT_TICKS: f32 = 6.0;
+
+/// The latest snapshots from the server, and a render clock running a little behind them.
+pub struct SnapshotBuffer {
+    tick_seconds: f32,
+    snapshots: VecDeque<Snapshot>,
+    render_tick: Option<f32>,
+}
+
+impl SnapshotBuffer {
+    pub fn new(tick_seconds: f32) -> Self {
+        Self {
+            tick_seconds,
+            snapshots: VecDeque::new(),
+            render_tick: None,
+        }
+    }
+
+    /// Adds a snapshot. Ones for ticks already seen, or for ticks older than the oldest
+    /// kept, are dropped since UDP may deliver them twice or out of order.
+    pub fn push(&mut self, snapshot: Snapshot) {
+        if self.snapshots.iter().any(|kept| kept.tick == snapshot.tick)
+            || self.snapshots.len() == CAPACITY
+                && self
+                    .snapshots
+                    .front()
+                    .is_some_and(|oldest| snapshot.tick < oldest.tick)
+        {
+            return;
+        }
+        let index = self
+            .snapshots
+            .partition_point(|kept| kept.tick < snapshot.tick);
+        self.snapshots.insert(index, snapshot);
+        if self.snapshots.len() > CAPACITY {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.snapshots.back()
+    }
+
+    /// The game at `tick`, which may fall between two snapshots. Before the first and
+    /// after the last snapshot it is the nearest one.
+    pub fn sample(&self, tick: f32) -> Option<Snapshot> {
+        let after = self
+            .snapshots
+            .partition_point(|snapshot| (snapshot.tick as f32) < tick);
+        let Some(newer) = self.snapshots.get(after) else {
+            return self.latest().cloned();
+        };
+        let Some(older) = after
+            .checked_sub(1)
+            .and_then(|before| self.snapshots.get(before))
+        else {
+            return Some(newer.clone());
+        };
+        let fraction = (tick - older.tick as f32) / (newer.tick - older.tick) as f32;
+        Some(interpolate(older, newer, fraction))
+    }
+
+    /// Moves the render clock on by `seconds` and returns the game as it should be drawn
+    /// now, or `None` before the first snapshot.
+    pub fn advance(&mut self, seconds: f32) -> Option<Snapshot> {
+        let target = self.latest()?.tick as f32 - INTERPOLATION_DELAY_TICKS;
+        let render_tick = match self.render_tick {
+            Some(tick) if (tick - target).abs() <= MAX_DRIFT_TICKS => {
+                tick + seconds / self.tick_seconds
+            }
+            _ => target,
+        };
+        self.render_tick = Some(render_tick);
+        self.sample(render_tick)
+    }
+}
+
+/// The game `fraction` of the way from `older` to `newer`.
+fn interpolate(older: &Snapshot, newer: &Snapshot, fraction: f32) -> Snapshot {
+    let nearer = if fraction < 0.5 { older } else { newer };
+    Snapshot {
+        tick: nearer.tick,
+        snakes: newer
+            .snakes
+            .iter()
+            .map(|snake| match older.snake(snake.player) {
+                // A snake that crashed or respawned in between jumps.
+                Some(before) if before.alive == snake.alive => {
+                    interpolate_snake(before, snake, fraction)
+                }
+                _ => snake.clone(),
+            })
+            .collect(),
+        apples: nearer.apples.clone(),
+    }
+}
+
+/// Joints are matched up from the head end, as that is where a snake that grew or shrank
+/// in between still lines up. Tail joints only one of them has come from `newer`.
+fn interpolate_snake(older: &SnakeState, newer: &SnakeState, fraction: f32) -> SnakeState {
+    let offset = newer.segments.len() as isize - older.segments.len() as isize;
+    let segments = newer
+        .segments
+        .iter()
+        .enumerate()
+        .map(|(index, &position)| {
+            let before = usize::try_from(index as isize - offset).ok();
+            let before = before.and_then(|index| older.segments.get(index));
+            before.map_or(position, |before| before.lerp(position, fraction))
+        })
+        .collect();
+    SnakeState {
+        player: newer.player,
+        segments,
+        score: if fraction < 0.5 {
+            older.score
+        } else {
+            newer.score
+        },
+        alive: newer.alive,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn snapshot(tick: u32, segments: Vec<Vec2>) -> Snapshot {
+        Snapshot {
+            tick,
+            snakes: vec![SnakeState {
+                player: 0,
+                segments,
+                score: tick as usize,
+                alive: true,
+            }],
+            apples: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn samples_between_snapshots_are_interpolated() {
+        let mut buffer = SnapshotBuffer::new(1.0 / 64.0);
+        buffer.push(snapshot(10, vec![Vec2::ZERO, Vec2::X]));
+        buffer.push(snapshot(12, vec![Vec2::new(2.0, 0.0), Vec2::new(3.0, 0.0)]));
+
+        let sample = buffer.sample(11.0).unwrap();
+        let segments = &sample.snakes[0].segments;
+        assert!(segments[0].distance(Vec2::new(1.0, 0.0)) < EPSILON);
+        assert!(segments[1].distance(Vec2::new(2.0, 0.0)) < EPSILON);
+
+        assert_eq!(buffer.sample(5.0).unwrap().tick, 10);
+        assert_eq!(buffer.sample(20.0).unwrap().tick, 12);
+    }
+
+    #[test]
+    fn grown_snakes_line_up_from_the_head() {
+        let mut buffer = SnapshotBuffer::new(1.0 / 64.0);
+        buffer.push(snapshot(0, vec![Vec2::ZERO, Vec2::X]));
+        buffer.push(snapshot(1, vec![Vec2::NEG_X, Vec2::Y, Vec2::new(1.0, 1.0)]));
+
+        let sample = buffer.sample(0.5).unwrap();
+        let segments = &sample.snakes[0].segments;
+        assert_eq!(segments.len(), 3);
+        assert!(segments[0].distance(Vec2::NEG_X) < EPSILON);
+        assert!(segments[1].distance(Vec2::new(0.0, 0.5)) < EPSILON);
+        assert!(segments[2].distance(Vec2::new(1.0, 0.5)) < EPSILON);
+    }
+
+    #[test]
+    fn late_and_duplicate_snapshots_are_dropped() {
+        let mut buffer = SnapshotBuffer::new(1.0 / 64.0);
+        for tick in 0..CAPACITY as u32 + 4 {
+            buffer.push(snapshot(tick, vec![Vec2::ZERO, Vec2::X]));
+        }
+        buffer.push(snapshot(1, vec![Vec2::ZERO, Vec2::Y]));
+        buffer.push(snapshot(CAPACITY as u32, vec![Vec2::ZERO, Vec2::Y]));
+        assert_eq!(buffer.snapshots.len(), CAPACITY);
+        assert_eq!(buffer.snapshots.front().unwrap().tick, 4);
+        assert!(
+            buffer
+                .snapshots
+                .iter()
+                .all(|snapshot| snapshot.snakes[0].segments[1] == Vec2::X)
+        );
+
+        // One that arrives out of order still goes in its place.
+        let mut buffer = SnapshotBuffer::new(1.0 / 64.0);
+        buffer.push(snapshot(3, Vec::new()));
+        buffer.push(snapshot(1, Vec::new()));
+        buffer.push(snapshot(2, Vec::new()));
+        let ticks: Vec<u32> = buffer
+            .snapshots
+            .iter()
+            .map(|snapshot| snapshot.tick)
+            .collect();
+        assert_eq!(ticks, [1, 2, 3]);
+    }
+
+    #[test]
+    fn the_render_clock_trails_the_latest_snapshot() {
+        let tick_seconds = 1.0 / 64.0;
+        let mut buffer = SnapshotBuffer::new(tick_seconds);
+        assert!(buffer.advance(tick_seconds).is_none());
+        for tick in 0..10 {
+            buffer.push(snapshot(tick, vec![Vec2::ZERO, Vec2::X * tick as f32]));
+        }
+        assert_eq!(buffer.advance(tick_seconds).unwrap().tick, 7);
+        let sample = buffer.advance(tick_seconds / 2.0).unwrap();
+        assert!(sample.snakes[0].segments[1].distance(Vec2::X * 7.5) < EPSILON);
+
+        // After a long stall it catches up instead of crawling through old snapshots.
+        for tick in 10..40 {
+            buffer.push(snapshot(tick, vec![Vec2::ZERO, Vec2::X]));
+        }
+        assert_eq!(buffer.advance(tick_seconds).unwrap().tick, 37);
+    }
+}
@@ -0,0 +1,209 @@
+//! The compact binary form snapshots travel in. Positions are rounded to
+//! [`POSITION_STEP`], and every joint after a snake's first is sent as its offset from the
+//! one before, so most joints take four bytes. Numbers are LEB128 varints, zigzag-encoded
+//! when they can be negative.
+
+use glam::Vec2;
+
+use super::{AppleState, SnakeState, Snapshot};
+
+/// What positions are rounded to, a small fraction of a pixel.
+pub(super) const POSITION_STEP: f32 = 1.0 / 16.0;
+
+/// Everything in `snapshot` but its tick, which goes in the datagram headers.
+pub(super) fn pack(snapshot: &Snapshot) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_unsigned(&mut bytes, snapshot.snakes.len() as u64);
+    for snake in &snapshot.snakes {
+        write_unsigned(&mut bytes, snake.player as u64);
+        write_unsigned(&mut bytes, snake.score as u64);
+        bytes.push(u8::from(snake.alive));
+        write_unsigned(&mut bytes, snake.segments.len() as u64);
+        let mut previous = [0_i64; 2];
+        for &joint in &snake.segments {
+            let joint = quantise(joint);
+            write_signed(&mut bytes, joint[0].wrapping_sub(previous[0]));
+            write_signed(&mut bytes, joint[1].wrapping_sub(previous[1]));
+            previous = joint;
+        }
+    }
+    write_unsigned(&mut bytes, snapshot.apples.len() as u64);
+    for apple in &snapshot.apples {
+        write_unsigned(&mut bytes, apple.kind as u64);
+        let [x, y] = quantise(apple.position);
+        write_signed(&mut bytes, x);
+        write_signed(&mut bytes, y);
+    }
+    bytes
+}
+
+/// The snapshot for `tick` that `bytes` were packed from, or `None` if they were not.
+pub(super) fn unpack(tick: u32, bytes: &[u8]) -> Option<Snapshot> {
+    let mut reader = Reader(bytes);
+    let mut snakes = Vec::new();
+    for _ in 0..reader.unsigned()? {
+        let player = reader.length()?;
+        let score = reader.length()?;
+        let alive = match reader.byte()? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        let mut segments = Vec::new();
+        let mut previous = [0_i64; 2];
+        for _ in 0..reader.unsigned()? {
+            let joint = [
+                previous[0].wrapping_add(reader.signed()?),
+                previous[1].wrapping_add(reader.signed()?),
+            ];
+            segments.push(dequantise(joint));
+            previous = joint;
+        }
+        snakes.push(SnakeState {
+            player,
+            segments,
+            score,
+            alive,
+        });
+    }
+    let mut apples = Vec::new();
+    for _ in 0..reader.unsigned()? {
+        let kind = reader.length()?;
+        let position = dequantise([reader.signed()?, reader.signed()?]);
+        apples.push(AppleState { kind, position });
+    }
+    reader.0.is_empty().then_some(Snapshot {
+        tick,
+        snakes,
+        apples,
+    })
+}
+
+fn quantise(position: Vec2) -> [i64; 2] {
+    (position / POSITION_STEP).round().as_i64vec2().to_array()
+}
+
+fn dequantise([x, y]: [i64; 2]) -> Vec2 {
+    Vec2::new(x as f32, y as f32) * POSITION_STEP
+}
+
+fn write_unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_signed(bytes: &mut Vec<u8>, value: i64) {
+    write_unsigned(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+/// Reads what the `write_` functions wrote, front to back. Every read is `None` once the
+/// bytes run out or make no sense.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Option<u8> {
+        let (&byte, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(byte)
+    }
+
+    fn unsigned(&mut self) -> Option<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn signed(&mut self) -> Option<i64> {
+        let value = self.unsigned()?;
+        Some((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn length(&mut self) -> Option<usize> {
+        usize::try_from(self.unsigned()?).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints_round_trip() {
+        let mut bytes = Vec::new();
+        let unsigned = [0, 1, 127, 128, 300, u64::MAX];
+        let signed = [0, -1, 1, -64, 64, i64::MIN, i64::MAX];
+        for value in unsigned {
+            write_unsigned(&mut bytes, value);
+        }
+        for value in signed {
+            write_signed(&mut bytes, value);
+        }
+
+        let mut reader = Reader(&bytes);
+        for value in unsigned {
+            assert_eq!(reader.unsigned(), Some(value));
+        }
+        for value in signed {
+            assert_eq!(reader.signed(), Some(value));
+        }
+        assert_eq!(reader.byte(), None);
+    }
+
+    #[test]
+    fn positions_are_rounded_to_the_step() {
+        let snapshot = Snapshot {
+            tick: 3,
+            snakes: vec![SnakeState {
+                player: 0,
+                segments: vec![Vec2::new(0.01, -250.3), Vec2::new(20.04, -250.29)],
+                score: 0,
+                alive: false,
+            }],
+            apples: vec![AppleState {
+                kind: 2,
+                position: Vec2::new(123.456, -0.02),
+            }],
+        };
+        let unpacked = unpack(3, &pack(&snapshot)).unwrap();
+        let sent = snapshot.snakes[0]
+            .segments
+            .iter()
+            .chain([&snapshot.apples[0].position]);
+        let received = unpacked.snakes[0]
+            .segments
+            .iter()
+            .chain([&unpacked.apples[0].position]);
+        for (sent, received) in sent.zip(received) {
+            assert!(sent.distance(*received) <= POSITION_STEP);
+        }
+        assert_eq!(unpacked.apples[0].kind, 2);
+        assert!(!unpacked.snakes[0].alive);
+    }
+
+    #[test]
+    fn truncated_or_padded_bytes_are_rejected() {
+        let snapshot = Snapshot {
+            tick: 0,
+            snakes: vec![SnakeState {
+                player: 1,
+                segments: vec![Vec2::ZERO, Vec2::X * 20.0],
+                score: 9,
+                alive: true,
+            }],
+            apples: Vec::new(),
+        };
+        let mut bytes = pack(&snapshot);
+        assert_eq!(unpack(0, &bytes[..bytes.len() - 1]), None);
+        bytes.push(0);
+        assert_eq!(unpack(0, &bytes), None);
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use web_time::Instant;
+
+use super::{MAX_DATAGRAM_SIZE, PeerInputs, RollbackSession, Simulation, protocol};
+use crate::{config::SnakeConfig, level::Level, replay::Input};
+
+/// How often to say hello again while the other peer has not answered.
+const HELLO_RETRY: Duration = Duration::from_millis(250);
+
+/// What two peers send each other.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PeerMessage {
+    Hello(Box<Hello>),
+    Inputs(PeerInputs),
+}
+
+/// Sent until the other peer answers. The peer with the lower `nonce` is player 0, and
+/// both play with its seed, rules and arena.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    pub nonce: u64,
+    pub seed: u64,
+    pub config: SnakeConfig,
+    pub level: Level,
+}
+
+impl PeerMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        protocol::encode(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        protocol::decode(bytes)
+    }
+}
+
+/// One end of a peer-to-peer versus game over UDP: says hello until the other end answers,
+/// then runs a [`RollbackSession`] with it.
+pub struct Peer {
+    socket: UdpSocket,
+    hello: Hello,
+    tick_seconds: f32,
+    session: Option<RollbackSession>,
+    last_hello: Instant,
+}
+
+impl Peer {
+    /// Listens on `bind` for the peer at `peer`, offering to play with `seed`, `config`
+    /// and `level`.
+    pub fn connect(
+        bind: impl ToSocketAddrs,
+        peer: impl ToSocketAddrs,
+        seed: u64,
+        config: SnakeConfig,
+        level: Level,
+        tick_seconds: f32,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(bind)?;
+        socket.connect(peer)?;
+        socket.set_nonblocking(true)?;
+        let hello = Hello {
+            nonce: rand::random(),
+            seed,
+            config,
+            level,
+        };
+        let peer = Self {
+            socket,
+            hello,
+            tick_seconds,
+            session: None,
+            last_hello: Instant::now(),
+        };
+        peer.say_hello()?;
+        Ok(peer)
+    }
+
+    /// The game, once the other peer has answered.
+    pub fn session(&self) -> Option<&RollbackSession> {
+        self.session.as_ref()
+    }
+
+    /// Handles everything the other peer sent, and says hello again while it has not
+    /// answered yet.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let length = match self.socket.recv(&mut buffer) {
+                Ok(length) => length,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                // The other peer is not up yet.
+                Err(error) if error.kind() == ErrorKind::ConnectionRefused => break,
+                Err(error) => return Err(error),
+            };
+            match PeerMessage::decode(&buffer[..length]) {
+                // They have not heard our hello yet.
+                Some(PeerMessage::Hello(_)) if self.session.is_some() => self.say_hello()?,
+                Some(PeerMessage::Hello(hello)) => self.start(*hello),
+                Some(PeerMessage::Inputs(inputs)) => {
+                    if let Some(session) = &mut self.session {
+                        session.receive(&inputs);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        if self.session.is_none() && self.last_hello.elapsed() >= HELLO_RETRY {
+            self.last_hello = Instant::now();
+            self.say_hello()?;
+        }
+        Ok(())
+    }
+
+    /// Plays the next frame with `input`, if the other peer is not too far behind, and
+    /// sends it the inputs it is missing. Returns whether a frame was played.
+    pub fn advance(&mut self, input: Option<Input>) -> io::Result<bool> {
+        let Some(session) = &mut self.session else {
+            return Ok(false);
+        };
+        let advanced = session.advance_frame(input);
+        let inputs = PeerMessage::Inputs(session.outgoing());
+        self.send(&inputs)?;
+        Ok(advanced)
+    }
+
+    fn start(&mut self, theirs: Hello) {
+        // Our own hello, bounced back by a peer address that is really us.
+        if theirs.nonce == self.hello.nonce {
+            return;
+        }
+        let (local_player, game) = if self.hello.nonce < theirs.nonce {
+            (0, self.hello.clone())
+        } else {
+            (1, theirs)
+        };
+        let mut simulation = Simulation::new(game.config, game.level, game.seed);
+        simulation.add_player();
+        simulation.add_player();
+        self.session = Some(RollbackSession::new(
+            simulation,
+            local_player,
+            self.tick_seconds,
+        ));
+    }
+
+    fn say_hello(&self) -> io::Result<()> {
+        self.send(&PeerMessage::Hello(Box::new(self.hello.clone())))
+    }
+
+    fn send(&self, message: &PeerMessage) -> io::Result<()> {
+        match self.socket.send(&message.encode()) {
+            // Lost like any datagram could be; hellos and inputs are sent again anyway.
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::ConnectionRefused
+                ) =>
+            {
+                Ok(())
+            }
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::replay::Direction;
+
+    const TICK: f32 = 1.0 / 64.0;
+
+    /// A free port on localhost, probably still free when the test binds it.
+    fn free_address() -> std::net::SocketAddr {
+        UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    }
+
+    #[test]
+    fn two_peers_play_the_same_game_on_localhost() {
+        let addresses = [free_address(), free_address()];
+        let mut peers = [0, 1].map(|index| {
+            let (bind, other) = (addresses[index], addresses[1 - index]);
+            let config = SnakeConfig::default();
+            Peer::connect(bind, other, index as u64, config, Level::default(), TICK).unwrap()
+        });
+
+        let frames = 200;
+        for _ in 0..frames * 4 {
+            for peer in &mut peers {
+                peer.poll().unwrap();
+                let frame = peer.session().map_or(0, RollbackSession::frame);
+                if frame < frames {
+                    let direction = if frame % 60 < 30 {
+                        Direction::Up
+                    } else {
+                        Direction::Left
+                    };
+                    peer.advance(Some(Input::Direction(direction))).unwrap();
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+            let confirmed = |peer: &Peer| peer.session().map(RollbackSession::confirmed_frame);
+            if peers.iter().all(|peer| confirmed(peer) == Some(frames)) {
+                break;
+            }
+        }
+
+        let [Some(first), Some(second)] = peers.each_ref().map(Peer::session) else {
+            panic!("the peers never met");
+        };
+        let mut players = [first.local_player(), second.local_player()];
+        players.sort();
+        assert_eq!(players, [0, 1]);
+        assert_eq!(first.confirmed_frame(), frames);
+        assert_eq!(second.confirmed_frame(), frames);
+        assert_eq!(
+            first.simulation().snapshot(),
+            second.simulation().snapshot()
+        );
+    }
+}
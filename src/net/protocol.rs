@@ -0,0 +1,308 @@
+use std::mem;
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use super::packing;
+use crate::{config::SnakeConfig, level::Level, replay::Input};
+
+pub const DEFAULT_PORT: u16 = 7878;
+/// Largest datagram either side expects to receive.
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
+/// Largest datagram a snapshot is sent in, small enough to cross the internet without
+/// being fragmented or refused.
+pub const MAX_SNAPSHOT_DATAGRAM_SIZE: usize = 1200;
+/// First byte of a snapshot datagram. The other messages are RON text, and no UTF-8 text
+/// starts with it.
+const SNAPSHOT_TAG: u8 = 0xff;
+/// The tag, the tick, which part of the snapshot the datagram carries and how many parts
+/// there are.
+const SNAPSHOT_HEADER_SIZE: usize = 1 + 4 + 2 + 2;
+
+/// What a client sends the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// Asks for a snake. Sent again until a [`ServerMessage::Welcome`] arrives, as it may
+    /// get lost.
+    Join,
+    /// Steering for the coming ticks, until the next input arrives.
+    Input(Option<Input>),
+    Leave,
+}
+
+/// What the server sends its clients. Snapshots are packed into a compact binary form and
+/// split over as many datagrams as they need; a [`MessageAssembler`] puts them back
+/// together.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// The player the client steers, and the rules and arena the server plays with.
+    Welcome {
+        player: usize,
+        config: Box<SnakeConfig>,
+        level: Level,
+        tick_seconds: f32,
+    },
+    /// There is no room for another player.
+    Full,
+    Snapshot(Snapshot),
+}
+
+/// Everything a client needs to draw one tick of the server's game.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub snakes: Vec<SnakeState>,
+    pub apples: Vec<AppleState>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnakeState {
+    pub player: usize,
+    /// Joint positions, tail first like in a `Limb`.
+    pub segments: Vec<Vec2>,
+    pub score: usize,
+    /// Dead snakes stay where they crashed until they respawn.
+    pub alive: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AppleState {
+    /// Index of its type in `SnakeConfig::apple_types`.
+    pub kind: usize,
+    pub position: Vec2,
+}
+
+impl Snapshot {
+    pub fn snake(&self, player: usize) -> Option<&SnakeState> {
+        self.snakes.iter().find(|snake| snake.player == player)
+    }
+}
+
+impl SnakeState {
+    pub fn head(&self) -> Option<Vec2> {
+        self.segments.last().copied()
+    }
+}
+
+impl ClientMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        decode(bytes)
+    }
+}
+
+impl ServerMessage {
+    /// The datagrams to send the message in, each at most [`MAX_DATAGRAM_SIZE`] long and
+    /// snapshot ones at most [`MAX_SNAPSHOT_DATAGRAM_SIZE`].
+    pub fn encode(&self) -> Vec<Vec<u8>> {
+        let ServerMessage::Snapshot(snapshot) = self else {
+            return vec![encode(self)];
+        };
+        let body = packing::pack(snapshot);
+        let parts: Vec<&[u8]> = body
+            .chunks(MAX_SNAPSHOT_DATAGRAM_SIZE - SNAPSHOT_HEADER_SIZE)
+            .collect();
+        // That many parts would take a snapshot of tens of megabytes.
+        let Ok(part_count) = u16::try_from(parts.len()) else {
+            return Vec::new();
+        };
+        (0..part_count)
+            .zip(parts)
+            .map(|(part, bytes)| {
+                let mut datagram = vec![SNAPSHOT_TAG];
+                datagram.extend(snapshot.tick.to_le_bytes());
+                datagram.extend(part.to_le_bytes());
+                datagram.extend(part_count.to_le_bytes());
+                datagram.extend(bytes);
+                datagram
+            })
+            .collect()
+    }
+}
+
+/// Puts the server's messages back together from the datagrams they arrive in.
+#[derive(Default)]
+pub struct MessageAssembler {
+    /// Tick of the snapshot being put together, and its parts that have arrived so far.
+    tick: u32,
+    parts: Vec<Option<Vec<u8>>>,
+}
+
+impl MessageAssembler {
+    /// The message `datagram` completes, if any, or `None` for datagrams that are not a
+    /// message, e.g. from some other program. A part of a newer snapshot than the one
+    /// being put together replaces it, and parts of older ones are dropped, so a lost
+    /// part only loses its own snapshot.
+    pub fn push(&mut self, datagram: &[u8]) -> Option<ServerMessage> {
+        let Some((&SNAPSHOT_TAG, rest)) = datagram.split_first() else {
+            return decode(datagram);
+        };
+        let (header, bytes) = rest.split_at_checked(SNAPSHOT_HEADER_SIZE - 1)?;
+        let tick = u32::from_le_bytes(header[..4].try_into().ok()?);
+        let part = u16::from_le_bytes(header[4..6].try_into().ok()?) as usize;
+        let part_count = u16::from_le_bytes(header[6..].try_into().ok()?) as usize;
+        if part >= part_count {
+            return None;
+        }
+        let assembling = !self.parts.is_empty();
+        if !assembling || tick != self.tick || part_count != self.parts.len() {
+            if assembling && tick < self.tick {
+                return None;
+            }
+            self.tick = tick;
+            self.parts = vec![None; part_count];
+        }
+        self.parts[part] = Some(bytes.to_vec());
+        if self.parts.iter().any(Option::is_none) {
+            return None;
+        }
+        let body: Vec<u8> = mem::take(&mut self.parts)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect();
+        packing::unpack(tick, &body).map(ServerMessage::Snapshot)
+    }
+}
+
+pub(super) fn encode(message: &impl Serialize) -> Vec<u8> {
+    // Messages are plain data, so serializing them cannot fail.
+    ron::to_string(message)
+        .map(String::into_bytes)
+        .unwrap_or_default()
+}
+
+/// `None` for datagrams that are not a message, e.g. from some other program.
+pub(super) fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    ron::from_str(std::str::from_utf8(bytes).ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::UdpSocket, time::Duration};
+
+    use super::*;
+    use crate::replay::Direction;
+
+    /// The message the last of `datagrams` completes.
+    fn assemble(datagrams: &[Vec<u8>]) -> Option<ServerMessage> {
+        let mut assembler = MessageAssembler::default();
+        datagrams
+            .iter()
+            .map(|datagram| assembler.push(datagram))
+            .last()
+            .flatten()
+    }
+
+    fn long_snake_snapshot(tick: u32, joints: usize) -> ServerMessage {
+        // Positions on the quantisation step survive the trip unchanged.
+        let segments = (0..joints)
+            .map(|joint| Vec2::new(joint as f32 * 0.5 - 1000.0, (joint % 40) as f32 * 12.25))
+            .collect();
+        ServerMessage::Snapshot(Snapshot {
+            tick,
+            snakes: vec![SnakeState {
+                player: 0,
+                segments,
+                score: joints,
+                alive: true,
+            }],
+            apples: vec![AppleState {
+                kind: 1,
+                position: Vec2::new(5.0, -7.5),
+            }],
+        })
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let input = ClientMessage::Input(Some(Input::Direction(Direction::Left)));
+        assert_eq!(ClientMessage::decode(&input.encode()), Some(input));
+
+        let snapshot = ServerMessage::Snapshot(Snapshot {
+            tick: 7,
+            snakes: vec![SnakeState {
+                player: 1,
+                segments: vec![Vec2::new(1.0, 2.0), Vec2::new(3.0, 4.0)],
+                score: 5,
+                alive: true,
+            }],
+            apples: vec![AppleState {
+                kind: 0,
+                position: Vec2::new(-10.0, 0.5),
+            }],
+        });
+        assert_eq!(snapshot.encode().len(), 1);
+        assert_eq!(assemble(&snapshot.encode()), Some(snapshot));
+
+        let full = ServerMessage::Full;
+        assert_eq!(assemble(&full.encode()), Some(full));
+    }
+
+    /// Far too long to send as one datagram, even over localhost.
+    #[test]
+    fn long_snakes_are_sent_in_parts_that_fit_the_mtu() {
+        let snapshot = long_snake_snapshot(9, 20_000);
+        assert!(encode(&snapshot).len() > MAX_DATAGRAM_SIZE);
+        let datagrams = snapshot.encode();
+        assert!(datagrams.len() > 1);
+        assert!(
+            datagrams
+                .iter()
+                .all(|datagram| datagram.len() <= MAX_SNAPSHOT_DATAGRAM_SIZE)
+        );
+
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut assembler = MessageAssembler::default();
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        let mut received = None;
+        // Out of order, like UDP may deliver them.
+        for datagram in datagrams.iter().rev() {
+            sender
+                .send_to(datagram, receiver.local_addr().unwrap())
+                .unwrap();
+            let length = receiver.recv(&mut buffer).unwrap();
+            assert_eq!(received, None);
+            received = assembler.push(&buffer[..length]);
+        }
+        assert_eq!(received, Some(snapshot));
+    }
+
+    #[test]
+    fn a_lost_part_only_loses_its_own_snapshot() {
+        let mut assembler = MessageAssembler::default();
+        let first = long_snake_snapshot(1, 1000).encode();
+        for datagram in &first[1..] {
+            assert_eq!(assembler.push(datagram), None);
+        }
+
+        let second = long_snake_snapshot(2, 1000);
+        let mut received = None;
+        for datagram in second.encode() {
+            received = assembler.push(&datagram);
+        }
+        assert_eq!(received, Some(second));
+
+        // What is left of the first one arrives late.
+        assert_eq!(assembler.push(&first[0]), None);
+    }
+
+    #[test]
+    fn garbage_is_ignored() {
+        assert_eq!(ClientMessage::decode(b"\xff\xfe"), None);
+        let mut assembler = MessageAssembler::default();
+        assert_eq!(assembler.push(b"Hello"), None);
+        assert_eq!(assembler.push(&[SNAPSHOT_TAG, 1, 2]), None);
+        assert_eq!(
+            assembler.push(&[SNAPSHOT_TAG, 0, 0, 0, 0, 1, 0, 1, 0]),
+            None
+        );
+    }
+}
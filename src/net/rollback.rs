@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Simulation, SimulationState};
+use crate::replay::Input;
+
+/// Ticks between a local input and the frame it is played on. Hides that much latency
+/// from the other peer, which then rarely has to roll back.
+pub const INPUT_DELAY_FRAMES: u32 = 2;
+/// Most frames a peer may run ahead of the other one's inputs, guessing at them.
+pub const MAX_PREDICTION_FRAMES: u32 = 8;
+
+/// Inputs one peer sends the other every tick.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PeerInputs {
+    /// Frame of the first of `inputs`.
+    pub start_frame: u32,
+    /// The sender's inputs from `start_frame` on. They are sent again every tick until the
+    /// other peer has them, so a lost datagram costs nothing but a little delay.
+    pub inputs: Vec<Option<Input>>,
+    /// The sender has every input of the receiver's before this frame.
+    pub received_until: u32,
+}
+
+/// One side of a two-player game where each peer runs the whole [`Simulation`] itself,
+/// GGRS style. The other player's inputs are predicted to be the same as their last one
+/// until the real ones arrive. When a guess turns out wrong, the game is rolled back to
+/// the frame it was wrong on and played forward again with the right inputs.
+///
+/// What is rolled back is the [`Simulation`], not the local game's Bevy world, so peer
+/// games play by the simulation's reduced rules.
+pub struct RollbackSession {
+    simulation: Simulation,
+    tick_seconds: f32,
+    local_player: usize,
+    /// Every local input by frame, [`INPUT_DELAY_FRAMES`] ahead of the simulation.
+    local_inputs: Vec<Option<Input>>,
+    /// The other player's inputs by frame, as far as they have arrived.
+    remote_inputs: Vec<Option<Input>>,
+    /// The remote input each frame so far was played with, real or predicted.
+    played_remote_inputs: Vec<Option<Input>>,
+    /// The state before each frame from the first one played on a prediction, oldest first.
+    saved_states: VecDeque<(u32, SimulationState)>,
+    /// The other peer has every local input before this frame.
+    acknowledged: u32,
+    rolled_back_frames: u64,
+}
+
+impl RollbackSession {
+    /// Starts a game between players 0 and 1 in `simulation`, a fresh one both peers must
+    /// have set up the same way. This peer steers `local_player`.
+    pub fn new(simulation: Simulation, local_player: usize, tick_seconds: f32) -> Self {
+        Self {
+            simulation,
+            tick_seconds,
+            local_player,
+            local_inputs: vec![None; INPUT_DELAY_FRAMES as usize],
+            remote_inputs: Vec::new(),
+            played_remote_inputs: Vec::new(),
+            saved_states: VecDeque::new(),
+            acknowledged: 0,
+            rolled_back_frames: 0,
+        }
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    pub fn local_player(&self) -> usize {
+        self.local_player
+    }
+
+    /// How many frames have been played again after a wrong guess, all told.
+    pub fn rolled_back_frames(&self) -> u64 {
+        self.rolled_back_frames
+    }
+
+    /// The next frame to be played.
+    pub fn frame(&self) -> u32 {
+        self.simulation.tick()
+    }
+
+    /// Frames before this were played with the other player's real inputs and will not
+    /// change any more.
+    pub fn confirmed_frame(&self) -> u32 {
+        self.frame().min(self.remote_inputs.len() as u32)
+    }
+
+    /// Plays the next frame with `input` queued for [`INPUT_DELAY_FRAMES`] later. Returns
+    /// `false`, and ignores `input`, when the other peer's inputs are too far behind to
+    /// guess on; the caller should try again next tick.
+    pub fn advance_frame(&mut self, input: Option<Input>) -> bool {
+        if self.frame() >= self.remote_inputs.len() as u32 + MAX_PREDICTION_FRAMES {
+            return false;
+        }
+        self.local_inputs.push(input);
+        self.play_frame();
+        self.discard_confirmed_states();
+        true
+    }
+
+    /// Takes in the other peer's inputs, and rolls back and replays the frames that were
+    /// played on a wrong guess.
+    pub fn receive(&mut self, message: &PeerInputs) {
+        let sent = self.local_inputs.len() as u32;
+        self.acknowledged = self.acknowledged.max(message.received_until.min(sent));
+        let known = self.remote_inputs.len();
+        let skip = known.saturating_sub(message.start_frame as usize);
+        // A gap means an older message went missing; the next one fills it.
+        if message.start_frame as usize > known {
+            return;
+        }
+        self.remote_inputs.extend(message.inputs.iter().skip(skip));
+
+        let played = self.frame() as usize;
+        let wrong = (known..self.remote_inputs.len().min(played))
+            .find(|&frame| self.played_remote_inputs[frame] != self.remote_inputs[frame]);
+        if let Some(frame) = wrong {
+            self.roll_back(frame as u32);
+            self.rolled_back_frames += (played - frame) as u64;
+            while (self.frame() as usize) < played {
+                self.play_frame();
+            }
+        }
+        self.discard_confirmed_states();
+    }
+
+    /// What to send the other peer: every local input it does not have yet.
+    pub fn outgoing(&self) -> PeerInputs {
+        PeerInputs {
+            start_frame: self.acknowledged,
+            inputs: self.local_inputs[self.acknowledged as usize..].to_vec(),
+            received_until: self.remote_inputs.len() as u32,
+        }
+    }
+
+    fn play_frame(&mut self) {
+        let frame = self.frame();
+        self.saved_states
+            .push_back((frame, self.simulation.save_state()));
+        // Until it arrives, the other player is guessed to do what they did last.
+        let remote_input = self
+            .remote_inputs
+            .get(frame as usize)
+            .or(self.remote_inputs.last())
+            .copied()
+            .flatten();
+        self.played_remote_inputs.truncate(frame as usize);
+        self.played_remote_inputs.push(remote_input);
+
+        let local_input = self.local_inputs[frame as usize];
+        let remote_player = 1 - self.local_player;
+        self.simulation.set_input(self.local_player, local_input);
+        self.simulation.set_input(remote_player, remote_input);
+        self.simulation.step(self.tick_seconds);
+    }
+
+    fn roll_back(&mut self, frame: u32) {
+        while let Some((saved_frame, state)) = self.saved_states.pop_back() {
+            if saved_frame == frame {
+                self.simulation.load_state(state);
+                return;
+            }
+        }
+    }
+
+    /// Forgets the states no rollback can go back to any more.
+    fn discard_confirmed_states(&mut self) {
+        let confirmed = self.confirmed_frame();
+        while self
+            .saved_states
+            .front()
+            .is_some_and(|(frame, _)| *frame < confirmed)
+        {
+            self.saved_states.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::{config::SnakeConfig, level::Level, replay::Direction};
+
+    const TICK: f32 = 1.0 / 64.0;
+    const FRAMES: u32 = 600;
+
+    fn simulation() -> Simulation {
+        let mut simulation = Simulation::new(SnakeConfig::default(), Level::default(), 11);
+        simulation.add_player();
+        simulation.add_player();
+        simulation
+    }
+
+    /// What `player` presses on `frame`: a new direction every so often, at different
+    /// times for the two players.
+    fn scripted_input(player: usize, frame: u32) -> Option<Input> {
+        let frame = frame + player as u32 * 7;
+        if !frame.is_multiple_of(23) {
+            return None;
+        }
+        let directions = [
+            Direction::Up,
+            Direction::Left,
+            Direction::Down,
+            Direction::Right,
+        ];
+        Some(Input::Direction(
+            directions[(frame / 23) as usize % directions.len()],
+        ))
+    }
+
+    /// Datagrams between the two peers, of which some get lost and the rest arrive a few
+    /// ticks late, not necessarily in order.
+    struct LossyLink {
+        rng: StdRng,
+        loss: f64,
+        delay: Range<u32>,
+        in_flight: Vec<(u32, usize, PeerInputs)>,
+    }
+
+    impl LossyLink {
+        fn send(&mut self, now: u32, to: usize, message: PeerInputs) {
+            if self.rng.random_bool(self.loss) {
+                return;
+            }
+            let arrival = now + self.rng.random_range(self.delay.clone());
+            self.in_flight.push((arrival, to, message));
+        }
+
+        fn deliver(&mut self, now: u32) -> Vec<(usize, PeerInputs)> {
+            let (due, in_flight) = self
+                .in_flight
+                .drain(..)
+                .partition(|(arrival, ..)| *arrival <= now);
+            self.in_flight = in_flight;
+            due.into_iter()
+                .map(|(_, to, message)| (to, message))
+                .collect()
+        }
+    }
+
+    /// Two peers play [`FRAMES`] frames over `link`, and keep talking until both have had
+    /// every input.
+    fn play_over(mut link: LossyLink) -> [RollbackSession; 2] {
+        let mut peers = [0, 1].map(|player| RollbackSession::new(simulation(), player, TICK));
+        for now in 0.. {
+            assert!(
+                now < FRAMES * 4,
+                "the peers never caught up with each other"
+            );
+            for (player, peer) in peers.iter_mut().enumerate() {
+                if peer.frame() < FRAMES {
+                    peer.advance_frame(scripted_input(player, peer.frame() + INPUT_DELAY_FRAMES));
+                }
+                link.send(now, 1 - player, peer.outgoing());
+            }
+            for (to, message) in link.deliver(now) {
+                peers[to].receive(&message);
+            }
+            if peers.iter().all(|peer| peer.confirmed_frame() == FRAMES) {
+                break;
+            }
+        }
+        peers
+    }
+
+    /// The game as it should have gone, played on one machine with every input known.
+    fn reference() -> SimulationState {
+        let mut simulation = simulation();
+        for frame in 0..FRAMES {
+            for player in 0..2 {
+                let delayed = frame.checked_sub(INPUT_DELAY_FRAMES);
+                let input = delayed.and(scripted_input(player, frame));
+                simulation.set_input(player, input);
+            }
+            simulation.step(TICK);
+        }
+        simulation.save_state()
+    }
+
+    #[test]
+    fn peers_converge_despite_delay_and_loss() {
+        let peers = play_over(LossyLink {
+            rng: StdRng::seed_from_u64(5),
+            loss: 0.25,
+            delay: 2..9,
+            in_flight: Vec::new(),
+        });
+        let expected = reference();
+        for peer in &peers {
+            assert_eq!(peer.frame(), FRAMES);
+            assert_eq!(peer.simulation().save_state(), expected);
+        }
+        assert!(peers.iter().any(|peer| peer.rolled_back_frames() > 0));
+    }
+
+    #[test]
+    fn a_perfect_link_needs_no_rollback() {
+        let peers = play_over(LossyLink {
+            rng: StdRng::seed_from_u64(5),
+            loss: 0.0,
+            delay: 1..2,
+            in_flight: Vec::new(),
+        });
+        for peer in &peers {
+            assert_eq!(peer.simulation().save_state(), reference());
+            assert_eq!(peer.rolled_back_frames(), 0);
+        }
+    }
+
+    #[test]
+    fn peers_wait_rather_than_guess_too_far_ahead() {
+        let mut session = RollbackSession::new(simulation(), 0, TICK);
+        for _ in 0..MAX_PREDICTION_FRAMES {
+            assert!(session.advance_frame(None));
+        }
+        assert!(!session.advance_frame(Some(Input::Direction(Direction::Up))));
+        assert_eq!(session.frame(), MAX_PREDICTION_FRAMES);
+
+        session.receive(&PeerInputs {
+            start_frame: 0,
+            inputs: vec![None; 3],
+            received_until: 0,
+        });
+        assert_eq!(session.confirmed_frame(), 3);
+        assert!(session.advance_frame(None));
+        let unacknowledged = INPUT_DELAY_FRAMES + MAX_PREDICTION_FRAMES + 1;
+        assert_eq!(session.outgoing().inputs.len(), unacknowledged as usize);
+    }
+}
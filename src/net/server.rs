@@ -0,0 +1,330 @@
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    thread,
+    time::Duration,
+};
+
+use web_time::Instant;
+
+use super::{ClientMessage, MAX_DATAGRAM_SIZE, ServerMessage, Simulation};
+
+/// Most snakes a server lets in at once.
+pub const MAX_PLAYERS: usize = 8;
+/// Clients silent for this long are taken to have gone, and their snakes are removed.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long [`Server::run`] sleeps between checks for datagrams.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Runs a [`Simulation`] for clients on the other end of a UDP socket.
+pub struct Server {
+    socket: UdpSocket,
+    simulation: Simulation,
+    tick_seconds: f32,
+    clients: Vec<Connection>,
+    send_failures: Vec<SendFailure>,
+}
+
+struct Connection {
+    address: SocketAddr,
+    player: usize,
+    last_heard: Instant,
+    /// Whether the last send to the client failed, so the failure is only reported once.
+    send_failed: bool,
+}
+
+/// A message the server could not send. It goes on serving everyone regardless.
+#[derive(Debug)]
+pub struct SendFailure {
+    pub address: SocketAddr,
+    /// The player at `address`, unless it was being turned away.
+    pub player: Option<usize>,
+    pub error: io::Error,
+}
+
+impl fmt::Display for SendFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.player {
+            Some(player) => write!(
+                f,
+                "could not send to player {player} at {}: {}",
+                self.address, self.error
+            ),
+            None => write!(f, "could not send to {}: {}", self.address, self.error),
+        }
+    }
+}
+
+impl Server {
+    pub fn bind(
+        address: impl ToSocketAddrs,
+        simulation: Simulation,
+        tick_seconds: f32,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            simulation,
+            tick_seconds,
+            clients: Vec::new(),
+            send_failures: Vec::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Sends that failed since the last call. A client's failures are reported once, and
+    /// then again only after a send to it has worked.
+    pub fn take_send_failures(&mut self) -> Vec<SendFailure> {
+        std::mem::take(&mut self.send_failures)
+    }
+
+    /// Handles every datagram that has arrived, and drops clients that went silent.
+    pub fn receive(&mut self) -> io::Result<()> {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (length, address) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                // A client that went away without leaving, reported by some platforms.
+                Err(error) if is_gone(&error) => continue,
+                Err(error) => return Err(error),
+            };
+            if let Some(message) = ClientMessage::decode(&buffer[..length]) {
+                self.handle(address, message);
+            }
+        }
+
+        let simulation = &mut self.simulation;
+        self.clients.retain(|client| {
+            let alive = client.last_heard.elapsed() < CLIENT_TIMEOUT;
+            if !alive {
+                simulation.remove_player(client.player);
+            }
+            alive
+        });
+        Ok(())
+    }
+
+    fn handle(&mut self, address: SocketAddr, message: ClientMessage) {
+        let client = self
+            .clients
+            .iter()
+            .position(|client| client.address == address);
+        if let Some(client) = client {
+            self.clients[client].last_heard = Instant::now();
+        }
+        match (message, client) {
+            // The welcome may have been lost, so joining again just repeats it.
+            (ClientMessage::Join, Some(client)) => {
+                self.send_to_client(client, &self.welcome(self.clients[client].player));
+            }
+            (ClientMessage::Join, None) if self.clients.len() >= MAX_PLAYERS => {
+                if let Err(error) = self.send(address, &ServerMessage::Full) {
+                    self.send_failures.push(SendFailure {
+                        address,
+                        player: None,
+                        error,
+                    });
+                }
+            }
+            (ClientMessage::Join, None) => {
+                let player = self.simulation.add_player();
+                self.clients.push(Connection {
+                    address,
+                    player,
+                    last_heard: Instant::now(),
+                    send_failed: false,
+                });
+                self.send_to_client(self.clients.len() - 1, &self.welcome(player));
+            }
+            (ClientMessage::Input(input), Some(client)) => {
+                self.simulation
+                    .set_input(self.clients[client].player, input);
+            }
+            (ClientMessage::Leave, Some(client)) => {
+                let client = self.clients.remove(client);
+                self.simulation.remove_player(client.player);
+            }
+            (ClientMessage::Input(_) | ClientMessage::Leave, None) => {}
+        }
+    }
+
+    /// Advances the game one tick and sends everyone the result.
+    pub fn step(&mut self) {
+        self.simulation.step(self.tick_seconds);
+        let snapshot = ServerMessage::Snapshot(self.simulation.snapshot());
+        for client in 0..self.clients.len() {
+            self.send_to_client(client, &snapshot);
+        }
+    }
+
+    /// Serves clients forever, stepping the game every tick. Sends that fail are handed
+    /// to `report` rather than stopping the server.
+    pub fn run(&mut self, mut report: impl FnMut(SendFailure)) -> io::Result<()> {
+        let tick = Duration::from_secs_f32(self.tick_seconds);
+        let mut next_tick = Instant::now();
+        loop {
+            self.receive()?;
+            self.take_send_failures().into_iter().for_each(&mut report);
+            let now = Instant::now();
+            if now < next_tick {
+                thread::sleep(POLL_INTERVAL.min(next_tick - now));
+                continue;
+            }
+            self.step();
+            next_tick += tick;
+            // Skip ticks rather than rush through them after a stall.
+            if next_tick < now {
+                next_tick = now + tick;
+            }
+        }
+    }
+
+    fn welcome(&self, player: usize) -> ServerMessage {
+        ServerMessage::Welcome {
+            player,
+            config: Box::new(self.simulation.config().clone()),
+            level: self.simulation.level().clone(),
+            tick_seconds: self.tick_seconds,
+        }
+    }
+
+    /// Failing to reach one client is no reason to stop serving the others, so errors
+    /// are kept for [`Server::take_send_failures`] instead.
+    fn send_to_client(&mut self, client: usize, message: &ServerMessage) {
+        let sent = self.send(self.clients[client].address, message);
+        let client = &mut self.clients[client];
+        match sent {
+            Err(error) if !client.send_failed => {
+                client.send_failed = true;
+                self.send_failures.push(SendFailure {
+                    address: client.address,
+                    player: Some(client.player),
+                    error,
+                });
+            }
+            Err(_) => {}
+            Ok(()) => client.send_failed = false,
+        }
+    }
+
+    fn send(&self, address: SocketAddr, message: &ServerMessage) -> io::Result<()> {
+        message.encode().iter().try_for_each(|datagram| {
+            match self.socket.send_to(datagram, address) {
+                // Like any datagram it may be dropped; the next tick sends a new one.
+                Err(error) if error.kind() == ErrorKind::WouldBlock || is_gone(&error) => Ok(()),
+                result => result.map(|_| ()),
+            }
+        })
+    }
+}
+
+/// Errors some platforms report for a peer that is no longer listening.
+fn is_gone(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionRefused
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec2;
+
+    use super::*;
+    use crate::{
+        config::SnakeConfig,
+        level::Level,
+        net::{Client, SnapshotBuffer, bot::bot_input},
+        rival::{Cautious, Greedy, Steer},
+    };
+
+    const TICK: f32 = 1.0 / 64.0;
+
+    /// A client the server cannot send to: an IPv6 address, from an IPv4 socket.
+    #[test]
+    fn failed_sends_are_reported_once_and_serving_goes_on() {
+        let simulation = Simulation::new(SnakeConfig::default(), Level::default(), 3);
+        let mut server = Server::bind("127.0.0.1:0", simulation, TICK).unwrap();
+        let unreachable: SocketAddr = "[::1]:9".parse().unwrap();
+        server.handle(unreachable, ClientMessage::Join);
+        server.step();
+        server.step();
+
+        let failures = server.take_send_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].address, unreachable);
+        assert_eq!(failures[0].player, Some(0));
+        assert_eq!(server.client_count(), 1);
+        assert_eq!(server.simulation().tick(), 2);
+        assert!(server.take_send_failures().is_empty());
+    }
+
+    /// Two bots join a server on localhost and play against each other.
+    #[test]
+    fn bots_play_a_game_on_localhost() {
+        let (config, level) = (SnakeConfig::default(), Level::default());
+        let simulation = Simulation::new(config.clone(), level.clone(), 3);
+        let mut server = Server::bind("127.0.0.1:0", simulation, TICK).unwrap();
+        let address = server.local_addr().unwrap();
+        let brains: [&dyn Steer; 2] = [&Greedy, &Cautious];
+        let mut bots: Vec<(Client, SnapshotBuffer, Option<Vec2>)> = brains
+            .iter()
+            .map(|_| {
+                (
+                    Client::connect(address).unwrap(),
+                    SnapshotBuffer::new(TICK),
+                    None,
+                )
+            })
+            .collect();
+        let mut moved = [false; 2];
+
+        for _ in 0..300 {
+            server.receive().unwrap();
+            server.step();
+            thread::sleep(Duration::from_millis(1));
+            for (index, (client, buffer, start)) in bots.iter_mut().enumerate() {
+                for message in client.poll().unwrap() {
+                    if let ServerMessage::Snapshot(snapshot) = message {
+                        buffer.push(snapshot);
+                    }
+                }
+                let (Some(player), Some(snapshot)) = (client.player(), buffer.latest()) else {
+                    continue;
+                };
+                let Some(head) = snapshot.snake(player).and_then(|snake| snake.head()) else {
+                    continue;
+                };
+                let start = start.get_or_insert(head);
+                moved[index] |= start.distance(head) > 50.0;
+                let input = bot_input(brains[index], player, snapshot, &level, &config);
+                client.send_input(input).unwrap();
+            }
+        }
+
+        assert_eq!(server.client_count(), 2);
+        assert_eq!(moved, [true, true]);
+        let players: Vec<_> = bots.iter().map(|(client, ..)| client.player()).collect();
+        assert_eq!(players, [Some(0), Some(1)]);
+
+        bots[0].0.leave().unwrap();
+        thread::sleep(Duration::from_millis(10));
+        server.receive().unwrap();
+        assert_eq!(server.client_count(), 1);
+        assert_eq!(server.simulation().player_count(), 1);
+    }
+}
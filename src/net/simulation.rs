@@ -0,0 +1,467 @@
+use glam::Vec2;
+use rand::{SeedableRng, rngs::StdRng};
+
+use super::{AppleState, SnakeState, Snapshot};
+use crate::{
+    config::SnakeConfig,
+    fabrik::{Limb, Segment},
+    level::{Level, distance_to_line_segment},
+    mode::GameMode,
+    placement::ApplePlacement,
+    replay::Input,
+    rival,
+};
+
+/// Ticks a crashed snake waits before it respawns.
+pub const RESPAWN_TICKS: u32 = 128;
+/// Joints at the head end of a snake its own head cannot run into.
+const NECK_JOINTS: usize = 3;
+
+/// The server's game: snakes steered like in classic mode by their players' latest input,
+/// apples to eat, and crashes into the walls and into bodies. Crashed snakes respawn
+/// after [`RESPAWN_TICKS`] with their score reset.
+///
+/// This is a reduced rule set next to the local game's. Steering and growth go through
+/// the same [`GameMode::steer`] and [`crate::config::AppleType::growth`], but the other
+/// modes, wrap-around walls, power-ups and rivals are left out, and crashes are distance
+/// checks rather than physics collisions.
+pub struct Simulation {
+    config: SnakeConfig,
+    level: Level,
+    state: SimulationState,
+}
+
+/// Everything in a [`Simulation`] that changes as it runs: every snake's `Limb`, the
+/// apples, the scores and the RNG. Saving it and loading it back later rewinds the game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationState {
+    rng: StdRng,
+    tick: u32,
+    snakes: Vec<Snake>,
+    apples: Vec<AppleState>,
+    next_player: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Snake {
+    player: usize,
+    limb: Limb,
+    /// How far the head moves each tick.
+    velocity: Vec2,
+    speed_factor: f32,
+    input: Option<Input>,
+    score: usize,
+    /// Ticks until it respawns once it has crashed.
+    respawn_in: Option<u32>,
+}
+
+impl Simulation {
+    pub fn new(config: SnakeConfig, level: Level, seed: u64) -> Self {
+        let mut simulation = Self {
+            config,
+            level,
+            state: SimulationState {
+                rng: StdRng::seed_from_u64(seed),
+                tick: 0,
+                snakes: Vec::new(),
+                apples: Vec::new(),
+                next_player: 0,
+            },
+        };
+        for _ in 0..simulation.config.apple_count {
+            let Some(kind) = simulation
+                .config
+                .random_apple_type(&mut simulation.state.rng)
+            else {
+                break;
+            };
+            let position = simulation.place_apple(None);
+            simulation.state.apples.push(AppleState { kind, position });
+        }
+        simulation
+    }
+
+    pub fn config(&self) -> &SnakeConfig {
+        &self.config
+    }
+
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    pub fn tick(&self) -> u32 {
+        self.state.tick
+    }
+
+    pub fn save_state(&self) -> SimulationState {
+        self.state.clone()
+    }
+
+    /// Puts the game back the way it was when `state` was saved.
+    pub fn load_state(&mut self, state: SimulationState) {
+        self.state = state;
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.state.snakes.len()
+    }
+
+    /// Spawns a snake for a new player somewhere clear and returns the player's index.
+    pub fn add_player(&mut self) -> usize {
+        let player = self.state.next_player;
+        self.state.next_player += 1;
+        let lengths = self.config.segment_lengths();
+        let mut limb = Limb::new(self.level.spawn, self.level.spawn, &lengths);
+        limb.set_max_bend(self.config.max_bend());
+        self.state.snakes.push(Snake {
+            player,
+            limb,
+            velocity: Vec2::ZERO,
+            speed_factor: 1.0,
+            input: None,
+            score: 0,
+            respawn_in: None,
+        });
+        self.respawn(self.state.snakes.len() - 1);
+        player
+    }
+
+    pub fn remove_player(&mut self, player: usize) {
+        self.state.snakes.retain(|snake| snake.player != player);
+    }
+
+    /// Steers `player`'s snake with `input` from the next tick on. `None` keeps it going
+    /// the way it was.
+    pub fn set_input(&mut self, player: usize, input: Option<Input>) {
+        if let Some(snake) = self
+            .state
+            .snakes
+            .iter_mut()
+            .find(|snake| snake.player == player)
+        {
+            snake.input = input;
+        }
+    }
+
+    /// Moves every snake `tick_seconds` further, then settles crashes and eaten apples.
+    pub fn step(&mut self, tick_seconds: f32) {
+        for index in 0..self.state.snakes.len() {
+            match self.state.snakes[index].respawn_in {
+                Some(0) => self.respawn(index),
+                Some(ticks) => self.state.snakes[index].respawn_in = Some(ticks - 1),
+                None => self.move_snake(index, tick_seconds),
+            }
+        }
+
+        let crashed: Vec<usize> = (0..self.state.snakes.len())
+            .filter(|index| self.crashed(*index))
+            .collect();
+        for index in crashed {
+            let snake = &mut self.state.snakes[index];
+            snake.velocity = Vec2::ZERO;
+            snake.respawn_in = Some(RESPAWN_TICKS);
+        }
+
+        for index in 0..self.state.snakes.len() {
+            if self.state.snakes[index].respawn_in.is_none() {
+                self.eat_apples(index);
+            }
+        }
+        self.state.tick += 1;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            tick: self.state.tick,
+            snakes: self
+                .state
+                .snakes
+                .iter()
+                .map(|snake| SnakeState {
+                    player: snake.player,
+                    segments: snake
+                        .limb
+                        .segments()
+                        .iter()
+                        .map(Segment::position)
+                        .collect(),
+                    score: snake.score,
+                    alive: snake.respawn_in.is_none(),
+                })
+                .collect(),
+            apples: self.state.apples.clone(),
+        }
+    }
+
+    fn move_snake(&mut self, index: usize, tick_seconds: f32) {
+        let speed = self.config.speed * self.state.snakes[index].speed_factor * tick_seconds;
+        let snake = &mut self.state.snakes[index];
+        snake.velocity = GameMode::Classic.steer(
+            snake.input,
+            snake.velocity,
+            snake.limb.heading(),
+            speed,
+            0.0,
+        );
+        if snake.velocity == Vec2::ZERO {
+            return;
+        }
+        let target = snake.limb.get_last_segment_position() + snake.velocity;
+        snake.limb.set_target(target);
+        snake.limb.solve();
+    }
+
+    /// Whether the head of the snake at `index` hit a wall or a body. When two heads meet,
+    /// the shorter snake crashes, or both if they are as long.
+    fn crashed(&self, index: usize) -> bool {
+        let snake = &self.state.snakes[index];
+        if snake.respawn_in.is_some() {
+            return false;
+        }
+        let head = snake.limb.get_last_segment_position();
+        let head_radius = self.config.head_thickness / 2.0;
+        if self.level.distance_to_nearest_wall(head) < head_radius {
+            return true;
+        }
+        let reach = head_radius + self.config.part_thickness / 2.0;
+        let mut others = self.state.snakes.iter().enumerate();
+        others.any(|(other_index, other)| {
+            if other.respawn_in.is_some() {
+                return false;
+            }
+            let joints: Vec<Vec2> = other
+                .limb
+                .segments()
+                .iter()
+                .map(Segment::position)
+                .collect();
+            if other_index == index {
+                let body = &joints[..joints.len().saturating_sub(NECK_JOINTS)];
+                return body_distance(body, head) < reach;
+            }
+            if head.distance(other.limb.get_last_segment_position()) < head_radius * 2.0 {
+                return rival::head_on_casualties(snake.limb.segments().len(), joints.len()).0;
+            }
+            body_distance(&joints, head) < reach
+        })
+    }
+
+    fn eat_apples(&mut self, index: usize) {
+        let head = self.state.snakes[index].limb.get_last_segment_position();
+        let reach = self.config.apple_radius + self.config.head_thickness / 2.0;
+        for apple_index in 0..self.state.apples.len() {
+            if self.state.apples[apple_index].position.distance(head) > reach {
+                continue;
+            }
+            let apple_type = &self.config.apple_types[self.state.apples[apple_index].kind];
+            let snake = &mut self.state.snakes[index];
+            snake.score = snake.score.saturating_add_signed(apple_type.score as isize);
+            snake.speed_factor *= apple_type.speed_factor;
+            let (added, removed) =
+                apple_type.growth(snake.limb.segments().len(), self.config.no_of_parts);
+            for _ in 0..added {
+                snake.limb.add_snake_part(self.config.part_length);
+            }
+            for _ in 0..removed {
+                snake.limb.remove_snake_part();
+            }
+
+            let kind = self.config.random_apple_type(&mut self.state.rng);
+            let kind = kind.unwrap_or(self.state.apples[apple_index].kind);
+            let position = self.place_apple(Some(apple_index));
+            self.state.apples[apple_index] = AppleState { kind, position };
+        }
+    }
+
+    /// A spot for an apple clear of the snakes and of the other apples than the one at
+    /// `moving`.
+    fn place_apple(&mut self, moving: Option<usize>) -> Vec2 {
+        let snakes: Vec<&Limb> = self.state.snakes.iter().map(|snake| &snake.limb).collect();
+        let others: Vec<Vec2> = self
+            .state
+            .apples
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| Some(*index) != moving)
+            .map(|(_, apple)| apple.position)
+            .collect();
+        let placement = ApplePlacement::from_config(&self.config);
+        placement.find_position(&self.level, &snakes, &others, &mut self.state.rng)
+    }
+
+    /// Lays the snake at `index` out afresh somewhere clear of the others.
+    fn respawn(&mut self, index: usize) {
+        let occupied: Vec<Vec2> = self
+            .state
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .flat_map(|(_, snake)| snake.limb.segments())
+            .map(|segment| segment.position())
+            .collect();
+        let snake = &self.state.snakes[index];
+        let start = rival::spawn_point(
+            &self.level,
+            &occupied,
+            snake.limb.reach(),
+            self.config.head_thickness,
+            self.config.apple_min_head_distance,
+            &mut self.state.rng,
+        );
+        let snake = &mut self.state.snakes[index];
+        snake.limb.reset_limb(start, &self.config.segment_lengths());
+        snake.velocity = Vec2::ZERO;
+        snake.speed_factor = 1.0;
+        snake.input = None;
+        snake.score = 0;
+        snake.respawn_in = None;
+    }
+}
+
+/// Distance from `point` to the body running through `joints`.
+fn body_distance(joints: &[Vec2], point: Vec2) -> f32 {
+    joints
+        .windows(2)
+        .map(|pair| distance_to_line_segment(point, pair[0], pair[1]))
+        .fold(f32::INFINITY, f32::min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::Direction;
+
+    const TICK: f32 = 1.0 / 64.0;
+
+    fn simulation() -> Simulation {
+        Simulation::new(SnakeConfig::default(), Level::default(), 7)
+    }
+
+    #[test]
+    fn the_same_seed_and_inputs_play_out_the_same() {
+        let play = || {
+            let mut simulation = simulation();
+            simulation.add_player();
+            simulation.add_player();
+            for tick in 0..200 {
+                let direction = if tick % 50 < 25 {
+                    Direction::Up
+                } else {
+                    Direction::Left
+                };
+                simulation.set_input(0, Some(Input::Direction(direction)));
+                simulation.set_input(1, Some(Input::heading(tick as f32 * 0.05)));
+                simulation.step(TICK);
+            }
+            simulation.snapshot()
+        };
+        assert_eq!(play(), play());
+    }
+
+    #[test]
+    fn loading_a_saved_state_rewinds_the_game() {
+        let mut simulation = simulation();
+        simulation.add_player();
+        simulation.set_input(0, Some(Input::Direction(Direction::Up)));
+        let saved = simulation.save_state();
+        let play = |simulation: &mut Simulation| {
+            for _ in 0..100 {
+                simulation.step(TICK);
+            }
+            simulation.snapshot()
+        };
+        let first = play(&mut simulation);
+        simulation.load_state(saved);
+        assert_eq!(simulation.tick(), 0);
+        assert_eq!(play(&mut simulation), first);
+    }
+
+    #[test]
+    fn snakes_follow_their_input_but_never_turn_back() {
+        let mut simulation = simulation();
+        let player = simulation.add_player();
+        let start = simulation.snapshot().snake(player).unwrap().head().unwrap();
+
+        simulation.set_input(player, Some(Input::Direction(Direction::Up)));
+        simulation.step(TICK);
+        let up = simulation.snapshot().snake(player).unwrap().head().unwrap();
+        assert!(up.y > start.y);
+
+        simulation.set_input(player, Some(Input::Direction(Direction::Down)));
+        simulation.step(TICK);
+        let still_up = simulation.snapshot().snake(player).unwrap().head().unwrap();
+        assert!(still_up.y > up.y);
+    }
+
+    #[test]
+    fn crashed_snakes_respawn_with_no_score() {
+        let mut simulation = simulation();
+        let player = simulation.add_player();
+        simulation.state.snakes[0].score = 4;
+        simulation.set_input(player, Some(Input::Direction(Direction::Up)));
+        let mut ticks = 0;
+        while simulation.snapshot().snake(player).unwrap().alive {
+            simulation.step(TICK);
+            ticks += 1;
+            assert!(ticks < 1000, "the snake never reached the wall");
+        }
+        let crash = simulation.snapshot();
+        assert!(
+            simulation
+                .level
+                .distance_to_nearest_wall(crash.snakes[0].head().unwrap())
+                < 20.0
+        );
+
+        for _ in 0..=RESPAWN_TICKS {
+            simulation.step(TICK);
+        }
+        let respawned = simulation.snapshot();
+        assert!(respawned.snakes[0].alive);
+        assert_eq!(respawned.snakes[0].score, 0);
+    }
+
+    #[test]
+    fn eating_an_apple_scores_grows_and_moves_it() {
+        let mut simulation = simulation();
+        let player = simulation.add_player();
+        let head = simulation.snapshot().snake(player).unwrap().head().unwrap();
+        let length = simulation.state.snakes[0].limb.segments().len();
+        simulation.state.apples[0] = AppleState {
+            kind: 0,
+            position: head,
+        };
+        simulation.step(TICK);
+
+        let snake = simulation.snapshot().snakes[0].clone();
+        let apple_type = &simulation.config.apple_types[0];
+        assert_eq!(snake.score, apple_type.score as usize);
+        assert_eq!(snake.segments.len(), length + apple_type.growth as usize);
+        assert_ne!(simulation.state.apples[0].position, head);
+    }
+
+    #[test]
+    fn heads_that_meet_kill_the_shorter_snake() {
+        let mut simulation = simulation();
+        simulation.add_player();
+        simulation.add_player();
+        let lengths = simulation.config.segment_lengths();
+        simulation.state.snakes[0]
+            .limb
+            .reset_limb(Vec2::new(100.0, 0.0), &lengths);
+        // The second snake is a part longer, and faces the first one's head.
+        simulation.state.snakes[1]
+            .limb
+            .add_snake_part(simulation.config.part_length);
+        let head = simulation.state.snakes[0].limb.get_last_segment_position();
+        simulation.state.snakes[1]
+            .limb
+            .lay_along(&[head - Vec2::X * 10.0, head - Vec2::X * 400.0]);
+
+        simulation.step(TICK);
+        let snapshot = simulation.snapshot();
+        assert!(!snapshot.snakes[0].alive);
+        assert!(snapshot.snakes[1].alive);
+    }
+}
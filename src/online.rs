@@ -0,0 +1,350 @@
+//! Playing over the network: on a game server given with `--connect`, or against a peer
+//! given with `--peer`.
+
+use bevy::prelude::*;
+use snake::{
+    args::arg_value,
+    config::SnakeConfig,
+    controls::{KeyBindings, dpad_direction, stick_heading},
+    level::{Level, Obstacle},
+    mode::{GameMode, Walls},
+    net::{Client, DEFAULT_PORT, Peer, ServerMessage, Snapshot, SnapshotBuffer},
+    replay::Input,
+};
+
+use crate::{
+    GameState, SimulationSeed,
+    players::{PLAYER_COLORS, ScoreboardUi},
+};
+
+pub struct OnlinePlugin;
+
+impl Plugin for OnlinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, read_server_address)
+            .add_systems(OnEnter(GameState::Online), join_server)
+            .add_systems(OnExit(GameState::Online), leave_server)
+            .add_systems(OnEnter(GameState::PeerVersus), connect_peer)
+            .add_systems(OnExit(GameState::PeerVersus), disconnect_peer)
+            .add_systems(
+                FixedUpdate,
+                step_peer_session
+                    .run_if(in_state(GameState::PeerVersus).and(resource_exists::<PeerSession>)),
+            )
+            .add_systems(
+                Update,
+                draw_peer_game
+                    .run_if(in_state(GameState::PeerVersus).and(resource_exists::<PeerSession>)),
+            )
+            .add_systems(
+                Update,
+                (send_online_input, receive_snapshots, draw_online_game)
+                    .chain()
+                    .run_if(in_state(GameState::Online)),
+            );
+    }
+}
+
+const ONLINE_WALL_COLOR: Color = Color::srgb(1.0, 0.647, 0.0);
+
+const ONLINE_APPLE_COLOR: Color = Color::srgb(0.9, 0.1, 0.1);
+
+/// Address of the game server given with `--connect`. Play joins it instead of starting
+/// a local game.
+#[derive(Resource, Deref)]
+pub struct ServerAddress(String);
+
+/// The other player's address given with `--peer`, and the address to listen on for them
+/// given with `--bind`. Play starts a versus game with them instead of a local game.
+#[derive(Resource)]
+pub struct PeerAddress {
+    bind: String,
+    peer: String,
+}
+
+#[derive(Resource, Deref, DerefMut)]
+struct PeerSession(Peer);
+
+/// The connection to the server while playing online.
+#[derive(Resource)]
+struct OnlineSession {
+    client: Client,
+    /// Set once the server has welcomed us.
+    game: Option<OnlineGame>,
+}
+
+struct OnlineGame {
+    player: usize,
+    config: SnakeConfig,
+    level: Level,
+    snapshots: SnapshotBuffer,
+}
+
+fn read_server_address(mut commands: Commands) {
+    let arg = |name: &str| arg_value(std::env::args().skip(1), name);
+    if let Some(address) = arg("--connect") {
+        commands.insert_resource(ServerAddress(address));
+    }
+    if let Some(peer) = arg("--peer") {
+        let bind = arg("--bind").unwrap_or(format!("0.0.0.0:{DEFAULT_PORT}"));
+        commands.insert_resource(PeerAddress { bind, peer });
+    }
+}
+
+/// Networked games run the reduced rules of [`snake::net::Simulation`] whatever was picked
+/// in the main menu, so say so when the picks cannot be honoured.
+fn warn_about_reduced_rules(mode: GameMode, walls: Walls) {
+    if mode != GameMode::Classic || walls != Walls::Solid {
+        warn!(
+            "Networked games are played in {} mode with {} walls and no power-ups",
+            GameMode::Classic.label(),
+            Walls::Solid.label()
+        );
+    }
+}
+
+fn join_server(
+    mut commands: Commands,
+    address: Res<ServerAddress>,
+    mode: Res<GameMode>,
+    walls: Res<Walls>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    match Client::connect(address.as_str()) {
+        Ok(client) => {
+            info!("Joining the server at {}", **address);
+            warn_about_reduced_rules(*mode, *walls);
+            commands.insert_resource(OnlineSession { client, game: None });
+        }
+        Err(error) => {
+            warn!("Could not connect to {}: {error}", **address);
+            game_state.set(GameState::MainMenu);
+        }
+    }
+}
+
+fn leave_server(mut commands: Commands, session: Option<ResMut<OnlineSession>>) {
+    if let Some(mut session) = session
+        && let Err(error) = session.client.leave()
+    {
+        warn!("Could not tell the server we left: {error}");
+    }
+    commands.remove_resource::<OnlineSession>();
+}
+
+fn connect_peer(
+    mut commands: Commands,
+    address: Res<PeerAddress>,
+    simulation_seed: Res<SimulationSeed>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    mode: Res<GameMode>,
+    walls: Res<Walls>,
+    fixed_time: Res<Time<Fixed>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let tick_seconds = fixed_time.timestep().as_secs_f32();
+    let seed = simulation_seed.seed;
+    let (config, level) = (config.clone(), level.clone());
+    match Peer::connect(
+        &address.bind,
+        &address.peer,
+        seed,
+        config,
+        level,
+        tick_seconds,
+    ) {
+        Ok(peer) => {
+            info!("Waiting for {} on {}", address.peer, address.bind);
+            warn_about_reduced_rules(*mode, *walls);
+            commands.insert_resource(PeerSession(peer));
+        }
+        Err(error) => {
+            warn!(
+                "Could not reach {} from {}: {error}",
+                address.peer, address.bind
+            );
+            game_state.set(GameState::MainMenu);
+        }
+    }
+}
+
+fn disconnect_peer(mut commands: Commands) {
+    commands.remove_resource::<PeerSession>();
+}
+
+/// Plays one frame of the peer game per fixed step, rolling back first when the other
+/// peer's inputs show it guessed wrong.
+fn step_peer_session(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    gamepads: Query<&Gamepad>,
+    mut peer: ResMut<PeerSession>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let input = online_input(&keyboard_input, &bindings, &gamepads);
+    if let Err(error) = peer.poll().and_then(|()| peer.advance(input)) {
+        warn!("Lost the connection to the other player: {error}");
+        game_state.set(GameState::MainMenu);
+    }
+}
+
+fn draw_peer_game(
+    peer: Res<PeerSession>,
+    mut gizmos: Gizmos,
+    scoreboards: Query<(Entity, &ScoreboardUi), With<Text>>,
+    mut writer: TextUiWriter,
+) {
+    let Some(session) = peer.session() else {
+        return;
+    };
+    let simulation = session.simulation();
+    let snapshot = simulation.snapshot();
+    draw_snapshot(
+        &mut gizmos,
+        simulation.level(),
+        simulation.config(),
+        &snapshot,
+    );
+    show_score(&scoreboards, &mut writer, &snapshot, session.local_player());
+}
+
+/// The way the player steers in a networked game, from the keys or any gamepad.
+fn online_input(
+    keyboard_input: &ButtonInput<KeyCode>,
+    bindings: &KeyBindings,
+    gamepads: &Query<&Gamepad>,
+) -> Option<Input> {
+    bindings
+        .direction(keyboard_input)
+        .or_else(|| gamepads.iter().find_map(dpad_direction))
+        .map(Input::Direction)
+        .or_else(|| gamepads.iter().find_map(stick_heading).map(Input::heading))
+}
+
+/// Sends the server the way the player steers. The server keeps to an input until the next
+/// one, so letting go of the keys sends nothing.
+fn send_online_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    bindings: Res<KeyBindings>,
+    gamepads: Query<&Gamepad>,
+    mut session: ResMut<OnlineSession>,
+) {
+    let input = online_input(&keyboard_input, &bindings, &gamepads);
+    if input.is_some()
+        && let Err(error) = session.client.send_input(input)
+    {
+        warn!("Could not send input to the server: {error}");
+    }
+}
+
+fn receive_snapshots(
+    mut session: ResMut<OnlineSession>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    let session = &mut *session;
+    let messages = match session.client.poll() {
+        Ok(messages) => messages,
+        Err(error) => {
+            warn!("Lost the connection to the server: {error}");
+            game_state.set(GameState::MainMenu);
+            return;
+        }
+    };
+    for message in messages {
+        match message {
+            ServerMessage::Welcome {
+                player,
+                config,
+                level,
+                tick_seconds,
+            } => {
+                if session.game.is_none() {
+                    info!("Playing {} as player {}", level.name, player + 1);
+                }
+                session.game.get_or_insert_with(|| OnlineGame {
+                    player,
+                    config: *config,
+                    level,
+                    snapshots: SnapshotBuffer::new(tick_seconds),
+                });
+            }
+            ServerMessage::Full => {
+                warn!("The server is full");
+                game_state.set(GameState::MainMenu);
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                if let Some(game) = &mut session.game {
+                    game.snapshots.push(snapshot);
+                }
+            }
+        }
+    }
+}
+
+/// Draws the server's game a moment in the past, in between the snapshots, so the snakes
+/// move smoothly whatever the frame rate.
+fn draw_online_game(
+    mut session: ResMut<OnlineSession>,
+    time: Res<Time>,
+    mut gizmos: Gizmos,
+    scoreboards: Query<(Entity, &ScoreboardUi), With<Text>>,
+    mut writer: TextUiWriter,
+) {
+    let Some(game) = &mut session.game else {
+        return;
+    };
+    let Some(snapshot) = game.snapshots.advance(time.delta_secs()) else {
+        return;
+    };
+    draw_snapshot(&mut gizmos, &game.level, &game.config, &snapshot);
+    show_score(&scoreboards, &mut writer, &snapshot, game.player);
+}
+
+/// Draws a networked game with gizmos. Its snakes are the simulation's, not entities of
+/// the local game, so they have none of its sprites or colliders.
+fn draw_snapshot(gizmos: &mut Gizmos, level: &Level, config: &SnakeConfig, snapshot: &Snapshot) {
+    gizmos.rect_2d(
+        Vec2::ZERO,
+        level.arena.inner_half_size() * 2.0,
+        ONLINE_WALL_COLOR,
+    );
+    for obstacle in &level.obstacles {
+        match obstacle {
+            Obstacle::Rectangle { center, size } => {
+                gizmos.rect_2d(*center, *size, ONLINE_WALL_COLOR);
+            }
+            Obstacle::Polygon { points } => {
+                let outline = points.iter().chain(points.first()).copied();
+                gizmos.linestrip_2d(outline, ONLINE_WALL_COLOR);
+            }
+        }
+    }
+    for apple in &snapshot.apples {
+        gizmos.circle_2d(apple.position, config.apple_radius, ONLINE_APPLE_COLOR);
+    }
+    for snake in &snapshot.snakes {
+        let (joint_color, part_color) = PLAYER_COLORS[snake.player % PLAYER_COLORS.len()];
+        // Crashed snakes fade until they respawn.
+        let alpha = if snake.alive { 1.0 } else { 0.3 };
+        gizmos.linestrip_2d(snake.segments.iter().copied(), part_color.with_alpha(alpha));
+        if let Some(head) = snake.head() {
+            let radius = config.head_thickness / 2.0;
+            gizmos.circle_2d(head, radius, joint_color.with_alpha(alpha));
+        }
+    }
+}
+
+/// Shows `player`'s score in a networked game on the first scoreboard.
+fn show_score(
+    scoreboards: &Query<(Entity, &ScoreboardUi), With<Text>>,
+    writer: &mut TextUiWriter,
+    snapshot: &Snapshot,
+    player: usize,
+) {
+    if let Some(own) = snapshot.snake(player)
+        && let Some((score_root, _)) = scoreboards.iter().find(|(_, scoreboard)| scoreboard.0 == 0)
+    {
+        *writer.text(score_root, 1) = own.score.to_string();
+    }
+}
@@ -0,0 +1,243 @@
+//! Power-ups: the pickups lying around the level, what they do while they last and the
+//! text that shows which are active.
+
+use std::f32::consts::FRAC_PI_4;
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use snake::{
+    config::SnakeConfig,
+    fabrik::{GameLayer, HeadOfSnake, Limb, PartOf, SnakeParts},
+    grid::GridTrail,
+    level::Level,
+    mode::{Players, Walls},
+    powerup::{ActivePowerUps, PowerUp},
+};
+
+use crate::{
+    SimulationRng,
+    apples::{Apple, place_apple, snake_limbs},
+    players::Player,
+};
+
+pub struct PickupsPlugin;
+
+impl Plugin for PickupsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            update_power_up_ui.run_if(any_match_filter::<Changed<ActivePowerUps>>),
+        );
+    }
+}
+
+/// Lists the running power-ups under the score.
+#[derive(Component)]
+pub struct PowerUpUi;
+
+/// Index of the pickup's power-up in `SnakeConfig::power_ups`.
+#[derive(Component)]
+pub struct PowerUpPickup(usize);
+
+pub const POWER_UP_FONT_SIZE: f32 = 22.0;
+
+pub const POWER_UP_TEXT_TOP: Val = Val::Px(45.0);
+
+/// Stops every running power-up and replaces the pickups with `power_up_count` fresh ones,
+/// clear of the apples.
+pub fn reset_power_ups(
+    mut commands: Commands,
+    mut power_ups: Query<&mut ActivePowerUps>,
+    pickups: Query<Entity, With<PowerUpPickup>>,
+    apples: Query<&Transform, With<Apple>>,
+    snakes: Query<(&Limb, Option<&GridTrail>)>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    walls: Res<Walls>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    for mut power_ups in &mut power_ups {
+        power_ups.clear();
+    }
+    let (limbs, grid_trail) = snake_limbs(&snakes);
+    for entity in pickups {
+        commands.entity(entity).despawn();
+    }
+    let mut positions: Vec<Vec2> = apples
+        .iter()
+        .map(|transform| transform.translation.truncate())
+        .collect();
+    for _ in 0..config.power_up_count {
+        let Some(kind) = config.random_power_up(&mut **rng) else {
+            return;
+        };
+        let position = place_apple(
+            &config, &level, &limbs, &positions, grid_trail, *walls, &mut rng,
+        );
+        positions.push(position);
+        commands.spawn(power_up_pickup(&config, kind, position));
+    }
+}
+
+fn power_up_pickup(config: &SnakeConfig, kind: usize, position: Vec2) -> impl Bundle {
+    (
+        Sprite::from_color(
+            power_up_color(config.power_ups[kind].effect),
+            Vec2::splat(config.apple_radius * 1.5),
+        ),
+        Transform::from_translation(position.extend(-10.0))
+            .with_rotation(Quat::from_rotation_z(FRAC_PI_4)),
+        RigidBody::Kinematic,
+        Collider::circle(config.apple_radius),
+        CollisionLayers::new(GameLayer::Apple, [GameLayer::Default, GameLayer::SnakeHead]),
+        Sensor,
+        PowerUpPickup(kind),
+    )
+}
+
+fn power_up_color(power_up: PowerUp) -> Color {
+    match power_up {
+        PowerUp::SpeedBoost => Color::srgb(1.0, 0.3, 0.1),
+        PowerUp::SlowMotion => Color::srgb(0.3, 0.6, 1.0),
+        PowerUp::Ghost => Color::srgba(0.9, 0.9, 1.0, 0.6),
+        PowerUp::Magnet => Color::srgb(0.7, 0.2, 0.9),
+        PowerUp::ScoreMultiplier => Color::srgb(1.0, 0.85, 0.1),
+    }
+}
+
+pub fn tick_power_ups(mut power_ups: Query<&mut ActivePowerUps>) {
+    for mut power_ups in &mut power_ups {
+        power_ups.tick();
+    }
+}
+
+/// Starts the power-up of every pickup a head touched on that head's snake and moves the
+/// pickup somewhere else as a newly picked power-up.
+pub fn collect_power_ups(
+    mut collision_reader: MessageReader<CollisionStart>,
+    mut pickups: Query<(Entity, &mut PowerUpPickup, &mut Transform, &mut Sprite)>,
+    apples: Query<&Transform, (With<Apple>, Without<PowerUpPickup>)>,
+    heads: Query<&PartOf, With<HeadOfSnake>>,
+    mut power_ups: Query<&mut ActivePowerUps>,
+    snakes: Query<(&Limb, Option<&GridTrail>)>,
+    time: Res<Time>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    walls: Res<Walls>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    let (limbs, grid_trail) = snake_limbs(&snakes);
+    for event in collision_reader.read() {
+        let colliders = [event.collider1, event.collider2];
+        let Some(collected) = colliders
+            .into_iter()
+            .find(|entity| pickups.contains(*entity))
+        else {
+            continue;
+        };
+        let Some(mut power_ups) = colliders
+            .into_iter()
+            .find_map(|entity| heads.get(entity).ok())
+            .and_then(|part_of| power_ups.get_mut(part_of.0).ok())
+        else {
+            continue;
+        };
+        let others: Vec<Vec2> = pickups
+            .iter()
+            .filter(|(entity, ..)| *entity != collected)
+            .map(|(_, _, transform, _)| transform)
+            .chain(&apples)
+            .map(|transform| transform.translation.truncate())
+            .collect();
+        let Ok((_, mut pickup, mut transform, mut sprite)) = pickups.get_mut(collected) else {
+            continue;
+        };
+        let power_up = &config.power_ups[pickup.0];
+        power_ups.activate(
+            power_up.effect,
+            (power_up.seconds / time.delta_secs()).round() as u32,
+        );
+
+        pickup.0 = config.random_power_up(&mut **rng).unwrap_or(pickup.0);
+        sprite.color = power_up_color(config.power_ups[pickup.0].effect);
+        let position = place_apple(
+            &config, &level, &limbs, &others, grid_trail, *walls, &mut rng,
+        );
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+/// Lets a player's head pass through the bodies while a ghost power-up runs on them.
+pub fn apply_ghost(
+    players: Query<(&ActivePowerUps, &SnakeParts), With<Player>>,
+    mut heads: Query<&mut CollisionLayers, With<HeadOfSnake>>,
+) {
+    for (power_ups, parts) in &players {
+        let mut head_layers = heads.iter_many_mut(parts.iter());
+        while let Some(mut head_layers) = head_layers.fetch_next() {
+            let mut filters = head_layers.filters;
+            if power_ups.is_active(PowerUp::Ghost) {
+                filters.remove(GameLayer::SnakePart);
+            } else {
+                filters.add(GameLayer::SnakePart);
+            }
+            if head_layers.filters != filters {
+                head_layers.filters = filters;
+            }
+        }
+    }
+}
+
+/// Pulls apples within `apple_field_radius` of a head towards it while a magnet runs on
+/// that player.
+pub fn magnet_apples(
+    players: Query<(&ActivePowerUps, &Limb), With<Player>>,
+    mut apples: Query<&mut Transform, With<Apple>>,
+    time: Res<Time>,
+    config: Res<SnakeConfig>,
+) {
+    let max_pull = config.magnet_pull * time.delta_secs();
+    for (power_ups, limb) in &players {
+        if !power_ups.is_active(PowerUp::Magnet) {
+            continue;
+        }
+        let head = limb.get_last_segment_position();
+        for mut transform in &mut apples {
+            let offset = head - transform.translation.truncate();
+            if offset.length() <= config.apple_field_radius {
+                let pull = offset.clamp_length_max(max_pull);
+                transform.translation.x += pull.x;
+                transform.translation.y += pull.y;
+            }
+        }
+    }
+}
+
+fn update_power_up_ui(
+    players: Query<(&Player, &ActivePowerUps)>,
+    player_count: Res<Players>,
+    fixed_time: Res<Time<Fixed>>,
+    mut text: Single<&mut Text, With<PowerUpUi>>,
+) {
+    let tick_seconds = fixed_time.timestep().as_secs_f32();
+    let mut players: Vec<_> = players.iter().collect();
+    players.sort_by_key(|(player, _)| player.0);
+    text.0 = players
+        .into_iter()
+        .flat_map(|(player, power_ups)| {
+            let prefix = match *player_count {
+                Players::Solo => String::new(),
+                Players::Versus => format!("P{} ", player.0 + 1),
+            };
+            power_ups.iter().map(move |(power_up, ticks)| {
+                format!(
+                    "{prefix}{} {:.1}s",
+                    power_up.label(),
+                    ticks as f32 * tick_seconds
+                )
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("   ");
+}
@@ -0,0 +1,248 @@
+//! Picking apple positions that are clear of the snakes and the level's walls.
+
+use glam::Vec2;
+use rand::Rng;
+
+use crate::{
+    config::SnakeConfig,
+    fabrik::Limb,
+    grid,
+    level::{Level, distance_to_line_segment},
+};
+
+const MAX_ATTEMPTS: usize = 64;
+
+pub struct ApplePlacement {
+    /// Free space required around the apple, usually its radius.
+    pub clearance: f32,
+    /// Half the thickness of the snake's body.
+    pub body_radius: f32,
+    pub min_head_distance: f32,
+    pub max_attempts: usize,
+    /// Whether the arena wraps around, so the snake can be close to an apple across
+    /// the opposite wall.
+    pub wraps: bool,
+}
+
+impl ApplePlacement {
+    pub fn from_config(config: &SnakeConfig) -> Self {
+        Self {
+            clearance: config.apple_radius,
+            body_radius: config.part_thickness / 2.0,
+            min_head_distance: config.apple_min_head_distance,
+            max_attempts: MAX_ATTEMPTS,
+            wraps: false,
+        }
+    }
+
+    /// How much room `position` has to spare. Negative when it overlaps one of the `snakes`,
+    /// a wall or one of the other `apples`, or is too close to a head.
+    pub fn free_space(
+        &self,
+        level: &Level,
+        snakes: &[&Limb],
+        apples: &[Vec2],
+        position: Vec2,
+    ) -> f32 {
+        // In a wrap-around arena, also measure to the copies of `position` next door.
+        let period = level.arena.period();
+        let images: Vec<Vec2> = if self.wraps {
+            [-1.0, 0.0, 1.0]
+                .into_iter()
+                .flat_map(|x| [-1.0, 0.0, 1.0].map(|y| position + Vec2::new(x, y) * period))
+                .collect()
+        } else {
+            vec![position]
+        };
+        let mut body_distance = f32::INFINITY;
+        let mut head_distance = f32::INFINITY;
+        for limb in snakes {
+            let segments = limb.segments();
+            body_distance = (0..segments.len().saturating_sub(1))
+                .flat_map(|i| {
+                    let (start, end) = (segments[i].position(), segments[i + 1].position());
+                    // Bring each segment into the arena by the same amount as its head end.
+                    let offset = if self.wraps {
+                        level.arena.wrap(end) - end
+                    } else {
+                        Vec2::ZERO
+                    };
+                    images.iter().map(move |image| {
+                        distance_to_line_segment(*image, start + offset, end + offset)
+                    })
+                })
+                .fold(body_distance, f32::min);
+            let head = limb.get_last_segment_position();
+            head_distance = images
+                .iter()
+                .map(|image| image.distance(head))
+                .fold(head_distance, f32::min);
+        }
+
+        let apple_distance = apples
+            .iter()
+            .flat_map(|apple| images.iter().map(|image| image.distance(*apple)))
+            .fold(f32::INFINITY, f32::min);
+
+        (body_distance - self.body_radius - self.clearance)
+            .min(level.distance_to_nearest_wall(position) - self.clearance)
+            .min(head_distance - self.min_head_distance)
+            .min(apple_distance - self.clearance * 2.0)
+    }
+
+    /// Samples the level's apple zones for a free position. When the arena is too crowded
+    /// to find one, the roomiest position that was sampled is used instead.
+    pub fn find_position(
+        &self,
+        level: &Level,
+        snakes: &[&Limb],
+        apples: &[Vec2],
+        rng: &mut impl Rng,
+    ) -> Vec2 {
+        self.search(level, snakes, apples, rng, |position| position)
+    }
+
+    /// Like [`ApplePlacement::find_position`], but only ever picks cell centres.
+    pub fn find_cell(
+        &self,
+        level: &Level,
+        snakes: &[&Limb],
+        apples: &[Vec2],
+        cell_size: f32,
+        rng: &mut impl Rng,
+    ) -> Vec2 {
+        self.search(level, snakes, apples, rng, |position| {
+            grid::snap(position, cell_size)
+        })
+    }
+
+    fn search(
+        &self,
+        level: &Level,
+        snakes: &[&Limb],
+        apples: &[Vec2],
+        rng: &mut impl Rng,
+        snap: impl Fn(Vec2) -> Vec2,
+    ) -> Vec2 {
+        let mut best: Option<(f32, Vec2)> = None;
+        for _ in 0..self.max_attempts.max(1) {
+            let candidate = snap(level.random_apple_position(rng));
+            let free_space = self.free_space(level, snakes, apples, candidate);
+            if free_space >= 0.0 {
+                return candidate;
+            }
+            if best.is_none_or(|(best_free_space, _)| free_space > best_free_space) {
+                best = Some((free_space, candidate));
+            }
+        }
+        best.map(|(_, position)| position).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::level::Obstacle;
+
+    fn snake(level: &Level, config: &SnakeConfig) -> Limb {
+        Limb::new(level.spawn, level.spawn, &config.segment_lengths())
+    }
+
+    #[test]
+    fn apples_avoid_the_body_head_and_obstacles() {
+        let config = SnakeConfig::default();
+        let level = Level {
+            obstacles: vec![Obstacle::Rectangle {
+                center: Vec2::new(0.0, 120.0),
+                size: Vec2::new(200.0, 60.0),
+            }],
+            ..Level::default()
+        };
+        let limb = snake(&level, &config);
+        let placement = ApplePlacement::from_config(&config);
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..200 {
+            let position = placement.find_position(&level, &[&limb], &[], &mut rng);
+            assert!(placement.free_space(&level, &[&limb], &[], position) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn apples_keep_clear_of_each_other() {
+        let config = SnakeConfig::default();
+        let level = Level::default();
+        let limb = snake(&level, &config);
+        let placement = ApplePlacement::from_config(&config);
+        let mut rng = StdRng::seed_from_u64(9);
+        let mut apples = Vec::new();
+        for _ in 0..5 {
+            let position = placement.find_position(&level, &[&limb], &apples, &mut rng);
+            assert!(placement.free_space(&level, &[&limb], &apples, position) >= 0.0);
+            apples.push(position);
+        }
+    }
+
+    #[test]
+    fn apples_keep_clear_of_every_snake() {
+        let config = SnakeConfig::default();
+        let level = Level::default();
+        let limb = snake(&level, &config);
+        let mut other = snake(&level, &config);
+        other.translate(Vec2::new(0.0, -120.0));
+        let placement = ApplePlacement::from_config(&config);
+        let on_the_other_tail = other.segments()[0].position() + Vec2::Y * 5.0;
+        assert!(placement.free_space(&level, &[&limb], &[], on_the_other_tail) >= 0.0);
+        assert!(placement.free_space(&level, &[&limb, &other], &[], on_the_other_tail) < 0.0);
+    }
+
+    #[test]
+    fn grid_apples_land_on_free_cell_centres() {
+        let config = SnakeConfig::default();
+        let level = Level::default();
+        let limb = snake(&level, &config);
+        let placement = ApplePlacement::from_config(&config);
+        let mut rng = StdRng::seed_from_u64(5);
+        for _ in 0..50 {
+            let position = placement.find_cell(&level, &[&limb], &[], 20.0, &mut rng);
+            assert_eq!(grid::snap(position, 20.0), position);
+            assert!(placement.free_space(&level, &[&limb], &[], position) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn wrapping_arenas_keep_apples_clear_across_the_walls() {
+        let config = SnakeConfig::default();
+        let level = Level::default();
+        let mut limb = snake(&level, &config);
+        // Put the head just inside the left wall.
+        let half_width = level.arena.width / 2.0;
+        let head = limb.get_last_segment_position();
+        limb.translate(Vec2::new(15.0 - half_width - head.x, 0.0));
+        let placement = ApplePlacement {
+            wraps: true,
+            ..ApplePlacement::from_config(&config)
+        };
+        let across_the_wall = Vec2::new(half_width - 50.0, head.y);
+        assert!(placement.free_space(&level, &[&limb], &[], across_the_wall) < 0.0);
+        assert!(
+            ApplePlacement::from_config(&config).free_space(&level, &[&limb], &[], across_the_wall)
+                >= 0.0
+        );
+    }
+
+    #[test]
+    fn crowded_arenas_fall_back_to_the_roomiest_sample() {
+        let config = SnakeConfig::default();
+        let level = Level::default();
+        let limb = snake(&level, &config);
+        let placement = ApplePlacement {
+            min_head_distance: 10_000.0,
+            ..ApplePlacement::from_config(&config)
+        };
+        let mut rng = StdRng::seed_from_u64(3);
+        let position = placement.find_position(&level, &[&limb], &[], &mut rng);
+        assert!(level.arena.contains(position));
+    }
+}
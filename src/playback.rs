@@ -0,0 +1,354 @@
+//! Recording every game and watching it back, either the last one played or a replay file
+//! opened with `--replay`.
+
+use bevy::{
+    app::FixedMain, input_focus::tab_navigation::TabGroup, prelude::*, ui::RelativeCursorPosition,
+};
+use snake::{
+    args::arg_value,
+    config::SnakeConfig,
+    level::Level,
+    mode::{GameMode, Players, Walls},
+    replay::Replay,
+    rival::Rivals,
+    storage,
+};
+
+use crate::{
+    GameState, ResetGame, SCORE_COLOR, SimulationSeed, SimulationTick, TickInput,
+    insert_snake_config,
+    menus::{ButtonAction, button},
+    players::Player,
+    reset_game,
+    stage::{LevelAssets, SelectedLevel, select_first_level},
+};
+
+pub struct PlaybackPlugin;
+
+impl Plugin for PlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnExit(GameState::Loading),
+            open_replay_from_args.after(select_first_level),
+        )
+        .add_systems(
+            OnEnter(GameState::Replay),
+            (load_replay, reset_game, replay_controls).chain(),
+        )
+        .add_systems(
+            OnExit(GameState::Replay),
+            (stop_replay, insert_snake_config, restore_selected_level),
+        )
+        .add_systems(
+            OnEnter(GameState::GameOver),
+            save_replay.run_if(resource_exists_and_changed::<Recording>),
+        )
+        .add_systems(
+            Update,
+            (
+                playback_speed_button,
+                (scrub_replay, seek_replay).chain(),
+                update_replay_progress,
+            )
+                .run_if(in_state(GameState::Replay)),
+        );
+    }
+}
+
+const PLAYBACK_SPEEDS: [f32; 4] = [0.5, 1.0, 2.0, 4.0];
+
+const DEFAULT_PLAYBACK_SPEED: usize = 1;
+
+/// How many of the latest games are kept as replays.
+const SAVED_REPLAYS: usize = 10;
+
+/// Storage key of the replay slot the next game is saved to.
+const NEXT_REPLAY_SLOT_KEY: &str = "next-replay";
+
+/// Replay of the game being played, or of the last one once it is over.
+#[derive(Resource, Deref, DerefMut)]
+pub struct Recording(Replay);
+
+/// A replay file opened with `--replay`, watched instead of the last game until a new one
+/// is played.
+#[derive(Resource, Deref)]
+struct OpenedReplay(Replay);
+
+/// Present while a replay is being watched.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    speed_index: usize,
+    /// Tick the scrubber asked to jump to.
+    seek_to: Option<u32>,
+    /// What was picked in the main menu before the replay's own settings replaced it.
+    menu_selection: (GameMode, Walls, Rivals, Players),
+}
+
+#[derive(Component)]
+struct ReplayScrubber;
+
+#[derive(Component)]
+struct ReplayProgress;
+
+pub fn replay_tick_input(
+    playback: Res<ReplayPlayback>,
+    tick: Res<SimulationTick>,
+    mut snakes: Query<(&Player, &mut TickInput)>,
+) {
+    for (player, mut tick_input) in &mut snakes {
+        **tick_input = playback.replay.input_at(player.0, **tick);
+    }
+}
+
+pub fn start_recording(
+    mut commands: Commands,
+    simulation_seed: Res<SimulationSeed>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    mode: Res<GameMode>,
+    walls: Res<Walls>,
+    rivals: Res<Rivals>,
+    players: Res<Players>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    if playback.is_some() {
+        return;
+    }
+    commands.remove_resource::<OpenedReplay>();
+    commands.insert_resource(Recording(Replay::new(
+        simulation_seed.seed,
+        config.clone(),
+        level.clone(),
+        *mode,
+        *walls,
+        *rivals,
+        *players,
+    )));
+}
+
+pub fn record_tick_input(snakes: Query<(&Player, &TickInput)>, mut recording: ResMut<Recording>) {
+    for (player, tick_input) in &snakes {
+        recording.record(player.0, **tick_input);
+    }
+}
+
+/// Saves the game just played over the oldest of the last [`SAVED_REPLAYS`], so replays
+/// never pile up in storage, which is small on the web.
+fn save_replay(recording: Res<Recording>) {
+    let slot = storage::read(NEXT_REPLAY_SLOT_KEY)
+        .and_then(|slot| slot.trim().parse::<usize>().ok())
+        .unwrap_or(0)
+        % SAVED_REPLAYS;
+    let key = format!("replay-{slot}");
+    let saved = recording
+        .to_ron()
+        .map_err(std::io::Error::other)
+        .and_then(|text| storage::write(&key, &text))
+        .and_then(|()| {
+            storage::write(
+                NEXT_REPLAY_SLOT_KEY,
+                &((slot + 1) % SAVED_REPLAYS).to_string(),
+            )
+        });
+    match saved {
+        Ok(()) => info!("Saved replay to {}", storage::location(&key)),
+        Err(error) => warn!("Could not save replay {key}: {error}"),
+    }
+}
+
+/// Watches the replay file passed with `--replay PATH` instead of showing the main menu.
+fn open_replay_from_args(mut commands: Commands, mut game_state: ResMut<NextState<GameState>>) {
+    let Some(path) = arg_value(std::env::args().skip(1), "--replay") else {
+        return;
+    };
+    match Replay::open(std::path::Path::new(&path)) {
+        Ok(replay) => {
+            commands.insert_resource(OpenedReplay(replay));
+            game_state.set(GameState::Replay);
+        }
+        Err(error) => warn!("Could not open replay {path}: {error}"),
+    }
+}
+
+fn load_replay(
+    mut commands: Commands,
+    opened: Option<Res<OpenedReplay>>,
+    recording: Option<Res<Recording>>,
+    mode: Res<GameMode>,
+    walls: Res<Walls>,
+    rivals: Res<Rivals>,
+    players: Res<Players>,
+) {
+    let Some(replay) = opened
+        .as_deref()
+        .map(|opened| &opened.0)
+        .or(recording.as_deref().map(|recording| &recording.0))
+    else {
+        return;
+    };
+    commands.insert_resource(replay.config.clone());
+    commands.insert_resource(replay.level.clone());
+    // The replay file may have been played with other settings than the ones picked in
+    // the main menu, which `stop_replay` puts back.
+    commands.insert_resource(replay.mode);
+    commands.insert_resource(replay.walls);
+    commands.insert_resource(replay.rivals);
+    commands.insert_resource(replay.players);
+    commands.insert_resource(ReplayPlayback {
+        replay: replay.clone(),
+        speed_index: DEFAULT_PLAYBACK_SPEED,
+        seek_to: None,
+        menu_selection: (*mode, *walls, *rivals, *players),
+    });
+}
+
+fn stop_replay(
+    mut commands: Commands,
+    playback: Option<Res<ReplayPlayback>>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    if let Some(playback) = playback {
+        let (mode, walls, rivals, players) = playback.menu_selection;
+        commands.insert_resource(mode);
+        commands.insert_resource(walls);
+        commands.insert_resource(rivals);
+        commands.insert_resource(players);
+    }
+    commands.remove_resource::<ReplayPlayback>();
+    time.set_relative_speed(1.0);
+}
+
+fn restore_selected_level(
+    mut commands: Commands,
+    selected_level: Res<SelectedLevel>,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<Level>>,
+) {
+    if let Some(level) = level_assets
+        .levels
+        .get(**selected_level)
+        .and_then(|handle| levels.get(handle))
+    {
+        commands.insert_resource(level.clone());
+    }
+}
+
+fn replay_controls(mut commands: Commands) {
+    commands.spawn((
+        DespawnOnExit(GameState::Replay),
+        TabGroup::new(0),
+        Node {
+            width: percent(100),
+            position_type: PositionType::Absolute,
+            bottom: px(20),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            column_gap: px(20),
+            ..default()
+        },
+        children![
+            button(
+                playback_speed_label(PLAYBACK_SPEEDS[DEFAULT_PLAYBACK_SPEED]),
+                ButtonAction::PlaybackSpeed,
+            ),
+            (
+                ReplayScrubber,
+                Interaction::default(),
+                RelativeCursorPosition::default(),
+                Node {
+                    width: px(400),
+                    height: px(20),
+                    border: UiRect::all(px(3)),
+                    ..default()
+                },
+                BorderColor::all(Color::WHITE),
+                BackgroundColor(Color::BLACK),
+                children![(
+                    ReplayProgress,
+                    Node {
+                        width: percent(0),
+                        height: percent(100),
+                        ..default()
+                    },
+                    BackgroundColor(SCORE_COLOR),
+                )],
+            ),
+            button("Stop", ButtonAction::StopReplay),
+        ],
+    ));
+}
+
+fn playback_speed_label(speed: f32) -> String {
+    format!("Speed: {speed}x")
+}
+
+fn playback_speed_button(
+    interaction_query: Query<(&Interaction, &ButtonAction, &Children), Changed<Interaction>>,
+    mut texts: Query<&mut Text>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut time: ResMut<Time<Virtual>>,
+) {
+    for (interaction, action, children) in interaction_query {
+        if *interaction != Interaction::Pressed || !matches!(action, ButtonAction::PlaybackSpeed) {
+            continue;
+        }
+        playback.speed_index = (playback.speed_index + 1) % PLAYBACK_SPEEDS.len();
+        let speed = PLAYBACK_SPEEDS[playback.speed_index];
+        time.set_relative_speed(speed);
+        if let Some(mut text) = texts.iter_many_mut(children).fetch_next() {
+            **text = playback_speed_label(speed);
+        }
+    }
+}
+
+fn scrub_replay(
+    scrubber: Query<
+        (&Interaction, &RelativeCursorPosition),
+        (Changed<Interaction>, With<ReplayScrubber>),
+    >,
+    mut playback: ResMut<ReplayPlayback>,
+) {
+    for (interaction, cursor) in scrubber {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(position) = cursor.normalized {
+            // `normalized` is relative to the centre of the bar.
+            let fraction = (position.x + 0.5).clamp(0.0, 1.0);
+            playback.seek_to = Some((fraction * playback.replay.len() as f32) as u32);
+        }
+    }
+}
+
+/// Jumps to the tick picked on the scrubber by restarting the replay if needed and
+/// stepping the fixed schedule until it gets there.
+fn seek_replay(world: &mut World) {
+    let Some(target) = world.resource_mut::<ReplayPlayback>().seek_to.take() else {
+        return;
+    };
+    if target < **world.resource::<SimulationTick>() {
+        world.run_schedule(ResetGame);
+    }
+
+    *world.resource_mut::<Time>() = world.resource::<Time<Fixed>>().as_generic();
+    for _ in **world.resource::<SimulationTick>()..target {
+        if matches!(
+            *world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::GameOver)
+        ) {
+            break;
+        }
+        world.run_schedule(FixedMain);
+    }
+    *world.resource_mut::<Time>() = world.resource::<Time<Virtual>>().as_generic();
+}
+
+fn update_replay_progress(
+    mut progress: Single<&mut Node, With<ReplayProgress>>,
+    playback: Res<ReplayPlayback>,
+    tick: Res<SimulationTick>,
+) {
+    let fraction = **tick as f32 / playback.replay.len().max(1) as f32;
+    progress.width = percent(100.0 * fraction.min(1.0));
+}
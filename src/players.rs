@@ -0,0 +1,231 @@
+//! The people playing: a snake each, alone or two at one keyboard in versus, and the
+//! scoreboard that keeps their scores.
+
+use std::cmp;
+
+use bevy::prelude::*;
+use snake::{
+    config::SnakeConfig,
+    fabrik::{Limb, SnakeStyle, SnakeVelocity},
+    level::Level,
+    mode::{Players, RoundResult},
+    powerup::ActivePowerUps,
+    rival,
+    turns::TurnBuffer,
+};
+
+use crate::{
+    GameState, HeadItems, HighScore, HighScoreUi, ResetGame, SimulationRng, SpeedFactor, TickInput,
+    head_features,
+    menus::cycle_button,
+    playback::{Recording, start_recording},
+};
+
+pub struct PlayersPlugin;
+
+impl Plugin for PlayersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Players>()
+            .add_systems(
+                ResetGame,
+                (
+                    // The recording is still the last game's, whose scores are being reset.
+                    reset_scores.before(start_recording),
+                    clear_round_result,
+                ),
+            )
+            .add_systems(
+                Update,
+                cycle_button::<Players>.run_if(in_state(GameState::MainMenu)),
+            )
+            .add_systems(
+                Update,
+                update_scoreboard_labels.run_if(resource_changed::<Players>),
+            );
+    }
+}
+
+/// Colour of the joints and head, and of the parts, of each player's snake.
+pub const PLAYER_COLORS: [(Color, Color); 2] = [
+    (Color::srgb(1.0, 0.647, 0.0), Color::srgb(0.2, 0.7, 0.9)),
+    (Color::srgb(0.3, 0.9, 0.3), Color::srgb(0.9, 0.4, 0.8)),
+];
+
+/// A snake a person steers, and which player they are, counted from 0.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub struct Player(pub usize);
+
+/// Points a player scored this game.
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct Score(usize);
+
+/// What each player's snake looks like, in player order.
+#[derive(Resource)]
+pub struct PlayerLooks(Vec<PlayerLook>);
+
+impl PlayerLooks {
+    pub fn new(
+        mesh: &Handle<Mesh>,
+        head_mesh: &Handle<Mesh>,
+        materials: &mut Assets<ColorMaterial>,
+    ) -> Self {
+        let looks = PLAYER_COLORS
+            .into_iter()
+            .map(|(color, part_color)| {
+                let material = materials.add(color);
+                PlayerLook {
+                    style: SnakeStyle::player(mesh.clone(), material.clone(), part_color),
+                    head_mesh: head_mesh.clone(),
+                    head_material: material,
+                }
+            })
+            .collect();
+        Self(looks)
+    }
+}
+
+struct PlayerLook {
+    style: SnakeStyle,
+    head_mesh: Handle<Mesh>,
+    head_material: Handle<ColorMaterial>,
+}
+
+/// The score of the player with this index.
+#[derive(Component)]
+pub struct ScoreboardUi(pub usize);
+
+/// The gamepads `player` steers with: any of them alone, or the one of their own in versus.
+pub fn player_gamepads<'a>(
+    gamepads: &'a Query<&Gamepad>,
+    players: Players,
+    player: usize,
+) -> impl Iterator<Item = &'a Gamepad> {
+    gamepads
+        .iter()
+        .enumerate()
+        .filter(move |(index, _)| players == Players::Solo || *index == player)
+        .map(|(_, gamepad)| gamepad)
+}
+
+/// Where player `index` starts: the level's spawn point for the first player, and
+/// somewhere clear of the snakes in `occupied` for the others.
+pub fn player_spawn_point(
+    index: usize,
+    level: &Level,
+    occupied: &[Vec2],
+    reach: f32,
+    config: &SnakeConfig,
+    rng: &mut SimulationRng,
+) -> Vec2 {
+    if index == 0 {
+        return level.spawn;
+    }
+    rival::spawn_point(
+        level,
+        occupied,
+        reach,
+        config.head_thickness,
+        config.apple_min_head_distance,
+        &mut **rng,
+    )
+}
+
+/// Spawns a snake for every player who has none yet, and despawns those of players that
+/// are no longer playing.
+pub fn spawn_players(
+    mut commands: Commands,
+    snakes: Query<(Entity, &Player, &Limb)>,
+    players: Res<Players>,
+    looks: Res<PlayerLooks>,
+    head_items: Res<HeadItems>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    let mut spawned = Vec::new();
+    let mut occupied = Vec::new();
+    for (entity, player, limb) in &snakes {
+        if player.0 >= players.count() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        spawned.push(player.0);
+        occupied.extend(limb.segments().iter().map(|segment| segment.position()));
+    }
+    for index in (0..players.count()).filter(|index| !spawned.contains(index)) {
+        let look = &looks.0[index];
+        let lengths = config.segment_lengths();
+        let mut limb = Limb::new(level.spawn, level.spawn, &lengths);
+        let reach = config.starting_reach();
+        let start = player_spawn_point(index, &level, &occupied, reach, &config, &mut rng);
+        limb.reset_limb(start, &lengths);
+        limb.set_max_bend(config.max_bend());
+        occupied.extend(limb.segments().iter().map(|segment| segment.position()));
+        let snake = commands.spawn_empty().id();
+        let head = (
+            Mesh2d(look.head_mesh.clone()),
+            MeshMaterial2d(look.head_material.clone()),
+            head_features(&head_items),
+        );
+        limb.display(snake, &mut commands, &look.style, head, &config);
+        commands.entity(snake).insert((
+            Player(index),
+            limb,
+            SnakeVelocity::default(),
+            SpeedFactor::default(),
+            Score::default(),
+            TickInput::default(),
+            TurnBuffer::default(),
+            ActivePowerUps::default(),
+            look.style.clone(),
+        ));
+    }
+}
+
+pub fn scoreboard_label(players: Players, player: usize) -> String {
+    match players {
+        Players::Solo => String::from("Score: "),
+        Players::Versus => format!("P{}: ", player + 1),
+    }
+}
+
+/// Labels the scoreboards by player in versus, and hides the second player's otherwise.
+fn update_scoreboard_labels(
+    players: Res<Players>,
+    mut scoreboards: Query<(&ScoreboardUi, &mut Text, &mut Visibility)>,
+) {
+    for (scoreboard, mut text, mut visibility) in &mut scoreboards {
+        text.0 = scoreboard_label(*players, scoreboard.0);
+        *visibility = if scoreboard.0 < players.count() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+/// Zeroes every player's score. Only a solo game's score can become the high score.
+fn reset_scores(
+    scoreboards: Query<Entity, (With<ScoreboardUi>, With<Text>)>,
+    high_score_root: Single<Entity, (With<HighScoreUi>, With<Text>)>,
+    mut writer: TextUiWriter,
+    mut scores: Query<(&Player, &mut Score)>,
+    mut high_score: ResMut<HighScore>,
+    recording: Option<Res<Recording>>,
+) {
+    let solo = recording.is_none_or(|recording| recording.players == Players::Solo);
+    for (player, mut score) in &mut scores {
+        if solo && player.0 == 0 {
+            **high_score = cmp::max(**score, **high_score);
+        }
+        **score = 0;
+    }
+    for score_root in &scoreboards {
+        *writer.text(score_root, 1) = 0.to_string();
+    }
+    *writer.text(*high_score_root, 1) = high_score.to_string();
+}
+
+fn clear_round_result(mut commands: Commands) {
+    commands.remove_resource::<RoundResult>();
+}
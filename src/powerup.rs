@@ -0,0 +1,143 @@
+//! Timed effects granted by power-up pickups, counted in simulation ticks so that
+//! replays see them run out on the same tick.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::SnakeConfig;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerUp {
+    /// The snake moves faster.
+    SpeedBoost,
+    /// The snake moves slower, which makes tight spaces easier.
+    SlowMotion,
+    /// The head passes through the body.
+    Ghost,
+    /// Apples near the head are pulled towards it.
+    Magnet,
+    /// Apples are worth more points.
+    ScoreMultiplier,
+}
+
+impl PowerUp {
+    pub fn label(self) -> &'static str {
+        match self {
+            PowerUp::SpeedBoost => "Speed",
+            PowerUp::SlowMotion => "Slow-mo",
+            PowerUp::Ghost => "Ghost",
+            PowerUp::Magnet => "Magnet",
+            PowerUp::ScoreMultiplier => "Multiplier",
+        }
+    }
+}
+
+/// The effects running on a snake right now and the ticks each has left, oldest first.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::component::Component))]
+pub struct ActivePowerUps {
+    effects: Vec<(PowerUp, u32)>,
+}
+
+impl ActivePowerUps {
+    /// Starts `power_up` for `ticks`. Picking up one that is already running restarts
+    /// its time, and speeding up cancels slowing down and vice versa.
+    pub fn activate(&mut self, power_up: PowerUp, ticks: u32) {
+        let cancels = match power_up {
+            PowerUp::SpeedBoost => Some(PowerUp::SlowMotion),
+            PowerUp::SlowMotion => Some(PowerUp::SpeedBoost),
+            _ => None,
+        };
+        self.effects
+            .retain(|(active, _)| *active != power_up && Some(*active) != cancels);
+        if ticks > 0 {
+            self.effects.push((power_up, ticks));
+        }
+    }
+
+    /// Counts down one tick and drops the effects that ran out.
+    pub fn tick(&mut self) {
+        for (_, ticks) in &mut self.effects {
+            *ticks -= 1;
+        }
+        self.effects.retain(|(_, ticks)| *ticks > 0);
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn is_active(&self, power_up: PowerUp) -> bool {
+        self.effects.iter().any(|(active, _)| *active == power_up)
+    }
+
+    /// The running effects and the ticks each has left.
+    pub fn iter(&self) -> impl Iterator<Item = (PowerUp, u32)> + '_ {
+        self.effects.iter().copied()
+    }
+
+    /// What the snake's speed is multiplied by.
+    pub fn speed_factor(&self, config: &SnakeConfig) -> f32 {
+        if self.is_active(PowerUp::SpeedBoost) {
+            config.speed_boost_factor
+        } else if self.is_active(PowerUp::SlowMotion) {
+            config.slow_motion_factor
+        } else {
+            1.0
+        }
+    }
+
+    /// What apple scores are multiplied by.
+    pub fn score_multiplier(&self, config: &SnakeConfig) -> i32 {
+        if self.is_active(PowerUp::ScoreMultiplier) {
+            config.score_multiplier
+        } else {
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn effects_run_out() {
+        let mut power_ups = ActivePowerUps::default();
+        power_ups.activate(PowerUp::Ghost, 2);
+        power_ups.activate(PowerUp::Magnet, 1);
+        power_ups.tick();
+        assert!(power_ups.is_active(PowerUp::Ghost));
+        assert!(!power_ups.is_active(PowerUp::Magnet));
+        power_ups.tick();
+        assert_eq!(power_ups.iter().count(), 0);
+    }
+
+    #[test]
+    fn picking_up_again_restarts_the_time() {
+        let mut power_ups = ActivePowerUps::default();
+        power_ups.activate(PowerUp::Ghost, 2);
+        power_ups.tick();
+        power_ups.activate(PowerUp::Ghost, 5);
+        assert_eq!(power_ups.iter().collect::<Vec<_>>(), [(PowerUp::Ghost, 5)]);
+    }
+
+    #[test]
+    fn speed_boost_and_slow_motion_cancel_out() {
+        let config = SnakeConfig::default();
+        let mut power_ups = ActivePowerUps::default();
+        power_ups.activate(PowerUp::SlowMotion, 10);
+        assert_eq!(power_ups.speed_factor(&config), config.slow_motion_factor);
+        power_ups.activate(PowerUp::SpeedBoost, 10);
+        assert!(!power_ups.is_active(PowerUp::SlowMotion));
+        assert_eq!(power_ups.speed_factor(&config), config.speed_boost_factor);
+    }
+
+    #[test]
+    fn scores_are_multiplied_while_active() {
+        let config = SnakeConfig::default();
+        let mut power_ups = ActivePowerUps::default();
+        assert_eq!(power_ups.score_multiplier(&config), 1);
+        power_ups.activate(PowerUp::ScoreMultiplier, 1);
+        assert_eq!(power_ups.score_multiplier(&config), config.score_multiplier);
+    }
+}
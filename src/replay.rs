@@ -0,0 +1,350 @@
+//! Recorded games: the seed, config, level, mode, rivals and players a game was played with
+//! and every player's steering input for each simulation tick, run-length encoded.
+
+use std::{f32::consts::TAU, fmt, fs, io, path::Path};
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{ConfigError, SnakeConfig},
+    level::{Level, LevelError},
+    mode::{GameMode, Players, Walls},
+    rival::Rivals,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub fn as_vec2(self) -> Vec2 {
+        match self {
+            Direction::Up => Vec2::Y,
+            Direction::Down => Vec2::NEG_Y,
+            Direction::Left => Vec2::NEG_X,
+            Direction::Right => Vec2::X,
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    /// The direction closest to `vector`, or `None` for a zero vector.
+    pub fn nearest(vector: Vec2) -> Option<Self> {
+        if vector == Vec2::ZERO {
+            None
+        } else if vector.x.abs() > vector.y.abs() {
+            Some(if vector.x > 0.0 {
+                Direction::Right
+            } else {
+                Direction::Left
+            })
+        } else {
+            Some(if vector.y > 0.0 {
+                Direction::Up
+            } else {
+                Direction::Down
+            })
+        }
+    }
+}
+
+/// What the player asked the snake to do on one tick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Input {
+    /// A direction to travel in, or in steering mode a way to turn.
+    Direction(Direction),
+    /// An absolute heading from an analog stick, in 256ths of a turn so that a steady
+    /// stick records as one run.
+    Heading(u8),
+}
+
+impl Input {
+    /// Quantizes `angle`, in radians counter-clockwise from +x.
+    pub fn heading(angle: f32) -> Self {
+        let steps = (angle.rem_euclid(TAU) / TAU * 256.0).round() as u32 % 256;
+        Input::Heading(steps as u8)
+    }
+
+    /// The angle of a [`Input::Heading`] in radians.
+    pub fn heading_angle(steps: u8) -> f32 {
+        f32::from(steps) / 256.0 * TAU
+    }
+}
+
+/// The same input held for `ticks` consecutive ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct InputRun {
+    input: Option<Input>,
+    ticks: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: SnakeConfig,
+    pub level: Level,
+    #[serde(default)]
+    pub mode: GameMode,
+    #[serde(default)]
+    pub walls: Walls,
+    #[serde(default)]
+    pub rivals: Rivals,
+    #[serde(default)]
+    pub players: Players,
+    inputs: Vec<InputRun>,
+    /// The second player's inputs in versus.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    second_inputs: Vec<InputRun>,
+}
+
+impl Replay {
+    pub fn new(
+        seed: u64,
+        config: SnakeConfig,
+        level: Level,
+        mode: GameMode,
+        walls: Walls,
+        rivals: Rivals,
+        players: Players,
+    ) -> Self {
+        Self {
+            seed,
+            config,
+            level,
+            mode,
+            walls,
+            rivals,
+            players,
+            inputs: Vec::new(),
+            second_inputs: Vec::new(),
+        }
+    }
+
+    fn runs(&self, player: usize) -> &[InputRun] {
+        if player == 0 {
+            &self.inputs
+        } else {
+            &self.second_inputs
+        }
+    }
+
+    /// Appends `player`'s input for the next tick.
+    pub fn record(&mut self, player: usize, input: Option<Input>) {
+        let runs = if player == 0 {
+            &mut self.inputs
+        } else {
+            &mut self.second_inputs
+        };
+        match runs.last_mut() {
+            Some(run) if run.input == input => run.ticks += 1,
+            _ => runs.push(InputRun { input, ticks: 1 }),
+        }
+    }
+
+    /// Number of recorded ticks.
+    pub fn len(&self) -> u32 {
+        self.inputs.iter().map(|run| run.ticks).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Input `player` made on `tick`, or no input past the end of the recording.
+    pub fn input_at(&self, player: usize, tick: u32) -> Option<Input> {
+        let mut start = 0;
+        for run in self.runs(player) {
+            if tick < start + run.ticks {
+                return run.input;
+            }
+            start += run.ticks;
+        }
+        None
+    }
+
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::to_string(self)
+    }
+
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(text)
+    }
+
+    /// Reads a replay file saved by the game, rejecting ones whose config or level the game
+    /// can't run.
+    pub fn open(path: &Path) -> Result<Self, ReplayError> {
+        let replay = Self::from_ron(&fs::read_to_string(path)?)?;
+        replay.config.validate()?;
+        replay.level.validate()?;
+        Ok(replay)
+    }
+}
+
+/// Why a replay file couldn't be opened.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    Config(ConfigError),
+    Level(LevelError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Parse(error) => error.fmt(f),
+            Self::Config(error) => write!(f, "invalid config: {error}"),
+            Self::Level(error) => write!(f, "invalid level: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for ReplayError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        Self::Parse(error)
+    }
+}
+
+impl From<ConfigError> for ReplayError {
+    fn from(error: ConfigError) -> Self {
+        Self::Config(error)
+    }
+}
+
+impl From<LevelError> for ReplayError {
+    fn from(error: LevelError) -> Self {
+        Self::Level(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replay() -> Replay {
+        Replay::new(
+            7,
+            SnakeConfig::default(),
+            Level::default(),
+            GameMode::Steering,
+            Walls::Wrap,
+            Rivals(2),
+            Players::Solo,
+        )
+    }
+
+    #[test]
+    fn repeated_inputs_are_run_length_encoded() {
+        let mut replay = replay();
+        let inputs = [
+            None,
+            None,
+            Some(Input::Direction(Direction::Up)),
+            Some(Input::Direction(Direction::Up)),
+            Some(Input::Direction(Direction::Up)),
+            Some(Input::Heading(3)),
+        ];
+        for input in inputs {
+            replay.record(0, input);
+        }
+        assert_eq!(replay.inputs.len(), 3);
+        assert_eq!(replay.len(), inputs.len() as u32);
+        for (tick, input) in inputs.into_iter().enumerate() {
+            assert_eq!(replay.input_at(0, tick as u32), input);
+        }
+        assert_eq!(replay.input_at(0, inputs.len() as u32), None);
+    }
+
+    #[test]
+    fn each_player_has_their_own_inputs() {
+        let mut replay = replay();
+        replay.players = Players::Versus;
+        replay.record(0, Some(Input::Direction(Direction::Up)));
+        replay.record(1, Some(Input::Direction(Direction::Down)));
+        replay.record(0, None);
+        replay.record(1, None);
+        assert_eq!(replay.len(), 2);
+        assert_eq!(replay.input_at(0, 0), Some(Input::Direction(Direction::Up)));
+        assert_eq!(
+            replay.input_at(1, 0),
+            Some(Input::Direction(Direction::Down))
+        );
+        assert_eq!(replay.input_at(1, 1), None);
+        let text = replay.to_ron().unwrap();
+        assert_eq!(Replay::from_ron(&text).unwrap(), replay);
+    }
+
+    #[test]
+    fn round_trips_through_ron() {
+        let mut replay = replay();
+        replay.record(0, Some(Input::Direction(Direction::Right)));
+        replay.record(0, Some(Input::heading(1.0)));
+        replay.record(0, None);
+        let text = replay.to_ron().unwrap();
+        assert_eq!(Replay::from_ron(&text).unwrap(), replay);
+    }
+
+    #[test]
+    fn replay_files_can_be_opened() {
+        let path = std::env::temp_dir().join(format!("snake-{}.replay.ron", std::process::id()));
+        let mut replay = replay();
+        replay.record(0, Some(Input::Direction(Direction::Left)));
+        fs::write(&path, replay.to_ron().unwrap()).unwrap();
+        assert_eq!(Replay::open(&path).unwrap(), replay);
+
+        replay.config.no_of_parts = 0;
+        fs::write(&path, replay.to_ron().unwrap()).unwrap();
+        assert!(matches!(
+            Replay::open(&path),
+            Err(ReplayError::Config(ConfigError::TooFewParts(0)))
+        ));
+
+        fs::write(&path, "not a replay").unwrap();
+        assert!(matches!(Replay::open(&path), Err(ReplayError::Parse(_))));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(Replay::open(&path), Err(ReplayError::Io(_))));
+    }
+
+    #[test]
+    fn nearest_direction_follows_the_dominant_axis() {
+        assert_eq!(
+            Direction::nearest(Vec2::new(-3.0, 1.0)),
+            Some(Direction::Left)
+        );
+        assert_eq!(
+            Direction::nearest(Vec2::new(0.5, -2.0)),
+            Some(Direction::Down)
+        );
+        assert_eq!(Direction::nearest(Vec2::ZERO), None);
+    }
+
+    #[test]
+    fn headings_are_quantized_to_256ths_of_a_turn() {
+        assert_eq!(Input::heading(0.0), Input::Heading(0));
+        assert_eq!(Input::heading(TAU / 4.0), Input::Heading(64));
+        assert_eq!(Input::heading(-TAU / 4.0), Input::Heading(192));
+        assert_eq!(Input::heading(TAU - 0.001), Input::Heading(0));
+        assert!((Input::heading_angle(64) - TAU / 4.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,314 @@
+//! Computer-controlled rival snakes: how many there are, where they start and how their
+//! brains pick a heading. New kinds of rival implement [`Steer`].
+
+use std::f32::consts::{FRAC_PI_6, PI};
+
+use glam::Vec2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::level::Level;
+
+/// Most rivals the player can pick from the main menu.
+pub const MAX_RIVALS: usize = 3;
+/// Headings a brain tries either side of the one it wants, in radians.
+const HEADING_STEP: f32 = FRAC_PI_6;
+/// Distance between the points checked along a heading.
+const PROBE_STEP: f32 = 10.0;
+/// How far in front of the player's head an aggressive rival aims.
+const CUT_OFF_LEAD: f32 = 80.0;
+/// Aggressive rivals only go after a player this close, and eat apples otherwise.
+const CHASE_RANGE: f32 = 400.0;
+const MAX_SPAWN_ATTEMPTS: usize = 64;
+
+/// Number of rivals in a game.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::resource::Resource))]
+#[serde(transparent)]
+pub struct Rivals(pub usize);
+
+impl Rivals {
+    /// One more rival, back to none after [`MAX_RIVALS`].
+    pub fn next(self) -> Self {
+        Rivals((self.0 + 1) % (MAX_RIVALS + 1))
+    }
+}
+
+/// Picks the heading a rival should turn towards.
+pub trait Steer: Send + Sync {
+    fn steer(&self, view: &View) -> f32;
+}
+
+/// Makes straight for the nearest apple.
+pub struct Greedy;
+
+/// Goes for apples too, but looks twice as far ahead and keeps twice the room.
+pub struct Cautious;
+
+/// Heads for a point just in front of the player to cut them off.
+pub struct Aggressive;
+
+/// The built-in brains, by the names config files and `snake-bot --brain` use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Brain {
+    Greedy,
+    Cautious,
+    Aggressive,
+}
+
+/// What a rival knows about the world when it picks a heading.
+pub struct View<'a> {
+    pub head: Vec2,
+    /// Current heading in radians counter-clockwise from +x.
+    pub heading: f32,
+    pub apples: &'a [Vec2],
+    pub level: &'a Level,
+    /// Body points of every snake to keep clear of, the rival's own head point left out.
+    pub bodies: &'a [Vec2],
+    /// The player's head and direction of travel.
+    pub prey: Option<(Vec2, Vec2)>,
+    /// How far ahead to look for trouble.
+    pub lookahead: f32,
+    /// Room to keep between the head and anything solid.
+    pub clearance: f32,
+}
+
+impl View<'_> {
+    /// Whether the head can go `distance` along `heading` without coming within
+    /// `clearance` of a wall or a body.
+    fn is_clear(&self, heading: f32, distance: f32, clearance: f32) -> bool {
+        let direction = Vec2::from_angle(heading);
+        let steps = (distance / PROBE_STEP).ceil() as usize;
+        (1..=steps).all(|step| {
+            let point = self.head + direction * (step as f32 * PROBE_STEP);
+            self.level.distance_to_nearest_wall(point) > clearance
+                && self
+                    .bodies
+                    .iter()
+                    .all(|body| body.distance(point) > clearance)
+        })
+    }
+
+    pub fn nearest_apple(&self) -> Option<Vec2> {
+        self.apples.iter().copied().min_by(|a, b| {
+            a.distance_squared(self.head)
+                .total_cmp(&b.distance_squared(self.head))
+        })
+    }
+
+    /// The way to `target` when that is clear, or else the clear heading closest to it.
+    /// Without a target the rival keeps its heading if it can. `caution` scales how far
+    /// ahead to look and how much room to keep.
+    pub fn clear_heading_towards(&self, target: Option<Vec2>, caution: f32) -> f32 {
+        let wanted = target.map_or(self.heading, |target| (target - self.head).to_angle());
+        let steps = (PI / HEADING_STEP).round() as i32;
+        (0..=steps)
+            .flat_map(|step| [step, -step])
+            .map(|step| wanted + step as f32 * HEADING_STEP)
+            .find(|heading| {
+                self.is_clear(*heading, self.lookahead * caution, self.clearance * caution)
+            })
+            .unwrap_or(self.heading)
+    }
+}
+
+impl Steer for Greedy {
+    fn steer(&self, view: &View) -> f32 {
+        view.clear_heading_towards(view.nearest_apple(), 1.0)
+    }
+}
+
+impl Steer for Cautious {
+    fn steer(&self, view: &View) -> f32 {
+        view.clear_heading_towards(view.nearest_apple(), 2.0)
+    }
+}
+
+impl Steer for Aggressive {
+    fn steer(&self, view: &View) -> f32 {
+        let target = view
+            .prey
+            .filter(|(head, _)| head.distance(view.head) < CHASE_RANGE)
+            .map(|(head, direction)| head + direction.normalize_or_zero() * CUT_OFF_LEAD)
+            .or_else(|| view.nearest_apple());
+        view.clear_heading_towards(target, 1.0)
+    }
+}
+
+impl Brain {
+    pub const ALL: [Brain; 3] = [Brain::Greedy, Brain::Cautious, Brain::Aggressive];
+
+    /// The brain this name stands for.
+    pub fn build(self) -> Box<dyn Steer> {
+        match self {
+            Brain::Greedy => Box::new(Greedy),
+            Brain::Cautious => Box::new(Cautious),
+            Brain::Aggressive => Box::new(Aggressive),
+        }
+    }
+}
+
+/// Which of two snakes that meet head-on die: the shorter one, or both when they are the
+/// same length.
+pub fn head_on_casualties(length: usize, other_length: usize) -> (bool, bool) {
+    (length <= other_length, other_length <= length)
+}
+
+/// Picks where a rival's tail starts, with its body laid out `reach` towards -x from there
+/// like the player's. The body keeps `clearance` from the walls and `spacing` from the
+/// `occupied` points. The roomiest sampled spot is used when none is clear.
+pub fn spawn_point(
+    level: &Level,
+    occupied: &[Vec2],
+    reach: f32,
+    clearance: f32,
+    spacing: f32,
+    rng: &mut impl Rng,
+) -> Vec2 {
+    let free_space = |tail: Vec2| {
+        let steps = (reach / PROBE_STEP).ceil() as usize;
+        (0..=steps)
+            .map(|step| tail - Vec2::X * (step as f32 * PROBE_STEP).min(reach))
+            .map(|point| {
+                let body_distance = occupied
+                    .iter()
+                    .map(|other| other.distance(point))
+                    .fold(f32::INFINITY, f32::min);
+                (level.distance_to_nearest_wall(point) - clearance).min(body_distance - spacing)
+            })
+            .fold(f32::INFINITY, f32::min)
+    };
+    let mut best: Option<(f32, Vec2)> = None;
+    for _ in 0..MAX_SPAWN_ATTEMPTS {
+        let candidate = level.random_apple_position(rng);
+        let free_space = free_space(candidate);
+        if free_space >= 0.0 {
+            return candidate;
+        }
+        if best.is_none_or(|(best_free_space, _)| free_space > best_free_space) {
+            best = Some((free_space, candidate));
+        }
+    }
+    best.map(|(_, position)| position).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::level::Obstacle;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn view<'a>(level: &'a Level, apples: &'a [Vec2], bodies: &'a [Vec2]) -> View<'a> {
+        View {
+            head: Vec2::ZERO,
+            heading: PI,
+            apples,
+            level,
+            bodies,
+            prey: None,
+            lookahead: 60.0,
+            clearance: 20.0,
+        }
+    }
+
+    #[test]
+    fn greedy_rivals_head_for_the_nearest_apple() {
+        let level = Level::default();
+        let apples = [Vec2::new(0.0, -200.0), Vec2::new(100.0, 0.0)];
+        let heading = Greedy.steer(&view(&level, &apples, &[]));
+        assert!(heading.abs() < EPSILON);
+    }
+
+    #[test]
+    fn rivals_turn_away_from_walls_and_bodies() {
+        let level = Level {
+            obstacles: vec![Obstacle::Rectangle {
+                center: Vec2::new(50.0, 0.0),
+                size: Vec2::new(20.0, 40.0),
+            }],
+            ..Level::default()
+        };
+        let apples = [Vec2::new(200.0, 0.0)];
+        let heading = Greedy.steer(&view(&level, &apples, &[]));
+        assert!(heading.abs() >= HEADING_STEP - EPSILON);
+
+        let level = Level::default();
+        let bodies = [Vec2::new(30.0, 0.0)];
+        let heading = Greedy.steer(&view(&level, &apples, &bodies));
+        assert!(heading.abs() >= HEADING_STEP - EPSILON);
+    }
+
+    #[test]
+    fn cautious_rivals_look_further_ahead() {
+        let level = Level::default();
+        let apples = [Vec2::new(300.0, 0.0)];
+        let bodies = [Vec2::new(100.0, 0.0)];
+        let greedy = Greedy.steer(&view(&level, &apples, &bodies));
+        let cautious = Cautious.steer(&view(&level, &apples, &bodies));
+        assert!(greedy.abs() < EPSILON);
+        assert!(cautious.abs() >= HEADING_STEP - EPSILON);
+    }
+
+    #[test]
+    fn aggressive_rivals_cut_the_player_off() {
+        let level = Level::default();
+        let apples = [Vec2::new(-100.0, 0.0)];
+        let mut view = view(&level, &apples, &[]);
+        view.prey = Some((Vec2::new(100.0, -80.0), Vec2::Y));
+        let heading = Aggressive.steer(&view);
+        assert!(heading.abs() < EPSILON);
+
+        view.prey = Some((Vec2::new(1000.0, -80.0), Vec2::Y));
+        let heading = Aggressive.steer(&view);
+        assert!((heading.abs() - PI).abs() < EPSILON);
+    }
+
+    #[test]
+    fn other_brains_plug_in_next_to_the_named_ones() {
+        struct Lazy;
+        impl Steer for Lazy {
+            fn steer(&self, view: &View) -> f32 {
+                view.clear_heading_towards(None, 1.0)
+            }
+        }
+
+        let level = Level::default();
+        let apples = [Vec2::new(0.0, 200.0)];
+        let view = view(&level, &apples, &[]);
+        let brains: Vec<Box<dyn Steer>> = vec![Brain::Greedy.build(), Box::new(Lazy)];
+        let headings: Vec<f32> = brains.iter().map(|brain| brain.steer(&view)).collect();
+        assert!((headings[0] - Vec2::Y.to_angle()).abs() < EPSILON);
+        assert!((headings[1] - view.heading).abs() < EPSILON);
+    }
+
+    #[test]
+    fn the_shorter_snake_loses_a_head_on_collision() {
+        assert_eq!(head_on_casualties(10, 12), (true, false));
+        assert_eq!(head_on_casualties(12, 10), (false, true));
+        assert_eq!(head_on_casualties(10, 10), (true, true));
+    }
+
+    #[test]
+    fn rivals_spawn_clear_of_walls_and_snakes() {
+        let level = Level::default();
+        let occupied: Vec<Vec2> = (0..20).map(|x| Vec2::new(x as f32 * 10.0, 0.0)).collect();
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..20 {
+            let tail = spawn_point(&level, &occupied, 200.0, 20.0, 50.0, &mut rng);
+            let head = tail - Vec2::X * 200.0;
+            for point in [tail, head, (tail + head) / 2.0] {
+                assert!(level.distance_to_nearest_wall(point) >= 20.0);
+                assert!(occupied.iter().all(|other| other.distance(point) >= 50.0));
+            }
+        }
+    }
+
+    #[test]
+    fn rival_counts_cycle() {
+        assert_eq!(Rivals(0).next(), Rivals(1));
+        assert_eq!(Rivals(MAX_RIVALS).next(), Rivals(0));
+    }
+}
@@ -0,0 +1,162 @@
+//! Rival snakes, steered by the computer, that compete with the players for apples.
+
+use bevy::prelude::*;
+use snake::{
+    config::SnakeConfig,
+    fabrik::{Limb, SnakeStyle, SnakeVelocity},
+    level::Level,
+    mode::{travel_direction, turn_towards},
+    rival::{self, Rivals, Steer, View},
+};
+
+use crate::{
+    GameState, SimulationRng, SpeedFactor, apples::Apple, menus::cycle_button, players::Player,
+};
+
+pub struct RivalSnakesPlugin;
+
+impl Plugin for RivalSnakesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rivals>().add_systems(
+            Update,
+            cycle_button::<Rivals>.run_if(in_state(GameState::MainMenu)),
+        );
+    }
+}
+
+/// A computer-controlled snake.
+#[derive(Component)]
+pub struct Rival {
+    brain: Box<dyn Steer>,
+}
+
+/// What every rival looks like.
+#[derive(Resource, Deref)]
+pub struct RivalStyle(pub SnakeStyle);
+
+pub const RIVAL_COLOR: Color = Color::srgb(0.8, 0.2, 0.3);
+
+/// Replaces the rivals with as many fresh ones as were picked in the main menu, laid out
+/// like the players' snakes somewhere clear of them.
+pub fn reset_rivals(
+    mut commands: Commands,
+    rivals: Query<Entity, With<Rival>>,
+    rival_count: Res<Rivals>,
+    players: Query<&Limb, With<Player>>,
+    style: Res<RivalStyle>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+    mut rng: ResMut<SimulationRng>,
+) {
+    for entity in rivals {
+        commands.entity(entity).despawn();
+    }
+    if config.rival_brains.is_empty() {
+        return;
+    }
+    let lengths = config.segment_lengths();
+    let mut occupied: Vec<Vec2> = players
+        .iter()
+        .flat_map(|limb| limb.segments())
+        .map(|segment| segment.position())
+        .collect();
+    for index in 0..rival_count.0 {
+        let tail = rival::spawn_point(
+            &level,
+            &occupied,
+            config.starting_reach(),
+            config.head_thickness,
+            config.apple_min_head_distance,
+            &mut **rng,
+        );
+        let mut limb = Limb::new(tail, tail, &lengths);
+        limb.set_target(limb.get_last_segment_position());
+        limb.set_max_bend(config.max_bend());
+        occupied.extend(limb.segments().iter().map(|segment| segment.position()));
+        let rival = commands.spawn_empty().id();
+        let head = Sprite::from_color(
+            RIVAL_COLOR,
+            Vec2::new(config.head_length, config.head_thickness),
+        );
+        limb.display(rival, &mut commands, &style, head, &config);
+        commands.entity(rival).insert((
+            Rival {
+                brain: config.rival_brains[index % config.rival_brains.len()].build(),
+            },
+            limb,
+            SnakeVelocity::default(),
+            SpeedFactor::default(),
+            (**style).clone(),
+        ));
+    }
+}
+
+/// Lets each rival's brain pick a heading, then moves the rival like a snake in steering
+/// mode. Rivals keep clear of the outer walls even when they wrap around.
+pub fn move_rivals(
+    mut rivals: Query<(&Rival, &mut Limb, &mut SnakeVelocity, &SpeedFactor), Without<Player>>,
+    players: Query<(&Limb, &SnakeVelocity), With<Player>>,
+    apples: Query<&Transform, With<Apple>>,
+    time: Res<Time>,
+    config: Res<SnakeConfig>,
+    level: Res<Level>,
+) {
+    let apples: Vec<Vec2> = apples
+        .iter()
+        .map(|transform| transform.translation.truncate())
+        .collect();
+    let player_bodies: Vec<Vec2> = players
+        .iter()
+        .flat_map(|(limb, _)| limb.segments())
+        .map(|segment| segment.position())
+        .collect();
+    let rival_bodies: Vec<Vec<Vec2>> = rivals
+        .iter()
+        .map(|(_, limb, ..)| {
+            limb.segments()
+                .iter()
+                .map(|segment| segment.position())
+                .collect()
+        })
+        .collect();
+    for (index, (rival, mut limb, mut velocity, speed_factor)) in rivals.iter_mut().enumerate() {
+        let own_body = &rival_bodies[index];
+        // Leave out the rival's own head, which every point it looks at is close to.
+        let bodies: Vec<Vec2> = rival_bodies
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .flat_map(|(_, body)| body)
+            .chain(&player_bodies)
+            .chain(&own_body[..own_body.len().saturating_sub(1)])
+            .copied()
+            .collect();
+        let head = limb.get_last_segment_position();
+        // Hunters go after whichever player is closest.
+        let prey = players
+            .iter()
+            .map(|(limb, velocity)| {
+                (
+                    limb.get_last_segment_position(),
+                    travel_direction(velocity.0, limb.heading()),
+                )
+            })
+            .min_by(|(a, _), (b, _)| a.distance(head).total_cmp(&b.distance(head)));
+        let view = View {
+            head,
+            heading: limb.heading().to_angle(),
+            apples: &apples,
+            level: &level,
+            bodies: &bodies,
+            prey,
+            lookahead: config.rival_lookahead,
+            clearance: config.head_thickness / 2.0,
+        };
+        let wanted = rival.brain.steer(&view);
+        let heading = turn_towards(view.heading, wanted, config.turn_rate() * time.delta_secs());
+        let speed = config.speed * config.rival_speed_factor * **speed_factor;
+        velocity.0 = Vec2::from_angle(heading) * speed * time.delta_secs();
+        limb.set_target(head + velocity.0);
+        limb.solve();
+    }
+}
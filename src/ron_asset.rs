@@ -0,0 +1,60 @@
+//! Generic loader for plain RON assets such as `SnakeConfig`.
+
+use std::marker::PhantomData;
+
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
+use bevy::prelude::*;
+use serde::de::DeserializeOwned;
+
+pub struct RonAssetLoader<A> {
+    extensions: &'static [&'static str],
+    marker: PhantomData<fn() -> A>,
+}
+
+impl<A> RonAssetLoader<A> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        Self {
+            extensions,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<A: Asset + DeserializeOwned> AssetLoader for RonAssetLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = BevyError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<A> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
+
+/// Registers `A` as an asset loaded from files ending in one of `extensions`.
+pub trait RonAssetAppExt {
+    fn init_ron_asset<A: Asset + DeserializeOwned>(
+        &mut self,
+        extensions: &'static [&'static str],
+    ) -> &mut Self;
+}
+
+impl RonAssetAppExt for App {
+    fn init_ron_asset<A: Asset + DeserializeOwned>(
+        &mut self,
+        extensions: &'static [&'static str],
+    ) -> &mut Self {
+        self.init_asset::<A>()
+            .register_asset_loader(RonAssetLoader::<A>::new(extensions))
+    }
+}
@@ -0,0 +1,343 @@
+//! The stage a game is played on: the chosen level with its walls, obstacles and portals.
+
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::*;
+use snake::{
+    fabrik::GameLayer,
+    level::{Arena, Level, Obstacle},
+    mode::Walls,
+};
+
+use crate::{GameState, ResetGame};
+
+pub struct StagePlugin;
+
+impl Plugin for StagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnExit(GameState::Loading),
+            (
+                (drop_invalid_levels, select_first_level).chain(),
+                insert_stage_assets,
+            ),
+        )
+        .add_systems(ResetGame, spawn_level)
+        .add_systems(OnEnter(GameState::Start), spawn_level);
+    }
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct LevelAssets {
+    #[asset(
+        paths(
+            "levels/open.level.ron",
+            "levels/pillars.level.ron",
+            "levels/cross.level.ron",
+            "levels/diamonds.level.ron",
+        ),
+        collection(typed)
+    )]
+    pub levels: Vec<Handle<Level>>,
+}
+
+#[derive(Resource, Deref, DerefMut)]
+pub struct SelectedLevel(usize);
+
+#[derive(Component)]
+pub struct Boundary;
+
+/// An outer wall of a wrap-around arena, which the snake passes through.
+#[derive(Component)]
+struct Portal;
+
+/// The stage's materials, made once, and the meshes of the level it was last built for,
+/// so that starting over doesn't add new assets every time.
+#[derive(Resource)]
+struct StageAssets {
+    material: Handle<ColorMaterial>,
+    portal_material: Handle<ColorMaterial>,
+    /// The level the meshes below are for.
+    level: Option<Level>,
+    wall_meshes: Vec<Handle<Mesh>>,
+    /// One per obstacle.
+    obstacle_meshes: Vec<Handle<Mesh>>,
+}
+
+impl StageAssets {
+    /// Makes the meshes for `level` unless they were already made for it.
+    fn build_meshes(&mut self, level: &Level, meshes: &mut Assets<Mesh>) {
+        if self.level.as_ref() == Some(level) {
+            return;
+        }
+        self.wall_meshes = outer_walls(&level.arena)
+            .into_iter()
+            .map(|(_, size)| meshes.add(Rectangle::from_size(size)))
+            .collect();
+        self.obstacle_meshes = level
+            .obstacles
+            .iter()
+            .map(|obstacle| match obstacle {
+                Obstacle::Rectangle { size, .. } => meshes.add(Rectangle::from_size(*size)),
+                Obstacle::Polygon { points } => meshes.add(
+                    ConvexPolygon::new(points.clone())
+                        .expect("validated levels only have convex obstacles"),
+                ),
+            })
+            .collect();
+        self.level = Some(level.clone());
+    }
+}
+
+/// Drops the levels that failed to load or validate. When that leaves none, the built-in
+/// open field stands in so there always is a level to play.
+fn drop_invalid_levels(
+    mut commands: Commands,
+    level_assets: Option<ResMut<LevelAssets>>,
+    mut levels: ResMut<Assets<Level>>,
+) {
+    let Some(mut level_assets) = level_assets else {
+        warn!("The levels could not be loaded; playing on the built-in open field");
+        commands.insert_resource(LevelAssets {
+            levels: vec![levels.add(Level::default())],
+        });
+        return;
+    };
+    level_assets.levels.retain(|handle| {
+        let Some(level) = levels.get(handle) else {
+            return false;
+        };
+        match level.validate() {
+            Ok(()) => true,
+            Err(error) => {
+                warn!("Skipping level {}: {error}", level.name);
+                false
+            }
+        }
+    });
+    if level_assets.levels.is_empty() {
+        warn!("No level could be loaded; playing on the built-in open field");
+        level_assets.levels.push(levels.add(Level::default()));
+    }
+}
+
+pub fn select_first_level(
+    mut commands: Commands,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<Level>>,
+) {
+    if let Some(level) = level_assets
+        .levels
+        .first()
+        .and_then(|handle| levels.get(handle))
+    {
+        commands.insert_resource(level.clone());
+    }
+    commands.insert_resource(SelectedLevel(0));
+}
+
+fn insert_stage_assets(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    let color = Color::Srgba(Srgba::rgb(1.0, 0.647, 0.0));
+    commands.insert_resource(StageAssets {
+        material: materials.add(color),
+        portal_material: materials.add(color.with_alpha(0.25)),
+        level: None,
+        wall_meshes: Vec::new(),
+        obstacle_meshes: Vec::new(),
+    });
+}
+
+/// Centre and size of each of the arena's four outer walls.
+fn outer_walls(arena: &Arena) -> [(Vec2, Vec2); 4] {
+    [
+        (
+            Vec2::new(-arena.width / 2.0, 0.0),
+            Vec2::new(arena.wall_thickness, arena.height),
+        ),
+        (
+            Vec2::new(arena.width / 2.0, 0.0),
+            Vec2::new(arena.wall_thickness, arena.height),
+        ),
+        (
+            Vec2::new(0.0, arena.height / 2.0),
+            Vec2::new(arena.width, arena.wall_thickness),
+        ),
+        (
+            Vec2::new(0.0, -arena.height / 2.0),
+            Vec2::new(arena.width, arena.wall_thickness),
+        ),
+    ]
+}
+
+pub fn boundary(
+    mesh: Handle<Mesh>,
+    material: Handle<ColorMaterial>,
+    transform: Transform,
+    collider: Collider,
+) -> impl Bundle {
+    (
+        Mesh2d(mesh),
+        MeshMaterial2d(material),
+        transform,
+        RigidBody::Static,
+        collider,
+        CollisionLayers::new(
+            GameLayer::Boundary,
+            [
+                GameLayer::Default,
+                GameLayer::SnakeHead,
+                GameLayer::RivalHead,
+            ],
+        ),
+        Boundary,
+    )
+}
+
+fn spawn_level(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut stage: ResMut<StageAssets>,
+    level: Res<Level>,
+    walls_mode: Res<Walls>,
+    boundaries: Query<Entity, Or<(With<Boundary>, With<Portal>)>>,
+) {
+    for entity in boundaries {
+        commands.entity(entity).despawn();
+    }
+
+    stage.build_meshes(&level, &mut meshes);
+    let walls = outer_walls(&level.arena)
+        .into_iter()
+        .zip(&stage.wall_meshes);
+    for ((center, size), mesh) in walls {
+        let transform = Transform::from_translation(center.extend(0.0));
+        match *walls_mode {
+            Walls::Solid => {
+                commands.spawn(boundary(
+                    mesh.clone(),
+                    stage.material.clone(),
+                    transform,
+                    Collider::rectangle(size.x, size.y),
+                ));
+            }
+            Walls::Wrap => {
+                commands.spawn((
+                    Mesh2d(mesh.clone()),
+                    MeshMaterial2d(stage.portal_material.clone()),
+                    transform,
+                    Portal,
+                ));
+            }
+        }
+    }
+
+    for (obstacle, mesh) in level.obstacles.iter().zip(&stage.obstacle_meshes) {
+        match obstacle {
+            Obstacle::Rectangle { center, size } => {
+                commands.spawn(boundary(
+                    mesh.clone(),
+                    stage.material.clone(),
+                    Transform::from_translation(center.extend(0.0)),
+                    Collider::rectangle(size.x, size.y),
+                ));
+            }
+            Obstacle::Polygon { points } => {
+                let collider = Collider::convex_hull(points.clone())
+                    .expect("validated levels only have convex obstacles");
+                commands.spawn(boundary(
+                    mesh.clone(),
+                    stage.material.clone(),
+                    Transform::default(),
+                    collider,
+                ));
+            }
+        }
+    }
+}
+
+/// The arena, if its outer walls wrap around.
+pub fn wrapping_arena(walls: Walls, level: &Level) -> Option<&Arena> {
+    (walls == Walls::Wrap).then_some(&level.arena)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{ecs::system::RunSystemOnce, state::app::StatesPlugin};
+    use snake::{config::SnakeConfig, ron_asset::RonAssetAppExt};
+
+    use super::*;
+
+    #[test]
+    fn a_broken_level_file_leaves_the_open_field_to_play_on() {
+        let root = std::env::temp_dir().join(format!("snake-assets-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("levels")).unwrap();
+        std::fs::write(
+            root.join("levels/open.level.ron"),
+            "(name: \"Open\", arena: (",
+        )
+        .unwrap();
+
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin {
+                file_path: root.display().to_string(),
+                ..default()
+            },
+            StatesPlugin,
+        ))
+        .init_ron_asset::<SnakeConfig>(&["snake.ron"])
+        .init_ron_asset::<Level>(&["level.ron"])
+        .init_state::<GameState>()
+        .add_loading_state(crate::loading_state())
+        .add_systems(
+            OnExit(GameState::Loading),
+            (drop_invalid_levels, select_first_level).chain(),
+        );
+        for _ in 0..500 {
+            if *app.world().resource::<State<GameState>>() != GameState::Loading {
+                break;
+            }
+            app.update();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(
+            *app.world().resource::<State<GameState>>(),
+            GameState::MainMenu
+        );
+        assert_eq!(app.world().resource::<LevelAssets>().levels.len(), 1);
+        assert_eq!(*app.world().resource::<Level>(), Level::default());
+    }
+
+    #[test]
+    fn starting_over_reuses_the_stage_assets() {
+        let mut world = World::new();
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<Assets<ColorMaterial>>();
+        world.insert_resource(Level {
+            obstacles: vec![Obstacle::Rectangle {
+                center: Vec2::new(-300.0, 0.0),
+                size: Vec2::splat(40.0),
+            }],
+            ..Level::default()
+        });
+        world.insert_resource(Walls::Solid);
+        world.run_system_once(insert_stage_assets).unwrap();
+
+        world.run_system_once(spawn_level).unwrap();
+        let counts = |world: &World| {
+            (
+                world.resource::<Assets<Mesh>>().len(),
+                world.resource::<Assets<ColorMaterial>>().len(),
+            )
+        };
+        let first = counts(&world);
+        world.run_system_once(spawn_level).unwrap();
+        assert_eq!(counts(&world), first);
+        assert_eq!(first, (5, 2));
+    }
+}
@@ -0,0 +1,64 @@
+//! Small key/value persistence for settings and scores: one RON file per key in the
+//! platform data directory, or `localStorage` on the web build.
+
+use std::io;
+
+#[cfg(not(target_arch = "wasm32"))]
+const APP_DIRECTORY: &str = "snake";
+#[cfg(target_arch = "wasm32")]
+const KEY_PREFIX: &str = "snake.";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn path(key: &str) -> Option<std::path::PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_DIRECTORY).join(format!("{key}.ron")))
+}
+
+/// Where the value under `key` is kept, to tell the player.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn location(key: &str) -> String {
+    path(key).map_or_else(|| key.to_owned(), |path| path.display().to_string())
+}
+
+/// The value stored under `key`, if there is one.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(key: &str) -> Option<String> {
+    std::fs::read_to_string(path(key)?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(key: &str, value: &str) -> io::Result<()> {
+    let path =
+        path(key).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, value)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// Where the value under `key` is kept, to tell the player.
+#[cfg(target_arch = "wasm32")]
+pub fn location(key: &str) -> String {
+    format!("localStorage[{KEY_PREFIX}{key}]")
+}
+
+/// The value stored under `key`, if there is one.
+#[cfg(target_arch = "wasm32")]
+pub fn read(key: &str) -> Option<String> {
+    local_storage()?
+        .get_item(&format!("{KEY_PREFIX}{key}"))
+        .ok()
+        .flatten()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write(key: &str, value: &str) -> io::Result<()> {
+    local_storage()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "localStorage is unavailable"))?
+        .set_item(&format!("{KEY_PREFIX}{key}"), value)
+        .map_err(|_| io::Error::other("localStorage rejected the write"))
+}
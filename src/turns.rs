@@ -0,0 +1,149 @@
+//! Classic-mode turn queue: taps made between simulation ticks are kept rather than
+//! lost, and turns straight back into the snake's neck are refused.
+
+use std::collections::VecDeque;
+
+use glam::Vec2;
+
+use crate::replay::Direction;
+
+/// Number of turns that can wait for the next ticks.
+pub const MAX_PENDING_TURNS: usize = 2;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "bevy", derive(bevy::ecs::component::Component))]
+pub struct TurnBuffer {
+    /// Direction the snake heads in once every pending turn has been taken.
+    heading: Option<Direction>,
+    pending: VecDeque<Direction>,
+    /// Where the head was when the last turn was taken.
+    turned_at: Option<Vec2>,
+}
+
+impl TurnBuffer {
+    /// Queues a turn. Reversals of the turn before it, the same turn twice in a row and
+    /// turns past [`MAX_PENDING_TURNS`] are dropped.
+    pub fn push(&mut self, direction: Direction) -> bool {
+        let previous = self.pending.back().copied();
+        if self.pending.len() == MAX_PENDING_TURNS
+            || previous == Some(direction)
+            || is_reversal(previous.or(self.heading), direction)
+        {
+            return false;
+        }
+        self.pending.push_back(direction);
+        true
+    }
+
+    /// Takes the turn for the next tick, given the direction the snake's head points in and
+    /// where it is. Turns that became reversals, say after the game was reset, are skipped.
+    ///
+    /// A turn waits until the head has moved `min_spacing` on from the last one, so two quick
+    /// turns can't fold the head back into the neck.
+    pub fn next(
+        &mut self,
+        heading: Option<Direction>,
+        head: Vec2,
+        min_spacing: f32,
+    ) -> Option<Direction> {
+        self.heading = heading;
+        if self
+            .turned_at
+            .is_some_and(|turned_at| turned_at.distance(head) < min_spacing)
+        {
+            return None;
+        }
+        while let Some(direction) = self.pending.pop_front() {
+            if !is_reversal(self.heading, direction) {
+                self.heading = Some(direction);
+                self.turned_at = Some(head);
+                return Some(direction);
+            }
+        }
+        None
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+fn is_reversal(heading: Option<Direction>, direction: Direction) -> bool {
+    heading.is_some_and(|heading| direction == heading.opposite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_reversals_and_repeats() {
+        let mut turns = TurnBuffer::default();
+        assert_eq!(turns.next(Some(Direction::Left), Vec2::ZERO, 0.0), None);
+        assert!(!turns.push(Direction::Right));
+        assert!(turns.push(Direction::Up));
+        assert!(!turns.push(Direction::Up));
+        assert!(!turns.push(Direction::Down));
+    }
+
+    #[test]
+    fn going_straight_on_sets_a_still_snake_off() {
+        let mut turns = TurnBuffer::default();
+        turns.next(Some(Direction::Left), Vec2::ZERO, 0.0);
+        assert!(turns.push(Direction::Left));
+        assert_eq!(
+            turns.next(Some(Direction::Left), Vec2::ZERO, 0.0),
+            Some(Direction::Left)
+        );
+    }
+
+    #[test]
+    fn keeps_two_quick_taps_for_the_following_ticks() {
+        let mut turns = TurnBuffer::default();
+        turns.next(Some(Direction::Right), Vec2::ZERO, 0.0);
+        assert!(turns.push(Direction::Up));
+        assert!(turns.push(Direction::Left));
+        assert!(!turns.push(Direction::Down));
+        assert_eq!(
+            turns.next(Some(Direction::Right), Vec2::ZERO, 0.0),
+            Some(Direction::Up)
+        );
+        assert_eq!(
+            turns.next(Some(Direction::Up), Vec2::ZERO, 0.0),
+            Some(Direction::Left)
+        );
+        assert_eq!(turns.next(Some(Direction::Left), Vec2::ZERO, 0.0), None);
+    }
+
+    #[test]
+    fn continuous_turns_wait_until_the_head_has_moved_on() {
+        const HEAD_THICKNESS: f32 = 20.0;
+        const STEP: f32 = 3.0;
+        let mut turns = TurnBuffer::default();
+        let mut head = Vec2::ZERO;
+        turns.next(Some(Direction::Right), head, HEAD_THICKNESS);
+        assert!(turns.push(Direction::Up));
+        assert!(turns.push(Direction::Left));
+        assert_eq!(
+            turns.next(Some(Direction::Right), head, HEAD_THICKNESS),
+            Some(Direction::Up)
+        );
+        let mut ticks = 0;
+        let second = loop {
+            head += Direction::Up.as_vec2() * STEP;
+            ticks += 1;
+            if let Some(direction) = turns.next(Some(Direction::Up), head, HEAD_THICKNESS) {
+                break direction;
+            }
+        };
+        assert_eq!(second, Direction::Left);
+        assert_eq!(ticks, (HEAD_THICKNESS / STEP).ceil() as usize);
+    }
+
+    #[test]
+    fn skips_turns_that_became_reversals() {
+        let mut turns = TurnBuffer::default();
+        assert!(turns.push(Direction::Right));
+        assert_eq!(turns.next(Some(Direction::Left), Vec2::ZERO, 0.0), None);
+    }
+}